@@ -2,15 +2,50 @@ use anyhow::*;
 use fs_extra::copy_items;
 use fs_extra::dir::CopyOptions;
 use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 fn main() -> Result<()> {
     // This tells Cargo to rerun this script if something in /res/ changes.
     println!("cargo:rerun-if-changed=res/*");
 
-    let out_dir = env::var("OUT_DIR")?;
+    // Next to the executable, where the asset source looks by default.
+    let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+    let target_dir = out_dir
+        .ancestors()
+        .nth(3)
+        .context("OUT_DIR isn't inside a target directory")?;
     let copy_options = CopyOptions::new().overwrite(true);
     let paths_to_copy = vec!["res/"];
-    copy_items(&paths_to_copy, out_dir, &copy_options)?;
+    copy_items(&paths_to_copy, target_dir, &copy_options)?;
+
+    write_embedded_assets(&out_dir.join("embedded_assets.rs"))?;
+
+    Ok(())
+}
+
+// A table of every file in /res/ as `include_bytes!`, for the `embedded-assets` feature.
+fn write_embedded_assets(file: &Path) -> Result<()> {
+    let manifest_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR")?);
+
+    let mut table = String::from("&[\n");
+    for path in glob::glob("res/**/*")? {
+        let path = path?;
+        if !path.is_file() {
+            continue;
+        }
+
+        let asset = path.to_string_lossy().replace('\\', "/");
+        writeln!(
+            table,
+            "    (\"/{asset}\", include_bytes!({:?})),",
+            manifest_dir.join(&path)
+        )?;
+    }
+    table += "]\n";
+
+    fs::write(file, table)?;
 
     Ok(())
 }
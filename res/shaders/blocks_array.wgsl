@@ -0,0 +1,27 @@
+#include "include/globals.wgsl"
+#include "include/mesh.wgsl"
+
+// Like default.wgsl, but with one texture array layer per block face instead of an atlas, see
+// `BlockUvs::from_layers`.
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
+};
+
+@vertex
+fn vs_main(vert: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    let model = instance_model(instance);
+    out.clip_position = global_context.camera.view_proj * model * vec4<f32>(vert.position, 1.0);
+    out.tex_coords = vert.tex_coords;
+    out.layer = vert.layer;
+    return out;
+}
+
+@group(1) @binding(0) var albedo_textures: texture_2d_array<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(albedo_textures, point_sampler, in.tex_coords, in.layer);
+}
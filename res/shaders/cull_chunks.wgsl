@@ -0,0 +1,55 @@
+// Writes one indirect draw per candidate mesh, with no instances if its bounds are outside the
+// frustum. The structs mirror the #[repr(C)] ones in gpu_cull.rs, which a test keeps in sync.
+struct CullParams {
+    planes: array<vec4<f32>, 6>, // normals point inwards
+    count: u32,
+}
+
+struct CullCandidate {
+    min: vec3<f32>,
+    index_count: u32,
+    max: vec3<f32>,
+    first_index: u32,
+    base_vertex: i32,
+}
+
+struct DrawArgs {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    base_vertex: i32,
+    first_instance: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: CullParams;
+@group(0) @binding(1) var<storage, read> candidates: array<CullCandidate>;
+@group(0) @binding(2) var<storage, read_write> draws: array<DrawArgs>;
+
+fn is_visible(min: vec3<f32>, max: vec3<f32>) -> bool {
+    for (var i = 0u; i < 6u; i++) {
+        let plane = params.planes[i];
+        // The corner furthest along the plane's normal.
+        let corner = select(min, max, plane.xyz >= vec3<f32>(0.0));
+        if dot(plane.xyz, corner) + plane.w < 0.0 {
+            return false;
+        }
+    }
+    return true;
+}
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.count {
+        return;
+    }
+
+    let candidate = candidates[index];
+    draws[index] = DrawArgs(
+        candidate.index_count,
+        select(0u, 1u, is_visible(candidate.min, candidate.max)),
+        candidate.first_index,
+        candidate.base_vertex,
+        0u,
+    );
+}
@@ -0,0 +1,28 @@
+// Drawn in place of shaders that failed to compile. Needs no material resources, so any object
+// can be switched over to it.
+#include "include/globals.wgsl"
+#include "include/mesh.wgsl"
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+@vertex
+fn vs_main(vert: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    let model = instance_model(instance);
+    out.clip_position = global_context.camera.view_proj * model * vec4<f32>(vert.position, 1.0);
+    out.tex_coords = vert.tex_coords;
+    return out;
+}
+
+// Magenta and black checkers, 4 per face.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let cell = vec2<u32>(floor(in.tex_coords * 4.0));
+    if ((cell.x + cell.y) % 2u == 0u) {
+        return vec4<f32>(1.0, 0.0, 1.0, 1.0);
+    }
+    return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+}
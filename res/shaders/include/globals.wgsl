@@ -0,0 +1,13 @@
+// Bindings every shader gets at @group(0), see global_bindings.rs. The structs mirror the
+// #[repr(C)] ones there, which a test keeps in sync.
+struct CameraBufferContext {
+    view_proj: mat4x4<f32>,
+}
+
+struct GlobalBufferContext {
+    camera: CameraBufferContext,
+}
+
+@group(0) @binding(0) var<uniform> global_context: GlobalBufferContext;
+@group(0) @binding(1) var trilinear_sampler: sampler;
+@group(0) @binding(2) var point_sampler: sampler;
@@ -0,0 +1,26 @@
+// Applied to the finished scene on its way to the surface: darkens the corners a little. Drawn as
+// one triangle covering the screen, without vertex buffers.
+@group(0) @binding(0) var scene: texture_2d<f32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.uv = uv;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The scene is the surface's size, so pixels map one to one.
+    let colour = textureLoad(scene, vec2<i32>(in.clip_position.xy), 0);
+    let offset = in.uv - vec2<f32>(0.5);
+    let vignette = 1.0 - 0.5 * dot(offset, offset);
+    return vec4<f32>(colour.rgb * vignette, colour.a);
+}
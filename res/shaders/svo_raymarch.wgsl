@@ -0,0 +1,104 @@
+#include "include/globals.wgsl"
+#include "include/mesh.wgsl"
+
+// Raymarches a sparse voxel octree (see svo.rs) from the back faces of its bounding box, so it
+// still draws with the camera inside. Nodes are single words: leaves have the top bit set and a
+// block id in the low 16 bits, other nodes are the index of their 8 children.
+const LEAF: u32 = 0x80000000u;
+
+// Mirrors `SvoParams` in svo_volume.rs, which a test keeps in sync.
+struct SvoParams {
+    origin: vec3<f32>,
+    size: u32,
+    eye: vec3<f32>,
+    palette: array<vec4<f32>, 16>, // colour per block id
+}
+
+@group(1) @binding(0) var<uniform> params: SvoParams;
+@group(1) @binding(1) var<storage, read> nodes: array<u32>;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) world_position: vec3<f32>,
+};
+
+@vertex
+fn vs_main(vert: VertexInput, instance: InstanceInput) -> VertexOutput {
+    var out: VertexOutput;
+    let world_position = instance_model(instance) * vec4<f32>(vert.position, 1.0);
+    out.clip_position = global_context.camera.view_proj * world_position;
+    out.world_position = world_position.xyz;
+    return out;
+}
+
+struct Cell {
+    node: u32,
+    min: vec3<f32>,
+    size: f32,
+}
+
+// The leaf containing `p`, in voxels from the octree's origin.
+fn find_leaf(p: vec3<f32>) -> Cell {
+    var node = nodes[0];
+    var min = vec3<f32>(0.0);
+    var size = f32(params.size);
+    while (node & LEAF) == 0u && size > 1.0 {
+        size *= 0.5;
+        let upper = vec3<u32>(p >= min + size);
+        min += vec3<f32>(upper) * size;
+        node = nodes[node + upper.x + upper.y * 2u + upper.z * 4u];
+    }
+    return Cell(node, min, size);
+}
+
+struct FragmentOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+    let size = f32(params.size);
+    var dir = normalize(in.world_position - params.eye);
+    dir = select(dir, vec3<f32>(1e-6), abs(dir) < vec3<f32>(1e-6));
+    let inv_dir = 1.0 / dir;
+
+    // Start where the ray enters the box, or at the eye if it's inside.
+    let t0 = (params.origin - params.eye) * inv_dir;
+    let t1 = (params.origin + size - params.eye) * inv_dir;
+    let t_near = min(t0, t1);
+    let t_enter = max(max(max(t_near.x, t_near.y), t_near.z), 0.0);
+    var normal = select(vec3<f32>(0.0), -sign(dir), t_near == vec3<f32>(t_enter));
+    var p = params.eye + dir * t_enter - params.origin;
+    p = clamp(p, vec3<f32>(0.0), vec3<f32>(size - 1e-3));
+
+    // A ray crosses at most `size` leaves along each axis, so this only ends runaway loops.
+    let max_steps = 3u * params.size + 1u;
+    for (var i = 0u; i < max_steps; i++) {
+        if any(p < vec3<f32>(0.0)) || any(p >= vec3<f32>(size)) {
+            break;
+        }
+
+        let cell = find_leaf(p);
+        let block = cell.node & 0xffffu;
+        if (cell.node & LEAF) != 0u && block != 0u {
+            let light = 0.6 + 0.4 * max(dot(normal, normalize(vec3<f32>(0.4, 1.0, 0.3))), 0.0);
+            let hit = params.origin + p;
+            let clip = global_context.camera.view_proj * vec4<f32>(hit, 1.0);
+
+            var out: FragmentOutput;
+            out.color = vec4<f32>(params.palette[min(block, 15u)].rgb * light, 1.0);
+            out.depth = clip.z / clip.w;
+            return out;
+        }
+
+        // On to where the ray leaves this cell.
+        let exit = cell.min + select(vec3<f32>(0.0), vec3<f32>(cell.size), dir > vec3<f32>(0.0));
+        let t = (exit - p) * inv_dir;
+        let t_exit = min(min(t.x, t.y), t.z);
+        normal = select(vec3<f32>(0.0), -sign(dir), t == vec3<f32>(t_exit));
+        p += dir * (t_exit + 1e-3);
+    }
+
+    discard;
+}
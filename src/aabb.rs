@@ -0,0 +1,102 @@
+use glam::{Mat4, Vec3};
+
+// An axis-aligned bounding box.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    // The smallest box around `points`, or an empty box at the origin if there are none.
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Self {
+        let mut points = points.into_iter();
+        let Some(first) = points.next() else {
+            return Self::new(Vec3::ZERO, Vec3::ZERO);
+        };
+
+        points.fold(Self::new(first, first), |aabb, point| {
+            Self::new(aabb.min.min(point), aabb.max.max(point))
+        })
+    }
+
+    // A box of `size` standing on `bottom_center`, the way entities are positioned.
+    pub fn from_bottom_center(bottom_center: Vec3, size: Vec3) -> Self {
+        let half = Vec3::new(size.x * 0.5, 0.0, size.z * 0.5);
+        Self {
+            min: bottom_center - half,
+            max: bottom_center + half + Vec3::Y * size.y,
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn translate(&self, offset: Vec3) -> Self {
+        Self {
+            min: self.min + offset,
+            max: self.max + offset,
+        }
+    }
+
+    // The box around `self` after transforming it, which is larger than `self` when rotated.
+    pub fn transform(&self, matrix: &Mat4) -> Self {
+        let center = matrix.transform_point3(self.center());
+        let half = self.size() * 0.5;
+        let extent = matrix.x_axis.truncate().abs() * half.x
+            + matrix.y_axis.truncate().abs() * half.y
+            + matrix.z_axis.truncate().abs() * half.z;
+
+        Self {
+            min: center - extent,
+            max: center + extent,
+        }
+    }
+
+    // Grows the box in the direction of `motion`, covering everything it sweeps through.
+    pub fn expand_towards(&self, motion: Vec3) -> Self {
+        Self {
+            min: self.min + motion.min(Vec3::ZERO),
+            max: self.max + motion.max(Vec3::ZERO),
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    // Shortens a movement of `self` along `axis` so it stops at `other`'s face instead of
+    // entering it. Boxes that don't overlap on the other two axes never block.
+    pub fn clip_motion(&self, other: &Aabb, axis: usize, motion: f32) -> f32 {
+        let overlaps_other_axes =
+            (0..3)
+                .filter(|&other_axis| other_axis != axis)
+                .all(|other_axis| {
+                    self.max[other_axis] > other.min[other_axis]
+                        && self.min[other_axis] < other.max[other_axis]
+                });
+        if !overlaps_other_axes {
+            return motion;
+        }
+
+        if motion > 0.0 && self.max[axis] <= other.min[axis] {
+            motion.min(other.min[axis] - self.max[axis])
+        } else if motion < 0.0 && self.min[axis] >= other.max[axis] {
+            motion.max(other.max[axis] - self.min[axis])
+        } else {
+            motion
+        }
+    }
+}
@@ -0,0 +1,379 @@
+use crate::assets::source::AssetSource;
+use crate::rendering::wgpu_context::WGPUContext;
+use log::error;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::{Debug, Formatter};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::num::NonZeroUsize;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{Receiver, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const MAX_WORKERS: usize = 4;
+
+// Something `AssetServer` can load. Loading is split so the slow part can run in the background:
+// `decode` reads and decodes CPU-side data on a worker thread, then `create` turns that into the
+// asset on the main thread, which is where GPU resources have to be created. The path usually
+// names a file, but assets made of several files can use it for a directory.
+pub trait Asset: Sized + 'static {
+    type Decoded: Send + 'static;
+    type Error: Error + Send + 'static;
+
+    fn decode(source: &AssetSource, path: &str) -> Result<Self::Decoded, Self::Error>;
+
+    fn create(
+        context: &WGPUContext,
+        path: &str,
+        decoded: Self::Decoded,
+    ) -> Result<Self, Self::Error>;
+
+    // Stands in for assets that failed to load.
+    fn placeholder(context: &WGPUContext) -> Self;
+}
+
+// Refers to an asset loaded by an `AssetServer`. Cheap to copy and the same for every load of a
+// path.
+pub struct Handle<T> {
+    id: usize,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    fn new(id: usize) -> Self {
+        Self {
+            id,
+            _marker: PhantomData,
+        }
+    }
+}
+
+// Implemented by hand, since deriving would require `T` to implement them too.
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+impl<T> Debug for Handle<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({})", self.id)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadState {
+    Loading,
+    Loaded,
+    Failed(String), // the error, for showing to the user
+}
+
+impl LoadState {
+    pub fn is_loading(&self) -> bool {
+        *self == LoadState::Loading
+    }
+}
+
+struct Entry<T> {
+    path: String,
+    state: LoadState,
+    asset: Option<T>,
+}
+
+// Everything loaded of one asset type.
+struct Storage<T> {
+    ids: HashMap<String, usize>,
+    entries: Vec<Entry<T>>,
+    placeholder: Option<T>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Self {
+            ids: HashMap::new(),
+            entries: vec![],
+            placeholder: None,
+        }
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// Finishes a load on the main thread, once the worker is done with it.
+type Completion = Box<dyn FnOnce(&mut AssetServer, &WGPUContext) + Send>;
+
+// Loads assets from an `AssetSource` in the background and hands out typed handles to them. Each
+// path is only loaded once per asset type. Loads finish in `update`, which has to be called
+// regularly, e.g. once per frame.
+pub struct AssetServer {
+    source: AssetSource,
+    storages: HashMap<TypeId, Box<dyn Any>>,
+    jobs: Sender<Job>,
+    completions: (Sender<Completion>, Receiver<Completion>),
+    pending: usize,
+}
+
+impl AssetServer {
+    pub fn new(source: AssetSource) -> Self {
+        let (jobs, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = thread::available_parallelism()
+            .map_or(1, NonZeroUsize::get)
+            .min(MAX_WORKERS);
+        for index in 0..workers {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("asset loader {index}"))
+                .spawn(move || {
+                    // Ends once the server and with it the sender are dropped.
+                    loop {
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    }
+                })
+                .expect("Failed to spawn asset loader thread");
+        }
+
+        Self {
+            source,
+            storages: HashMap::new(),
+            jobs,
+            completions: channel(),
+            pending: 0,
+        }
+    }
+
+    pub fn source(&self) -> &AssetSource {
+        &self.source
+    }
+
+    // Starts loading `path` unless it's already loading or loaded.
+    pub fn load<T: Asset>(&mut self, path: &str) -> Handle<T> {
+        let storage = self.storage_mut::<T>();
+        if let Some(&id) = storage.ids.get(path) {
+            return Handle::new(id);
+        }
+
+        let id = storage.entries.len();
+        storage.ids.insert(path.to_owned(), id);
+        storage.entries.push(Entry {
+            path: path.to_owned(),
+            state: LoadState::Loading,
+            asset: None,
+        });
+
+        let source = self.source.clone();
+        let completions = self.completions.0.clone();
+        let path = path.to_owned();
+        let job: Job = Box::new(move || {
+            // A panicking decoder still completes the load, or `wait_for_all` would never return.
+            let decoded = panic::catch_unwind(AssertUnwindSafe(|| T::decode(&source, &path)));
+
+            let completion: Completion = Box::new(move |server, context| {
+                let result = match decoded {
+                    Ok(decoded) => decoded
+                        .and_then(|decoded| T::create(context, &path, decoded))
+                        .map_err(|err| err.to_string()),
+                    Err(payload) => Err(panic_message(payload)),
+                };
+                server.finish(Handle::<T>::new(id), result, context);
+            });
+            let _ = completions.send(completion);
+        });
+        self.pending += 1;
+        // The workers only stop once `jobs` is dropped, so this can't fail.
+        let _ = self.jobs.send(job);
+
+        Handle::new(id)
+    }
+
+    // Finishes the loads whose background part is done.
+    pub fn update(&mut self, context: &WGPUContext) {
+        let finished: Vec<Completion> = self.completions.1.try_iter().collect();
+        for completion in finished {
+            completion(self, context);
+            self.pending -= 1;
+        }
+    }
+
+    // Blocks until every load has finished.
+    #[cfg(test)]
+    pub fn wait_for_all(&mut self, context: &WGPUContext) {
+        while self.pending > 0 {
+            let Ok(completion) = self.completions.1.recv() else {
+                break;
+            };
+            completion(self, context);
+            self.pending -= 1;
+        }
+    }
+
+    fn finish<T: Asset>(
+        &mut self,
+        handle: Handle<T>,
+        result: Result<T, String>,
+        context: &WGPUContext,
+    ) {
+        let storage = self.storage_mut::<T>();
+        let entry = &mut storage.entries[handle.id];
+        match result {
+            Ok(asset) => {
+                entry.state = LoadState::Loaded;
+                entry.asset = Some(asset);
+            }
+            Err(err) => {
+                error!("Failed to load {}: {}", entry.path, err);
+                entry.state = LoadState::Failed(err);
+                storage
+                    .placeholder
+                    .get_or_insert_with(|| T::placeholder(context));
+            }
+        }
+    }
+
+    pub fn state<T: Asset>(&self, handle: Handle<T>) -> &LoadState {
+        &self.storage::<T>().unwrap().entries[handle.id].state
+    }
+
+    // Whether anything is still loading.
+    #[cfg(test)]
+    pub fn is_loading(&self) -> bool {
+        self.pending > 0
+    }
+
+    // The asset once it's loaded, or the placeholder if it failed to. None while still loading.
+    pub fn get<T: Asset>(&self, handle: Handle<T>) -> Option<&T> {
+        let storage = self.storage::<T>()?;
+        let entry = &storage.entries[handle.id];
+        match entry.state {
+            LoadState::Loading => None,
+            LoadState::Loaded => entry.asset.as_ref(),
+            LoadState::Failed(_) => storage.placeholder.as_ref(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn path<T: Asset>(&self, handle: Handle<T>) -> &str {
+        &self.storage::<T>().unwrap().entries[handle.id].path
+    }
+
+    fn storage<T: Asset>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.downcast_ref())
+    }
+
+    fn storage_mut<T: Asset>(&mut self) -> &mut Storage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::default()))
+            .downcast_mut()
+            .unwrap()
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    let message = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown error");
+
+    format!("decoding panicked: {message}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::source::AssetError;
+    use crate::rendering::texture::Texture;
+
+    struct Panics;
+
+    impl Asset for Panics {
+        type Decoded = ();
+        type Error = AssetError;
+
+        fn decode(_: &AssetSource, path: &str) -> Result<(), AssetError> {
+            panic!("can't decode {path}");
+        }
+
+        fn create(_: &WGPUContext, _: &str, _: ()) -> Result<Self, AssetError> {
+            Ok(Self)
+        }
+
+        fn placeholder(_: &WGPUContext) -> Self {
+            Self
+        }
+    }
+
+    #[test]
+    fn loads_are_deduplicated_by_path() {
+        let mut server =
+            AssetServer::new(AssetSource::new().with_directory(env!("CARGO_MANIFEST_DIR")));
+
+        let atlas = server.load::<Texture>("/res/textures/blocks/stone.png");
+        let missing = server.load::<Texture>("/res/textures/missing.png");
+        assert_eq!(
+            server.load::<Texture>("/res/textures/blocks/stone.png"),
+            atlas
+        );
+        assert_ne!(atlas, missing);
+
+        // Nothing is finished before `update`, even if the worker is already done.
+        assert!(server.is_loading());
+        assert_eq!(server.state(atlas), &LoadState::Loading);
+        assert!(server.get(atlas).is_none());
+        assert_eq!(server.path(missing), "/res/textures/missing.png");
+    }
+
+    #[test]
+    fn failed_loads_resolve_to_the_placeholder() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let mut server =
+            AssetServer::new(AssetSource::new().with_directory(env!("CARGO_MANIFEST_DIR")));
+
+        let stone = server.load::<Texture>("/res/textures/blocks/stone.png");
+        let missing = server.load::<Texture>("/res/textures/missing.png");
+        let panics = server.load::<Panics>("/anything");
+        server.wait_for_all(&context);
+
+        assert!(!server.is_loading());
+        assert_eq!(server.state(stone), &LoadState::Loaded);
+        assert!(matches!(server.state(missing), LoadState::Failed(_)));
+        assert_eq!(
+            server.state(panics),
+            &LoadState::Failed("decoding panicked: can't decode /anything".to_owned())
+        );
+
+        // The 8x8 checkerboard rather than the 16x16 block texture.
+        assert_eq!(server.get(stone).unwrap().size.width, 16);
+        assert_eq!(server.get(missing).unwrap().size.width, 8);
+        assert!(server.get(panics).is_some());
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeSet;
+use std::path::{Component, Path, PathBuf};
+use std::{env, fs, io};
+use thiserror::Error;
+
+// Overrides the directory assets are loaded from.
+pub const ROOT_VAR: &str = "VOXEL_ASSET_ROOT";
+// Extra directories searched before the root, highest priority first, separated like PATH.
+pub const OVERLAYS_VAR: &str = "VOXEL_ASSET_OVERLAYS";
+
+#[derive(Error, Debug)]
+pub enum AssetError {
+    #[error("Asset '{0}' wasn't found in any asset directory.")]
+    NotFound(String),
+    #[error("Asset path '{0}' must start with '/' and can't contain '..'.")]
+    InvalidPath(String),
+    #[error("Failed to read asset '{path}' due to {source:?}.")]
+    Io { path: String, source: io::Error },
+    #[error("Asset '{0}' isn't valid UTF-8.")]
+    InvalidUtf8(String),
+}
+
+pub type EmbeddedAssets = &'static [(&'static str, &'static [u8])];
+
+// Every file in /res/, built into the binary so it runs without the directory next to it.
+#[cfg(feature = "embedded-assets")]
+const EMBEDDED: EmbeddedAssets = include!(concat!(env!("OUT_DIR"), "/embedded_assets.rs"));
+
+#[derive(Clone, Debug)]
+enum Layer {
+    Directory(PathBuf),
+    Embedded(EmbeddedAssets),
+}
+
+// Where assets are read from. Paths look like "/res/textures/atlas.png" and are looked up in each
+// layer in turn, so mods and overrides can replace single files of the layers below them.
+#[derive(Clone, Debug, Default)]
+pub struct AssetSource {
+    layers: Vec<Layer>,
+}
+
+impl AssetSource {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Overlays from `VOXEL_ASSET_OVERLAYS`, then the root, then the embedded assets if the
+    // `embedded-assets` feature is on. The root is `VOXEL_ASSET_ROOT` if set, otherwise the
+    // directory of the executable, where `build.rs` copies /res/. Debug builds use the repository
+    // instead, so edits show up without a rebuild.
+    pub fn from_env() -> Self {
+        let root = env::var_os(ROOT_VAR)
+            .map(PathBuf::from)
+            .unwrap_or_else(default_root);
+
+        let mut source = Self::new();
+        if let Some(overlays) = env::var_os(OVERLAYS_VAR) {
+            for overlay in env::split_paths(&overlays) {
+                source = source.with_directory(overlay);
+            }
+        }
+        let source = source.with_directory(root);
+
+        #[cfg(feature = "embedded-assets")]
+        let source = source.with_embedded(EMBEDDED);
+
+        source
+    }
+
+    // Searched after every layer added so far.
+    pub fn with_directory(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.layers.push(Layer::Directory(dir.into()));
+
+        self
+    }
+
+    // Searched after every layer added so far.
+    pub fn with_embedded(mut self, assets: EmbeddedAssets) -> Self {
+        self.layers.push(Layer::Embedded(assets));
+
+        self
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, AssetError> {
+        let relative = relative_path(path)?;
+
+        for layer in &self.layers {
+            match layer {
+                Layer::Directory(dir) => match fs::read(dir.join(relative)) {
+                    Ok(bytes) => return Ok(bytes),
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(source) => {
+                        return Err(AssetError::Io {
+                            path: path.to_owned(),
+                            source,
+                        });
+                    }
+                },
+                Layer::Embedded(assets) => {
+                    if let Some((_, bytes)) = assets.iter().find(|(asset, _)| *asset == path) {
+                        return Ok(bytes.to_vec());
+                    }
+                }
+            }
+        }
+
+        Err(AssetError::NotFound(path.to_owned()))
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Result<String, AssetError> {
+        String::from_utf8(self.read(path)?).map_err(|_| AssetError::InvalidUtf8(path.to_owned()))
+    }
+
+    // Paths of the files directly inside `dir` (like "/res/textures/blocks") in any layer, sorted.
+    pub fn list(&self, dir: &str) -> Result<Vec<String>, AssetError> {
+        let relative = relative_path(dir)?;
+        let prefix = dir.trim_end_matches('/').to_owned() + "/";
+        let io_error = |source| AssetError::Io {
+            path: dir.to_owned(),
+            source,
+        };
+
+        let mut paths = BTreeSet::new();
+        for layer in &self.layers {
+            match layer {
+                Layer::Directory(root) => {
+                    let entries = match fs::read_dir(root.join(relative)) {
+                        Ok(entries) => entries,
+                        Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                        Err(err) => return Err(io_error(err)),
+                    };
+                    for entry in entries {
+                        let entry = entry.map_err(io_error)?;
+                        if entry.file_type().map_err(io_error)?.is_file()
+                            && let Some(name) = entry.file_name().to_str()
+                        {
+                            paths.insert(prefix.clone() + name);
+                        }
+                    }
+                }
+                Layer::Embedded(assets) => {
+                    for (path, _) in assets.iter() {
+                        if let Some(name) = path.strip_prefix(&prefix)
+                            && !name.contains('/')
+                        {
+                            paths.insert(path.to_string());
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(paths.into_iter().collect())
+    }
+
+    // The directory layers, highest priority first.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))] // for hot reloading
+    pub fn directories(&self) -> impl Iterator<Item = &Path> {
+        self.layers.iter().filter_map(|layer| match layer {
+            Layer::Directory(dir) => Some(dir.as_path()),
+            Layer::Embedded(_) => None,
+        })
+    }
+}
+
+fn default_root() -> PathBuf {
+    if cfg!(debug_assertions) {
+        return PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    }
+
+    env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.to_owned()))
+        .unwrap_or_default()
+}
+
+// Asset paths are absolute within the source. What follows the leading `/` may only name
+// directories and files, so joining it onto a directory can't leave it: no roots, drive
+// prefixes or `..`.
+fn relative_path(path: &str) -> Result<&str, AssetError> {
+    match path.strip_prefix('/') {
+        Some(relative)
+            if !relative.is_empty()
+                && Path::new(relative)
+                    .components()
+                    .all(|component| matches!(component, Component::Normal(_))) =>
+        {
+            Ok(relative)
+        }
+        _ => Err(AssetError::InvalidPath(path.to_owned())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: EmbeddedAssets = &[("/res/a.txt", b"base a"), ("/res/b.txt", b"base b")];
+    const MOD: EmbeddedAssets = &[("/res/a.txt", b"mod a")];
+
+    #[test]
+    fn layers_are_searched_in_priority_order() {
+        let source = AssetSource::new().with_embedded(BASE).with_embedded(MOD);
+        assert_eq!(source.read_to_string("/res/a.txt").unwrap(), "base a");
+
+        let source = AssetSource::new().with_embedded(MOD).with_embedded(BASE);
+        assert_eq!(source.read_to_string("/res/a.txt").unwrap(), "mod a");
+        assert_eq!(source.read_to_string("/res/b.txt").unwrap(), "base b");
+    }
+
+    #[test]
+    fn overlays_take_priority_over_the_root() {
+        // Like `from_env` adds them.
+        let source = AssetSource::new()
+            .with_directory(env!("CARGO_MANIFEST_DIR"))
+            .with_embedded(BASE);
+
+        assert!(source.read("/res/textures/blocks/stone.png").is_ok());
+        assert_eq!(source.read_to_string("/res/b.txt").unwrap(), "base b");
+        assert_eq!(
+            source.directories().collect::<Vec<_>>(),
+            [Path::new(env!("CARGO_MANIFEST_DIR"))]
+        );
+    }
+
+    #[test]
+    fn listings_merge_all_layers() {
+        let source = AssetSource::new()
+            .with_embedded(BASE)
+            .with_directory(env!("CARGO_MANIFEST_DIR"));
+
+        assert_eq!(source.list("/res").unwrap(), ["/res/a.txt", "/res/b.txt"]);
+        assert!(
+            source
+                .list("/res/textures/blocks")
+                .unwrap()
+                .contains(&"/res/textures/blocks/stone.png".to_owned())
+        );
+        assert!(source.list("/res/missing").unwrap().is_empty());
+    }
+
+    #[test]
+    fn missing_and_invalid_paths_are_errors() {
+        let source = AssetSource::new()
+            .with_directory(env!("CARGO_MANIFEST_DIR"))
+            .with_embedded(BASE);
+
+        assert!(matches!(
+            source.read("/res/missing.png"),
+            Err(AssetError::NotFound(path)) if path == "/res/missing.png"
+        ));
+        assert!(matches!(
+            source.read("/res/../Cargo.toml"),
+            Err(AssetError::InvalidPath(_))
+        ));
+        assert!(matches!(
+            source.read("res/a.txt"),
+            Err(AssetError::InvalidPath(_))
+        ));
+        for path in ["//etc/passwd", "/", "/res/./../Cargo.toml"] {
+            assert!(
+                matches!(source.read(path), Err(AssetError::InvalidPath(_))),
+                "{path}"
+            );
+        }
+    }
+}
@@ -1,38 +1,20 @@
+use crate::aabb::Aabb;
 use crate::rendering::buffer::Buffer;
+use crate::rendering::instance::InstanceData;
 use crate::rendering::material::Material;
 use crate::rendering::mesh::Mesh;
 use crate::rendering::render_object::{PassType, RenderObject};
 use crate::rendering::renderer::Renderer;
 use crate::rendering::vertex::Vertex;
-use bytemuck::{Pod, Zeroable};
 use glam::{Mat4, Quat, Vec3};
-use std::time::Instant;
-use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexStepMode, vertex_attr_array};
-
-#[repr(C)]
-#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
-pub struct CubeData {
-    pub model: Mat4,
-}
-
-impl CubeData {
-    pub(crate) fn desc() -> VertexBufferLayout<'static> {
-        const ATTRIBS: [VertexAttribute; 4] =
-            vertex_attr_array![5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4];
-
-        VertexBufferLayout {
-            array_stride: size_of::<CubeData>() as BufferAddress,
-            step_mode: VertexStepMode::Instance,
-            attributes: &ATTRIBS,
-        }
-    }
-}
 
 pub struct Cubes {
     render_object: RenderObject,
-    instance_buffer: Buffer<CubeData>, // gpu side
-    instance_data: Vec<CubeData>,      // cpu side
-    start_time: Instant,
+    instance_buffer: Buffer<InstanceData>, // gpu side
+    instance_data: Vec<InstanceData>,      // cpu side
+    base_models: Vec<Mat4>,
+    previous_angle: f32,
+    angle: f32,
 }
 
 impl Cubes {
@@ -43,56 +25,69 @@ impl Cubes {
         Self::NUM_INSTANCES_PER_ROW as f32 * 0.5,
     );
 
+    const ROTATION_SPEED: f32 = 2.0; // radians per second
+
     const TRIANGLE_VERTICES: [Vertex; 6] = [
         Vertex {
             position: [0.0, 0.625, 0.0],
             tex_coords: [1.0, 0.0],
+            layer: 0,
         },
         Vertex {
             position: [-0.5, -0.5, 0.0],
             tex_coords: [0.0, 1.0],
+            layer: 0,
         },
         Vertex {
             position: [0.5, -0.5, 0.0],
             tex_coords: [0.0, 0.0],
+            layer: 0,
         },
         Vertex {
             position: [0.0, -0.5, 0.0],
             tex_coords: [0.0, 0.5],
+            layer: 0,
         },
         Vertex {
             position: [-0.25, 0.125, 0.0],
             tex_coords: [0.5, 0.5],
+            layer: 0,
         },
         Vertex {
             position: [0.25, 0.125, 0.0],
             tex_coords: [0.0, 0.5],
+            layer: 0,
         },
     ];
 
-    const TRIANGLE_INDICES: [u16; 9] = [0, 4, 5, 1, 3, 4, 2, 5, 3];
+    const TRIANGLE_INDICES: [u32; 9] = [0, 4, 5, 1, 3, 4, 2, 5, 3];
 
     pub fn new(renderer: &Renderer, material: &Material) -> Self {
-        let instances: Vec<CubeData> = (0..Self::NUM_INSTANCES_PER_ROW)
+        let instances: Vec<InstanceData> = (0..Self::NUM_INSTANCES_PER_ROW)
             .flat_map(|z| {
                 (0..Self::NUM_INSTANCES_PER_ROW).map(move |x| {
                     let position = Vec3::new(x as f32, 0.0, z as f32) - Self::INSTANCE_DISPLACEMENT;
                     let rotation = Quat::from_axis_angle(Vec3::Z, 0f32.to_degrees());
                     let model = Mat4::from_translation(position) * Mat4::from_quat(rotation);
 
-                    CubeData { model }
+                    InstanceData { model }
                 })
             })
             .collect();
 
         let mesh = Mesh {
-            vertices: Buffer::new_vertex(&renderer.context(), Some(&Self::TRIANGLE_VERTICES)),
-            indices: Buffer::new_index(&renderer.context(), Some(&Self::TRIANGLE_INDICES)),
+            vertices: Buffer::new_vertex(renderer.context(), Some(&Self::TRIANGLE_VERTICES)),
+            indices: Buffer::new_index(renderer.context(), Some(&Self::TRIANGLE_INDICES)),
             num_indices: Self::TRIANGLE_INDICES.len() as u32,
             start_index: 0,
+            bounds: Aabb::from_points(
+                Self::TRIANGLE_VERTICES
+                    .iter()
+                    .map(|vertex| vertex.position.into()),
+            ),
         };
 
-        let instance_buffer = Buffer::new_instance(&renderer.context(), Some(&instances));
+        let instance_buffer = Buffer::new_instance(renderer.context(), Some(&instances));
 
         Self {
             render_object: RenderObject {
@@ -101,23 +96,32 @@ impl Cubes {
                 pass: PassType::Opaque,
                 instances: instance_buffer.buffer().clone(),
                 instances_len: instance_buffer.len(),
+                bounds: None, // set once the instances are placed
             },
             instance_buffer,
+            base_models: instances.iter().map(|instance| instance.model).collect(),
             instance_data: instances,
-            start_time: Instant::now(),
+            previous_angle: 0.0,
+            angle: 0.0,
         }
     }
 
-    pub fn render(&mut self, renderer: &mut Renderer) {
-        let dt = (Instant::now() - self.start_time).as_secs_f32();
-        self.start_time = Instant::now();
-        let rotation = Quat::from_axis_angle(Vec3::Y, dt * 2f32);
-        for instance in &mut self.instance_data {
-            instance.model *= Mat4::from_quat(rotation);
+    pub fn tick(&mut self, dt: f32) {
+        self.previous_angle = self.angle;
+        self.angle += dt * Self::ROTATION_SPEED;
+    }
+
+    // `alpha` blends between the previous and the current tick so motion stays smooth at any frame rate.
+    pub fn render(&mut self, renderer: &mut Renderer, alpha: f32) {
+        let angle = self.previous_angle + (self.angle - self.previous_angle) * alpha;
+        let rotation = Mat4::from_quat(Quat::from_axis_angle(Vec3::Y, angle));
+        for (instance, base_model) in self.instance_data.iter_mut().zip(&self.base_models) {
+            instance.model = *base_model * rotation;
         }
 
         self.instance_buffer
-            .upload(&renderer.context(), &self.instance_data);
+            .upload(renderer.context(), &self.instance_data);
+        self.render_object.bounds = self.render_object.mesh.instance_bounds(&self.instance_data);
 
         renderer.push_object(&self.render_object);
     }
@@ -0,0 +1,139 @@
+use crate::world::World;
+use std::time::Duration;
+
+// A fixed-rate simulation clock. Frame time is fed into an accumulator and drained in whole ticks,
+// leaving a fractional remainder that rendering uses to interpolate between the last two ticks.
+// Inspired by https://gafferongames.com/post/fix_your_timestep/.
+pub struct FixedTimestep {
+    tick_duration: Duration,
+    accumulator: Duration,
+    tick: u64,
+    max_ticks_per_frame: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(ticks_per_second: u32) -> Self {
+        Self {
+            tick_duration: Duration::from_secs(1) / ticks_per_second,
+            accumulator: Duration::ZERO,
+            tick: 0,
+            max_ticks_per_frame: 10,
+        }
+    }
+
+    // Caps how many ticks a single frame may run, so a long stall doesn't snowball into
+    // ever-longer frames (the "spiral of death"). Time beyond the cap is dropped.
+    #[cfg(test)]
+    pub fn with_max_ticks_per_frame(mut self, max_ticks: u32) -> Self {
+        self.max_ticks_per_frame = max_ticks;
+
+        self
+    }
+
+    // Adds elapsed frame time and returns how many ticks should be simulated this frame.
+    pub fn advance(&mut self, frame_time: Duration) -> u32 {
+        self.accumulator += frame_time;
+
+        let mut ticks = 0;
+        while self.accumulator >= self.tick_duration {
+            if ticks == self.max_ticks_per_frame {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+
+            self.accumulator -= self.tick_duration;
+            self.tick += 1;
+            ticks += 1;
+        }
+
+        ticks
+    }
+
+    // Advances exactly one tick regardless of wall-clock time, for `run_headless`.
+    pub fn step(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    // How far rendering is between the previous tick and the current one, in [0, 1).
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.tick_duration.as_secs_f32()
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.tick_duration.as_secs_f32()
+    }
+}
+
+// Ticks `world` `ticks` times as fast as it'll go, without a window or renderer.
+pub fn run_headless(world: &mut World, timestep: &mut FixedTimestep, ticks: u64) {
+    let end = timestep.tick() + ticks;
+    while timestep.tick() < end {
+        timestep.step();
+        world.tick();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::{BlockId, BlockRegistry};
+    use crate::world::test_worlds::floor_world;
+    use glam::IVec3;
+
+    #[test]
+    fn accumulates_partial_frames_into_ticks() {
+        let mut timestep = FixedTimestep::new(20);
+
+        assert_eq!(timestep.advance(Duration::from_millis(30)), 0);
+        assert_eq!(timestep.advance(Duration::from_millis(30)), 1);
+        assert_eq!(timestep.tick(), 1);
+        assert!((timestep.alpha() - 0.2).abs() < 1e-4);
+    }
+
+    #[test]
+    fn same_frame_times_produce_same_ticks() {
+        let frames = [16, 17, 33, 5, 120, 16, 16, 70].map(Duration::from_millis);
+
+        let run = || {
+            let mut timestep = FixedTimestep::new(60);
+            frames
+                .iter()
+                .map(|&frame| timestep.advance(frame))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn long_stalls_are_clamped() {
+        let mut timestep = FixedTimestep::new(20).with_max_ticks_per_frame(4);
+
+        assert_eq!(timestep.advance(Duration::from_secs(10)), 4);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn headless_runs_step_the_world() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_block(IVec3::new(4, 10, 4), BlockId::SAND);
+        let mut timestep = FixedTimestep::new(20);
+        timestep.advance(Duration::from_millis(30));
+
+        run_headless(&mut world, &mut timestep, 100);
+
+        assert_eq!(timestep.tick(), 100);
+        // Stepping leaves the partial frame alone.
+        assert!((timestep.alpha() - 0.6).abs() < 1e-4);
+        assert_eq!(world.block(IVec3::new(4, 1, 4)), BlockId::SAND);
+    }
+}
@@ -1,22 +1,40 @@
+mod aabb;
+mod assets;
 mod camera_controller;
 mod cubes;
+mod game_loop;
 mod macros;
 mod rendering;
+mod world;
+mod world_renderer;
 
+use crate::assets::server::{AssetServer, Handle};
+use crate::assets::source::AssetSource;
 use crate::camera_controller::CameraController;
 use crate::cubes::Cubes;
+use crate::game_loop::FixedTimestep;
+use crate::rendering::atlas::TextureAtlas;
 use crate::rendering::global_bindings::{GlobalBindings, GlobalBufferContext};
 use crate::rendering::material::Material;
-use crate::rendering::renderer::Renderer;
+use crate::rendering::pipeline::PipelineDescriptor;
+use crate::rendering::render_graph::POST_PROCESS_PASS;
+use crate::rendering::renderer::{RenderError, Renderer};
 use crate::rendering::shader::Shader;
-use crate::rendering::texture::Texture;
+use crate::rendering::texture::TextureArray;
 use crate::rendering::utils::bind_group_builder::BindGroupBuilder;
-use crate::rendering::utils::bind_group_layout_builder::BindGroupLayoutBuilder;
+use crate::world::World;
+use crate::world::block::BlockId;
+use crate::world::chunk::{CHUNK_SIZE, Chunk};
+use crate::world::fluid::{Fluid, FluidState};
+use crate::world::mesher::BlockUvs;
+use crate::world_renderer::WorldRenderer;
+use glam::IVec3;
 use log::*;
+use std::env;
 use std::process::abort;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use wgpu::{ShaderStages, SurfaceError};
+use wgpu::SurfaceError;
 use winit::application::ApplicationHandler;
 use winit::dpi::{LogicalSize, PhysicalPosition};
 use winit::event::{DeviceId, KeyEvent, WindowEvent};
@@ -24,34 +42,100 @@ use winit::event_loop::{ActiveEventLoop, EventLoop};
 use winit::keyboard::{KeyCode, PhysicalKey};
 use winit::window::{CursorGrabMode, Window, WindowId};
 
+const BLOCK_TEXTURES: &str = "/res/textures/blocks";
+// Set to draw blocks from a texture array, one layer per texture, instead of the atlas.
+const TEXTURE_ARRAY_VAR: &str = "VOXEL_TEXTURE_ARRAY";
+// `voxel --headless [ticks]` only runs the simulation, a minute of it by default.
+const HEADLESS_ARG: &str = "--headless";
+const DEFAULT_HEADLESS_TICKS: u64 = 60 * App::TICKS_PER_SECOND as u64;
+
+enum BlockTextureSet {
+    Atlas(Handle<TextureAtlas>),
+    Array(Handle<TextureArray>),
+}
+
 struct App {
     last_frame_time: Instant,
+    last_update_time: Instant,
     frame_count: u64,
+    timestep: FixedTimestep,
     cam_controller: CameraController,
 
     renderer: Option<Renderer>,
     global_bindings: Option<GlobalBindings>,
 
-    atlas: Option<Texture>,
+    assets: AssetServer,
+    block_textures: BlockTextureSet,
     default_opaque_shader: Option<Shader>,
     default_opaque: Option<Material>,
+    default_transparent: Option<Material>,
 
     cubes: Option<Cubes>,
+
+    world: World,
+    world_renderer: Option<WorldRenderer>,
 }
 
 impl App {
+    const TICKS_PER_SECOND: u32 = 20;
+
     pub fn new(_event_loop: &EventLoop<()>) -> Self {
+        // Loading starts right away, the materials using it are created once it's done.
+        let mut assets = AssetServer::new(AssetSource::from_env());
+        let block_textures = if env::var_os(TEXTURE_ARRAY_VAR).is_some() {
+            BlockTextureSet::Array(assets.load(BLOCK_TEXTURES))
+        } else {
+            BlockTextureSet::Atlas(assets.load(BLOCK_TEXTURES))
+        };
+
         Self {
             last_frame_time: Instant::now(),
+            last_update_time: Instant::now(),
             frame_count: 0,
+            timestep: FixedTimestep::new(Self::TICKS_PER_SECOND),
             cam_controller: CameraController::new(0.002),
             renderer: None,
             global_bindings: None,
-            atlas: None,
+            assets,
+            block_textures,
             default_opaque_shader: None,
             default_opaque: None,
+            default_transparent: None,
             cubes: None,
+            world: Self::create_world(),
+            world_renderer: None,
+        }
+    }
+
+    // A small basin with a water spring on a pillar, a lava pool and a floating column of sand.
+    fn create_world() -> World {
+        let mut world = World::new();
+        for x in -1..=0 {
+            for y in -1..=0 {
+                for z in -1..=0 {
+                    world.insert_chunk(IVec3::new(x, y, z), Chunk::new());
+                }
+            }
+        }
+
+        for x in -CHUNK_SIZE..CHUNK_SIZE {
+            for z in -CHUNK_SIZE..CHUNK_SIZE {
+                world.set_block(IVec3::new(x, -4, z), BlockId::STONE);
+                world.set_block(IVec3::new(x, -3, z), BlockId::GRASS);
+            }
+        }
+        for y in -2..=0 {
+            world.set_block(IVec3::new(-6, y, -6), BlockId::STONE);
         }
+
+        world.set_fluid(IVec3::new(-6, 1, -6), FluidState::source(Fluid::Water));
+        world.set_fluid(IVec3::new(6, -2, 6), FluidState::source(Fluid::Lava));
+
+        for y in 4..8 {
+            world.set_block(IVec3::new(2, y, -2), BlockId::SAND);
+        }
+
+        world
     }
 }
 
@@ -60,6 +144,62 @@ impl App {
         self.cam_controller.handle_key(code, is_pressed);
         match (code, is_pressed) {
             (KeyCode::Escape, true) => event_loop.exit(),
+            // Toggles occlusion culling, to compare against drawing everything.
+            (KeyCode::KeyO, true) => {
+                if let Some(world_renderer) = &mut self.world_renderer {
+                    world_renderer.occlusion_culling = !world_renderer.occlusion_culling;
+                    info!(
+                        "Occlusion culling {}",
+                        if world_renderer.occlusion_culling {
+                            "on"
+                        } else {
+                            "off"
+                        }
+                    );
+                }
+            }
+            // Switches frustum culling of chunks between the CPU and a compute pass.
+            (KeyCode::KeyG, true) => {
+                if let Some(world_renderer) = &mut self.world_renderer
+                    && world_renderer.supports_gpu_culling()
+                {
+                    world_renderer.gpu_culling = !world_renderer.gpu_culling;
+                    info!(
+                        "Chunk culling on the {}",
+                        if world_renderer.gpu_culling {
+                            "GPU"
+                        } else {
+                            "CPU"
+                        }
+                    );
+                }
+            }
+            // Toggles raymarching distant regions instead of drawing their meshes.
+            (KeyCode::KeyF, true) => {
+                if let Some(world_renderer) = &mut self.world_renderer
+                    && world_renderer.supports_far_field()
+                {
+                    world_renderer.far_field = !world_renderer.far_field;
+                    info!(
+                        "Far field {}",
+                        if world_renderer.far_field {
+                            "on"
+                        } else {
+                            "off"
+                        }
+                    );
+                }
+            }
+            // Toggles post-processing, drawing the scene straight to the surface without it.
+            (KeyCode::KeyP, true) => {
+                if let Some(renderer) = &mut self.renderer {
+                    let enabled = !renderer.is_pass_enabled(POST_PROCESS_PASS);
+                    match renderer.set_pass_enabled(POST_PROCESS_PASS, enabled) {
+                        Ok(()) => info!("Post-processing {}", if enabled { "on" } else { "off" }),
+                        Err(err) => error!("Failed to toggle post-processing: {}", err),
+                    }
+                }
+            }
             _ => {}
         }
     }
@@ -72,54 +212,103 @@ impl App {
     ) {
     }
 
-    pub fn load_assets(&mut self) -> anyhow::Result<()> {
-        let renderer = self.renderer.as_mut().unwrap();
+    // Called once the textures have loaded, or been replaced by placeholders.
+    pub fn create_materials(&mut self) {
+        let renderer = self.renderer.as_ref().unwrap();
+        let registry = self.world.registry();
+        let (shader_path, textures, uvs) = match self.block_textures {
+            BlockTextureSet::Atlas(handle) => {
+                let atlas = self.assets.get(handle).unwrap();
+                (
+                    "/res/shaders/default.wgsl",
+                    BindGroupBuilder::new().with_texture2d(0, &atlas.texture.view),
+                    BlockUvs::new(registry, &atlas.uvs),
+                )
+            }
+            BlockTextureSet::Array(handle) => {
+                let array = self.assets.get(handle).unwrap();
+                (
+                    "/res/shaders/blocks_array.wgsl",
+                    BindGroupBuilder::new().with_texture2d_array(0, &array.texture.view),
+                    BlockUvs::from_layers(registry, array),
+                )
+            }
+        };
 
-        let atlas = renderer
-            .create_texture("/res/textures/atlas.png")
-            .unwrap_or_else(|err| {
-                fatal!("Failed to load texture atlas: {}", err);
-            });
-
-        self.atlas = Some(atlas);
-
-        let default_shader_layout = BindGroupLayoutBuilder::new()
-            .with_texture2d(ShaderStages::FRAGMENT)
-            .build(renderer.context(), Some("Default shader layout"));
-
-        let default_shader = renderer
-            .create_shader(
-                "/res/shaders/default.wgsl",
-                default_shader_layout,
-                self.global_bindings.as_ref().unwrap(),
-            )
-            .unwrap_or_else(|err| {
-                fatal!("Failed to load default shader: {}", err);
-            });
-
-        self.default_opaque_shader = Some(default_shader);
-
-        let default_material_bind_group = BindGroupBuilder::new()
-            .with_texture2d(&self.atlas.as_ref().unwrap().view)
-            .build(
-                renderer.context(),
-                &self.default_opaque_shader.as_ref().unwrap().material_layout,
-                Some("Default Material Bind Group"),
-            );
+        // The material layout comes from the shader's @group(1). A broken shader shouldn't take
+        // the whole application down, so it's replaced with the fallback.
+        let global_bindings = self.global_bindings.as_ref().unwrap();
+        let default_opaque = match renderer.create_shader(shader_path, global_bindings) {
+            Ok(shader) => renderer
+                .create_material(&shader, textures, Some("Default Material Bind Group"))
+                .unwrap_or_else(|err| {
+                    fatal!("Failed to create default material: {}", err);
+                }),
+            Err(err) => {
+                error!("Failed to load default shader, using the fallback: {}", err);
+                renderer.create_fallback_material(global_bindings)
+            }
+        };
 
-        let default_opaque = Material {
-            shader: self.default_opaque_shader.as_ref().unwrap().clone(),
-            bind_group: default_material_bind_group,
+        self.default_opaque_shader = Some(default_opaque.shader.clone());
+
+        // Same shader and textures, blended and drawn after everything opaque.
+        let default_transparent = Material {
+            shader: renderer
+                .create_shader_variant(&default_opaque.shader, &PipelineDescriptor::transparent())
+                .unwrap_or_else(|err| {
+                    fatal!("Failed to create transparent shader variant: {}", err);
+                }),
+            bind_group: default_opaque.bind_group.clone(),
         };
 
         self.cubes = Some(Cubes::new(self.renderer.as_ref().unwrap(), &default_opaque));
+        self.world_renderer = Some(WorldRenderer::new(
+            self.renderer.as_ref().unwrap(),
+            global_bindings,
+            &default_opaque,
+            &default_transparent,
+            uvs,
+        ));
 
         self.default_opaque = Some(default_opaque);
+        self.default_transparent = Some(default_transparent);
+    }
+
+    pub fn update(&mut self) {
+        let now = Instant::now();
+        let ticks = self.timestep.advance(now - self.last_update_time);
+        self.last_update_time = now;
+
+        for _ in 0..ticks {
+            self.tick();
+        }
+    }
+
+    // Advances the simulation by one fixed step.
+    pub fn tick(&mut self) {
+        let dt = self.timestep.delta_seconds();
+        if let Some(cubes) = self.cubes.as_mut() {
+            cubes.tick(dt);
+        }
 
-        Ok(())
+        self.world.tick();
     }
 
     pub fn render(&mut self) {
+        let alpha = self.timestep.alpha();
+        let renderer = self.renderer.as_ref().unwrap();
+
+        renderer.reload_changed_shaders();
+        self.assets.update(renderer.context());
+        let loading = match self.block_textures {
+            BlockTextureSet::Atlas(handle) => self.assets.state(handle).is_loading(),
+            BlockTextureSet::Array(handle) => self.assets.state(handle).is_loading(),
+        };
+        if self.world_renderer.is_none() && !loading {
+            self.create_materials();
+        }
+
         let renderer = self.renderer.as_mut().unwrap();
 
         self.cam_controller.update_camera(&mut renderer.camera);
@@ -128,17 +317,23 @@ impl App {
             GlobalBufferContext::new(&renderer.camera),
         );
 
-        let cubes = self.cubes.as_mut().unwrap();
-        cubes.render(renderer);
+        // Both are None until the materials exist.
+        if let Some(cubes) = self.cubes.as_mut() {
+            cubes.render(renderer, alpha);
+        }
+        if let Some(world_renderer) = self.world_renderer.as_mut() {
+            world_renderer.update(renderer, &mut self.world);
+            world_renderer.render(renderer, &self.world, alpha);
+        }
 
         match renderer.render(self.global_bindings.as_ref().unwrap()) {
             Ok(_) => {}
-            Err(SurfaceError::Lost) => {}
-            Err(SurfaceError::Outdated) => {}
-            Err(SurfaceError::OutOfMemory) => {
+            Err(RenderError::Surface(SurfaceError::Lost)) => {}
+            Err(RenderError::Surface(SurfaceError::Outdated)) => {}
+            Err(RenderError::Surface(SurfaceError::OutOfMemory)) => {
                 fatal!("Out of memory!!");
             }
-            Err(SurfaceError::Timeout) => {
+            Err(RenderError::Surface(SurfaceError::Timeout)) => {
                 warn!("Surface timed out!");
             }
             Err(err) => error!("Failed to render! Error: {:?}", err),
@@ -148,7 +343,15 @@ impl App {
         let elapsed = self.last_frame_time.elapsed();
         if elapsed >= Duration::from_secs(1) {
             let fps = self.frame_count as f32 / elapsed.as_secs_f32();
-            println!("FPS: {:.1}", fps);
+            let stats = self.renderer.as_ref().unwrap().cull_stats();
+            let occluded = self
+                .world_renderer
+                .as_ref()
+                .map_or(0, WorldRenderer::occluded_chunks);
+            println!(
+                "FPS: {:.1}, drawn: {}, culled: {}, occluded chunks: {}",
+                fps, stats.drawn, stats.culled, occluded
+            );
 
             self.frame_count = 0;
             self.last_frame_time = Instant::now();
@@ -171,17 +374,15 @@ impl ApplicationHandler for App {
             .set_cursor_grab(CursorGrabMode::Confined)
             .unwrap_or_else(|_| error!("Failed to set cursor grab mode!"));
         window.set_cursor_visible(false);
-        let renderer = pollster::block_on(Renderer::new(window))
+        let renderer = pollster::block_on(Renderer::new(window, self.assets.source().clone()))
             .unwrap_or_else(|err| fatal!("Failed to create renderer! Error: {:?}", err));
 
         self.global_bindings = Some(GlobalBindings::new(
-            &renderer.context(),
+            renderer.context(),
             GlobalBufferContext::new(&renderer.camera),
         ));
         self.renderer = Some(renderer);
-
-        self.load_assets()
-            .unwrap_or_else(|err| fatal!("Failed to load assets! Error: {:?}", err));
+        self.last_update_time = Instant::now();
     }
 
     fn window_event(
@@ -196,7 +397,10 @@ impl ApplicationHandler for App {
                 let renderer = self.renderer.as_mut().unwrap();
                 renderer.resize(size.width, size.height);
             }
-            WindowEvent::RedrawRequested => self.render(),
+            WindowEvent::RedrawRequested => {
+                self.update();
+                self.render();
+            }
             WindowEvent::KeyboardInput {
                 event:
                     KeyEvent {
@@ -215,8 +419,30 @@ impl ApplicationHandler for App {
     }
 }
 
+// Simulates the demo world for `ticks` ticks without opening a window and prints how long it took.
+fn run_headless(ticks: u64) {
+    let mut world = App::create_world();
+    let mut timestep = FixedTimestep::new(App::TICKS_PER_SECOND);
+
+    let start = Instant::now();
+    game_loop::run_headless(&mut world, &mut timestep, ticks);
+    println!(
+        "Simulated {} ticks ({:.1?}) in {:.1?}",
+        ticks,
+        timestep.tick_duration().mul_f64(ticks as f64),
+        start.elapsed()
+    );
+}
+
 pub fn run() -> anyhow::Result<()> {
     pretty_env_logger::init();
+    let mut args = env::args().skip(1);
+    if args.next().as_deref() == Some(HEADLESS_ARG) {
+        let ticks = args.next().map(|ticks| ticks.parse()).transpose()?;
+        run_headless(ticks.unwrap_or(DEFAULT_HEADLESS_TICKS));
+        return Ok(());
+    }
+
     let event_loop = EventLoop::new()?;
     let mut app = App::new(&event_loop);
     event_loop.run_app(&mut app)?;
@@ -0,0 +1,247 @@
+use crate::rendering::buffer::{Buffer, grown_capacity};
+use crate::rendering::wgpu_context::WGPUContext;
+use bytemuck::{Pod, Zeroable};
+use std::ops::Range;
+use wgpu::BufferUsages;
+
+// Hands out ranges of `0..capacity`, e.g. elements of a shared buffer. Freed ranges are merged with
+// free neighbours, and allocations take the first free range that's large enough.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RangeAllocator {
+    capacity: u32,
+    free: Vec<Range<u32>>, // sorted, never empty or touching each other
+}
+
+impl RangeAllocator {
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            capacity,
+            free: Some(0..capacity)
+                .filter(|range| range.start < range.end)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+
+    // Adds `self.capacity()..capacity` to the free space, joined with a free range at the end.
+    pub fn grow(&mut self, capacity: u32) {
+        if capacity <= self.capacity {
+            return;
+        }
+
+        let added = self.capacity..capacity;
+        self.capacity = capacity;
+        self.free(added);
+    }
+
+    // None if no free range is large enough, even if enough space is free in total.
+    pub fn allocate(&mut self, size: u32) -> Option<Range<u32>> {
+        if size == 0 {
+            return Some(0..0);
+        }
+
+        let index = self
+            .free
+            .iter()
+            .position(|range| range.end - range.start >= size)?;
+        let range = &mut self.free[index];
+        let allocated = range.start..range.start + size;
+        range.start += size;
+        if range.start == range.end {
+            self.free.remove(index);
+        }
+
+        Some(allocated)
+    }
+
+    // `range` has to come from `allocate` and not have been freed yet.
+    pub fn free(&mut self, range: Range<u32>) {
+        if range.is_empty() {
+            return;
+        }
+        debug_assert!(range.end <= self.capacity);
+
+        let index = self.free.partition_point(|free| free.start < range.start);
+        debug_assert!(index == 0 || self.free[index - 1].end <= range.start);
+        debug_assert!(index == self.free.len() || range.end <= self.free[index].start);
+
+        let merges_before = index > 0 && self.free[index - 1].end == range.start;
+        let merges_after = index < self.free.len() && self.free[index].start == range.end;
+        match (merges_before, merges_after) {
+            (true, true) => {
+                self.free[index - 1].end = self.free[index].end;
+                self.free.remove(index);
+            }
+            (true, false) => self.free[index - 1].end = range.end,
+            (false, true) => self.free[index].start = range.start,
+            (false, false) => self.free.insert(index, range),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn free_space(&self) -> u32 {
+        self.free.iter().map(|range| range.end - range.start).sum()
+    }
+
+    #[cfg(test)]
+    pub fn largest_free(&self) -> u32 {
+        self.free
+            .iter()
+            .map(|range| range.end - range.start)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+// A `Buffer` shared by many allocations, e.g. meshes, growing when none of its free ranges fit.
+pub struct BufferAllocator<Content>
+where
+    Content: Pod + Zeroable,
+{
+    buffer: Buffer<Content>,
+    ranges: RangeAllocator,
+}
+
+impl<Content> BufferAllocator<Content>
+where
+    Content: Pod + Zeroable,
+{
+    pub fn new(context: &WGPUContext, capacity: u32, usage: BufferUsages) -> Self {
+        Self {
+            buffer: Buffer::with_capacity(context, capacity, usage),
+            ranges: RangeAllocator::new(capacity),
+        }
+    }
+
+    // Copies `content` into a free range. None if it only fits by growing the buffer past what
+    // the device allows.
+    pub fn allocate(&mut self, context: &WGPUContext, content: &[Content]) -> Option<Range<u32>> {
+        let size = content.len() as u32;
+        let range = match self.ranges.allocate(size) {
+            Some(range) => range,
+            None => {
+                let max_capacity =
+                    context.device.limits().max_buffer_size / size_of::<Content>() as u64;
+                let capacity = self.ranges.capacity();
+                let capacity = grown_capacity(capacity, capacity.saturating_add(size))
+                    .min(max_capacity.min(u32::MAX as u64) as u32);
+
+                self.buffer.grow(context, capacity);
+                self.ranges.grow(capacity);
+                self.ranges.allocate(size)?
+            }
+        };
+
+        self.buffer.write(context, range.start, content);
+        Some(range)
+    }
+
+    pub fn free(&mut self, range: Range<u32>) {
+        self.ranges.free(range);
+    }
+
+    // Recreated when the allocator grows, so it shouldn't be held on to across allocations.
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        self.buffer.buffer()
+    }
+
+    #[cfg(test)]
+    pub fn ranges(&self) -> &RangeAllocator {
+        &self.ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_ranges_merge_and_are_reused() {
+        let mut allocator = RangeAllocator::new(100);
+        let a = allocator.allocate(30).unwrap();
+        let b = allocator.allocate(30).unwrap();
+        let c = allocator.allocate(30).unwrap();
+        assert_eq!((a.clone(), b.clone(), c.clone()), (0..30, 30..60, 60..90));
+        assert_eq!(allocator.allocate(20), None);
+
+        allocator.free(a);
+        allocator.free(c);
+        assert_eq!(allocator.free_space(), 70);
+        assert_eq!(allocator.largest_free(), 40);
+        assert_eq!(allocator.allocate(20), Some(0..20));
+
+        // Freeing the middle joins it with the free space on both sides.
+        allocator.free(b);
+        assert_eq!(allocator.largest_free(), 80);
+        assert_eq!(allocator.allocate(80), Some(20..100));
+        assert_eq!(allocator.free_space(), 0);
+    }
+
+    #[test]
+    fn fragmented_space_needs_growth_or_frees() {
+        let mut allocator = RangeAllocator::new(64);
+        let ranges: Vec<_> = (0..16).map(|_| allocator.allocate(4).unwrap()).collect();
+
+        // Every other range freed: half the space is free, but only in pieces of 4.
+        for range in ranges.iter().step_by(2) {
+            allocator.free(range.clone());
+        }
+        assert_eq!(allocator.free_space(), 32);
+        assert_eq!(allocator.largest_free(), 4);
+        assert_eq!(allocator.allocate(8), None);
+
+        // Small allocations reuse the holes from the front.
+        assert_eq!(allocator.allocate(3), Some(0..3));
+        assert_eq!(allocator.allocate(4), Some(8..12));
+
+        // Growing adds space at the end, where the large allocation then goes.
+        allocator.grow(128);
+        assert_eq!(allocator.capacity(), 128);
+        assert_eq!(allocator.allocate(8), Some(64..72));
+
+        // A free range at the old end joins the new space.
+        let mut allocator = RangeAllocator::new(16);
+        let head = allocator.allocate(10).unwrap();
+        allocator.grow(32);
+        assert_eq!(allocator.largest_free(), 22);
+        allocator.free(head);
+        assert_eq!(allocator.allocate(32), Some(0..32));
+    }
+
+    #[test]
+    fn buffers_grow_when_fragmented_and_keep_allocations() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let mut allocator = BufferAllocator::<u32>::new(&context, 16, BufferUsages::STORAGE);
+        let a = allocator.allocate(&context, &[1; 4]).unwrap();
+        let b = allocator.allocate(&context, &[2; 4]).unwrap();
+        let c = allocator.allocate(&context, &[3; 4]).unwrap();
+        allocator.free(b);
+
+        // 8 elements are free, but in two pieces of 4.
+        let before = allocator.buffer().clone();
+        let d = allocator.allocate(&context, &[4; 6]).unwrap();
+        assert_ne!(allocator.buffer(), &before);
+        assert_eq!(allocator.ranges().capacity(), 32);
+        // Joined with the free range at the old end.
+        assert_eq!(d, 12..18);
+        // The hole is still reused.
+        let e = allocator.allocate(&context, &[5; 4]).unwrap();
+        assert_eq!(e, 4..8);
+
+        let bytes = context.read_buffer(allocator.buffer());
+        let contents: &[u32] = bytemuck::cast_slice(&bytes);
+        for (range, value) in [(a, 1), (e, 5), (c, 3), (d, 4)] {
+            assert!(
+                contents[range.start as usize..range.end as usize]
+                    .iter()
+                    .all(|&element| element == value)
+            );
+        }
+    }
+}
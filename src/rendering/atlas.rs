@@ -0,0 +1,456 @@
+use crate::assets::server::Asset;
+use crate::assets::source::{AssetError, AssetSource};
+use crate::rendering::mipmap::downsample;
+use crate::rendering::texture::Texture;
+use crate::rendering::wgpu_context::WGPUContext;
+use glam::{UVec2, Vec2};
+use image::{ImageError, Rgba, RgbaImage};
+use std::collections::HashMap;
+use thiserror::Error;
+
+// Always in the atlas, and what unknown tile names resolve to.
+pub const MISSING_TILE: &str = "missing";
+const MISSING_SIZE: u32 = 16;
+const MISSING_COLORS: [Rgba<u8>; 2] = [Rgba([255, 0, 255, 255]), Rgba([0, 0, 0, 255])];
+
+#[derive(Error, Debug)]
+pub enum AtlasError {
+    #[error(transparent)]
+    Asset(#[from] AssetError),
+    #[error("Failed to decode atlas tile '{name}' due to {source:?}.")]
+    Decode { name: String, source: ImageError },
+    #[error("Atlas tile '{0}' is empty.")]
+    EmptyTile(String),
+    #[error("Atlas tiles don't fit into {0}x{0}.")]
+    TooLarge(u32),
+}
+
+// Part of the atlas in texture coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct UvRect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl UvRect {
+    pub const FULL: Self = Self {
+        min: Vec2::ZERO,
+        max: Vec2::ONE,
+    };
+
+    // Maps texture coordinates of the tile to ones of the atlas.
+    pub fn map(&self, tex_coords: [f32; 2]) -> [f32; 2] {
+        (self.min + (self.max - self.min) * Vec2::from(tex_coords)).to_array()
+    }
+}
+
+// Where each tile ended up.
+#[derive(Clone, Debug, Default)]
+pub struct AtlasUvs {
+    rects: HashMap<String, UvRect>,
+}
+
+impl AtlasUvs {
+    pub fn get(&self, name: &str) -> Option<UvRect> {
+        self.rects.get(name).copied()
+    }
+
+    // Unknown names get the missing tile, or the whole texture for an empty atlas like the
+    // placeholder.
+    pub fn uv(&self, name: &str) -> UvRect {
+        self.get(name)
+            .or_else(|| self.get(MISSING_TILE))
+            .unwrap_or(UvRect::FULL)
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.rects.len()
+    }
+}
+
+// A packed atlas on the CPU, with every mip level.
+pub struct Atlas {
+    pub levels: Vec<RgbaImage>,
+    pub uvs: AtlasUvs,
+}
+
+// Packs named images into one texture. Each tile is surrounded by `padding` texels copied from
+// its edges, so filtering near the edge doesn't pick up the neighbouring tile. Mip levels are
+// downsampled per tile for the same reason, which needs tile origins to stay whole texels at
+// every level, so tiles are aligned to the size of a texel at the smallest level. The padding
+// is raised to that size too, so it doesn't halve away to nothing on the smaller levels.
+pub struct AtlasBuilder {
+    padding: u32,
+    mip_levels: u32, // at most, fewer if the smallest tile would shrink below a texel
+    max_size: u32,
+    tiles: Vec<(String, RgbaImage)>,
+}
+
+impl Default for AtlasBuilder {
+    fn default() -> Self {
+        Self {
+            padding: 2,
+            mip_levels: 4,
+            max_size: 4096,
+            tiles: vec![],
+        }
+    }
+}
+
+impl AtlasBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    pub fn with_tile(mut self, name: &str, image: RgbaImage) -> Self {
+        self.tiles.push((name.to_owned(), image));
+
+        self
+    }
+
+    // Adds every PNG in `dir`, named after its file without the extension.
+    pub fn with_directory(mut self, source: &AssetSource, dir: &str) -> Result<Self, AtlasError> {
+        for path in source.list(dir)? {
+            let Some(name) = path
+                .rsplit_once('/')
+                .and_then(|(_, file)| file.strip_suffix(".png"))
+            else {
+                continue;
+            };
+
+            let image = image::load_from_memory(&source.read(&path)?)
+                .map_err(|source| AtlasError::Decode {
+                    name: name.to_owned(),
+                    source,
+                })?
+                .to_rgba8();
+            self.tiles.push((name.to_owned(), image));
+        }
+
+        Ok(self)
+    }
+
+    pub fn build(mut self) -> Result<Atlas, AtlasError> {
+        if !self.tiles.iter().any(|(name, _)| name == MISSING_TILE) {
+            let missing = RgbaImage::from_fn(MISSING_SIZE, MISSING_SIZE, |x, y| {
+                MISSING_COLORS[((x / 4 + y / 4) % 2) as usize]
+            });
+            self.tiles.push((MISSING_TILE.to_owned(), missing));
+        }
+        if let Some((name, _)) = self
+            .tiles
+            .iter()
+            .find(|(_, image)| image.width() == 0 || image.height() == 0)
+        {
+            return Err(AtlasError::EmptyTile(name.clone()));
+        }
+
+        // Every tile has to be at least a texel at the smallest level.
+        let smallest = self
+            .tiles
+            .iter()
+            .map(|(_, image)| image.width().min(image.height()))
+            .min()
+            .unwrap();
+        let levels = self.mip_levels.min(smallest.ilog2() + 1);
+        let align = 1 << (levels - 1);
+        let padding = self.padding.max(align);
+
+        // Tallest first, so shelves waste little space.
+        self.tiles.sort_by(|(a_name, a), (b_name, b)| {
+            b.height().cmp(&a.height()).then_with(|| a_name.cmp(b_name))
+        });
+        let sizes: Vec<UVec2> = self
+            .tiles
+            .iter()
+            .map(|(_, image)| UVec2::new(image.width(), image.height()))
+            .collect();
+        let (size, origins) = pack(&sizes, padding, align, self.max_size)
+            .ok_or(AtlasError::TooLarge(self.max_size))?;
+
+        let mut uvs = AtlasUvs::default();
+        for ((name, _), (&origin, &tile_size)) in self.tiles.iter().zip(origins.iter().zip(&sizes))
+        {
+            uvs.rects.insert(
+                name.clone(),
+                UvRect {
+                    min: origin.as_vec2() / size.as_vec2(),
+                    max: (origin + tile_size).as_vec2() / size.as_vec2(),
+                },
+            );
+        }
+
+        let mut tiles: Vec<RgbaImage> = self.tiles.into_iter().map(|(_, image)| image).collect();
+        let mut images = vec![];
+        for level in 0..levels {
+            if level > 0 {
+                tiles = tiles.iter().map(downsample).collect();
+            }
+
+            let mut image = RgbaImage::new(size.x >> level, size.y >> level);
+            for (tile, origin) in tiles.iter().zip(&origins) {
+                blit_padded(&mut image, tile, *origin >> level, padding >> level);
+            }
+            images.push(image);
+        }
+
+        Ok(Atlas {
+            levels: images,
+            uvs,
+        })
+    }
+}
+
+// Shelf packing into the narrowest power of two width that also keeps the atlas at most as tall
+// as it is wide. Returns the atlas size and the origin of each tile.
+fn pack(sizes: &[UVec2], padding: u32, align: u32, max_size: u32) -> Option<(UVec2, Vec<UVec2>)> {
+    let slot_area: u32 = sizes
+        .iter()
+        .map(|size| (size.x + 2 * padding) * (size.y + 2 * padding))
+        .sum();
+    let mut width = (slot_area.isqrt().next_power_of_two()).max(align);
+
+    while width <= max_size {
+        if let Some((height, origins)) = pack_shelves(sizes, padding, align, width)
+            && height <= width
+        {
+            return Some((UVec2::new(width, height), origins));
+        }
+        width *= 2;
+    }
+
+    None
+}
+
+fn pack_shelves(
+    sizes: &[UVec2],
+    padding: u32,
+    align: u32,
+    width: u32,
+) -> Option<(u32, Vec<UVec2>)> {
+    let start = padding.next_multiple_of(align);
+    let mut cursor = UVec2::splat(start);
+    let mut shelf_height = 0;
+
+    let mut origins = vec![];
+    for size in sizes {
+        if cursor.x + size.x + padding > width {
+            cursor.x = start;
+            cursor.y = (cursor.y + shelf_height + 2 * padding).next_multiple_of(align);
+            shelf_height = 0;
+        }
+        if cursor.x + size.x + padding > width {
+            return None;
+        }
+
+        origins.push(cursor);
+        cursor.x = (cursor.x + size.x + 2 * padding).next_multiple_of(align);
+        shelf_height = shelf_height.max(size.y);
+    }
+
+    let height = (cursor.y + shelf_height + padding).next_multiple_of(align);
+    Some((height, origins))
+}
+
+// Copies `tile` to `origin`, extending its edge texels `padding` texels outwards.
+fn blit_padded(atlas: &mut RgbaImage, tile: &RgbaImage, origin: UVec2, padding: u32) {
+    let padding = padding as i64;
+    for y in -padding..tile.height() as i64 + padding {
+        for x in -padding..tile.width() as i64 + padding {
+            let atlas_x = origin.x as i64 + x;
+            let atlas_y = origin.y as i64 + y;
+            if atlas_x < 0
+                || atlas_y < 0
+                || atlas_x >= atlas.width() as i64
+                || atlas_y >= atlas.height() as i64
+            {
+                continue;
+            }
+
+            let tile_x = x.clamp(0, tile.width() as i64 - 1) as u32;
+            let tile_y = y.clamp(0, tile.height() as i64 - 1) as u32;
+            atlas.put_pixel(
+                atlas_x as u32,
+                atlas_y as u32,
+                *tile.get_pixel(tile_x, tile_y),
+            );
+        }
+    }
+}
+
+// The packed block textures on the GPU. Loaded from a directory of PNGs, see
+// `AtlasBuilder::with_directory`.
+pub struct TextureAtlas {
+    pub texture: Texture,
+    pub uvs: AtlasUvs,
+}
+
+impl Asset for TextureAtlas {
+    type Decoded = Atlas;
+    type Error = AtlasError;
+
+    fn decode(source: &AssetSource, path: &str) -> Result<Atlas, AtlasError> {
+        AtlasBuilder::new().with_directory(source, path)?.build()
+    }
+
+    fn create(context: &WGPUContext, path: &str, atlas: Atlas) -> Result<Self, AtlasError> {
+        Ok(Self {
+            texture: context.create_texture_from_mips(path, &atlas.levels),
+            uvs: atlas.uvs,
+        })
+    }
+
+    // Has no tiles, so every lookup covers the whole placeholder texture.
+    fn placeholder(context: &WGPUContext) -> Self {
+        Self {
+            texture: <Texture as Asset>::placeholder(context),
+            uvs: AtlasUvs::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::IVec2;
+
+    fn solid(width: u32, height: u32, color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(width, height, Rgba(color))
+    }
+
+    fn texel(atlas: &Atlas, level: usize, uv: Vec2) -> Rgba<u8> {
+        let image = &atlas.levels[level];
+        let pos = uv * Vec2::new(image.width() as f32, image.height() as f32);
+        *image.get_pixel(pos.x as u32, pos.y as u32)
+    }
+
+    #[test]
+    fn tiles_are_packed_without_overlap() {
+        let mut builder = AtlasBuilder {
+            padding: 1,
+            ..AtlasBuilder::default()
+        };
+        for i in 0..10 {
+            builder = builder.with_tile(&format!("tile{i}"), solid(8 + i % 3 * 4, 8, [i as u8; 4]));
+        }
+        let atlas = builder.build().unwrap();
+
+        assert_eq!(atlas.uvs.len(), 11); // including the missing tile
+        let rects: Vec<UvRect> = (0..10).map(|i| atlas.uvs.uv(&format!("tile{i}"))).collect();
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.min.cmpge(Vec2::ZERO).all() && a.max.cmple(Vec2::ONE).all());
+            for b in &rects[i + 1..] {
+                let overlaps = a.min.cmplt(b.max).all() && b.min.cmplt(a.max).all();
+                assert!(!overlaps, "{a:?} overlaps {b:?}");
+            }
+        }
+
+        // Each tile shows its own color in its center.
+        for (i, rect) in rects.iter().enumerate() {
+            assert_eq!(
+                texel(&atlas, 0, (rect.min + rect.max) / 2.0),
+                Rgba([i as u8; 4])
+            );
+        }
+    }
+
+    #[test]
+    fn padding_repeats_tile_edges() {
+        let atlas = AtlasBuilder {
+            padding: 2,
+            ..AtlasBuilder::default()
+        }
+        .with_tile("red", solid(4, 4, [255, 0, 0, 255]))
+        .build()
+        .unwrap();
+
+        let rect = atlas.uvs.uv("red");
+        let image = &atlas.levels[0];
+        let origin = (rect.min * Vec2::new(image.width() as f32, image.height() as f32)).as_uvec2();
+        for offset in [1, 2] {
+            assert_eq!(
+                image.get_pixel(origin.x - offset, origin.y),
+                &Rgba([255, 0, 0, 255])
+            );
+            assert_eq!(
+                image.get_pixel(origin.x + 3 + offset, origin.y + 3),
+                &Rgba([255, 0, 0, 255])
+            );
+        }
+    }
+
+    #[test]
+    fn mip_levels_are_downsampled_per_tile() {
+        let checker = RgbaImage::from_fn(8, 8, |x, _| {
+            if x % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([200, 200, 200, 255])
+            }
+        });
+        let atlas = AtlasBuilder {
+            mip_levels: 10,
+            ..AtlasBuilder::default()
+        }
+        .with_tile("checker", checker)
+        .with_tile("tiny", solid(4, 4, [0, 0, 255, 255]))
+        .build()
+        .unwrap();
+
+        // Limited by the 4x4 tile, which is a single texel at the last level.
+        assert_eq!(atlas.levels.len(), 3);
+        for (level, image) in atlas.levels.iter().enumerate() {
+            assert_eq!(image.width(), atlas.levels[0].width() >> level);
+        }
+
+        // Averaged in linear space, so brighter than the byte halfway between.
+        let rect = atlas.uvs.uv("checker");
+        assert_eq!(texel(&atlas, 1, rect.min), Rgba([146, 146, 146, 255]));
+        assert_eq!(
+            texel(&atlas, 2, atlas.uvs.uv("tiny").min),
+            Rgba([0, 0, 255, 255])
+        );
+    }
+
+    #[test]
+    fn the_smallest_mip_keeps_padding() {
+        let color = Rgba([0, 255, 0, 255]);
+        let atlas = AtlasBuilder::new()
+            .with_tile("green", solid(8, 8, color.0))
+            .build()
+            .unwrap();
+
+        // The 8x8 tile is a single texel at the last of the 4 levels.
+        assert_eq!(atlas.levels.len(), 4);
+        let image = atlas.levels.last().unwrap();
+        let origin = (atlas.uvs.uv("green").min
+            * Vec2::new(image.width() as f32, image.height() as f32))
+        .as_uvec2();
+        assert_eq!(image.get_pixel(origin.x, origin.y), &color);
+        for (x, y) in [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, 1)] {
+            let border = origin.as_ivec2() + IVec2::new(x, y);
+            assert_eq!(image.get_pixel(border.x as u32, border.y as u32), &color);
+        }
+    }
+
+    #[test]
+    fn unknown_tiles_resolve_to_the_missing_tile() {
+        let atlas = AtlasBuilder::new().build().unwrap();
+        assert_eq!(atlas.uvs.uv("nonexistent"), atlas.uvs.uv(MISSING_TILE));
+        assert_eq!(AtlasUvs::default().uv("nonexistent"), UvRect::FULL);
+    }
+
+    #[test]
+    fn oversized_atlases_are_errors() {
+        let result = AtlasBuilder {
+            max_size: 32,
+            ..AtlasBuilder::default()
+        }
+        .with_tile("big", solid(64, 64, [0; 4]))
+        .build();
+
+        assert!(matches!(result, Err(AtlasError::TooLarge(32))));
+    }
+}
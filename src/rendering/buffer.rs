@@ -3,9 +3,17 @@ use bytemuck::{Pod, Zeroable, cast_slice};
 use std::any::type_name;
 use std::marker::PhantomData;
 use wgpu::util::{BufferInitDescriptor, DeviceExt};
-use wgpu::{BindingResource, BufferUsages};
+use wgpu::{BufferDescriptor, BufferUsages};
 
-// A generic wgpu buffer implementation.
+const MIN_CAPACITY: u32 = 16;
+
+// How many elements a buffer holding `capacity` should be recreated with to fit `required`:
+// double, unless that's still too small, so repeated growth stays amortized constant.
+pub fn grown_capacity(capacity: u32, required: u32) -> u32 {
+    capacity.saturating_mul(2).max(required).max(MIN_CAPACITY)
+}
+
+// A generic wgpu buffer implementation, recreated larger when uploads don't fit.
 // Inspired by https://github.com/Wumpf/blub/blob/master/src/wgpu_utils/uniformbuffer.rs.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Buffer<Content>
@@ -14,7 +22,8 @@ where
 {
     buffer: wgpu::Buffer,
     _content_type: PhantomData<Content>,
-    len: u32,
+    len: u32,      // elements last uploaded
+    capacity: u32, // elements the buffer has room for
 }
 impl<Content> Buffer<Content>
 where
@@ -33,7 +42,7 @@ where
         };
 
         let buffer = context.device.create_buffer_init(&BufferInitDescriptor {
-            label: Some(&format!("Uniform Buffer: {:?}", Self::name())),
+            label: Some(&Self::label()),
             contents: cast_slice(contents),
             usage,
         });
@@ -42,9 +51,38 @@ where
             buffer,
             _content_type: PhantomData,
             len: contents.len() as u32,
+            capacity: contents.len() as u32,
         }
     }
 
+    // An empty buffer with room for `capacity` elements, for filling piecewise with `write`. It can
+    // be copied from and to, which `grow` relies on.
+    pub fn with_capacity(context: &WGPUContext, capacity: u32, usage: BufferUsages) -> Self {
+        Self {
+            buffer: Self::create(
+                context,
+                capacity,
+                usage | BufferUsages::COPY_SRC | BufferUsages::COPY_DST,
+            ),
+            _content_type: PhantomData,
+            len: 0,
+            capacity,
+        }
+    }
+
+    fn create(context: &WGPUContext, capacity: u32, usage: BufferUsages) -> wgpu::Buffer {
+        context.device.create_buffer(&BufferDescriptor {
+            label: Some(&Self::label()),
+            size: capacity as u64 * size_of::<Content>() as u64,
+            usage,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn label() -> String {
+        format!("Buffer: {:?}", Self::name())
+    }
+
     pub fn new_uniform(context: &WGPUContext, content: Option<&[Content]>) -> Self {
         Self::new(
             context,
@@ -69,10 +107,48 @@ where
         Self::new(context, content, BufferUsages::INDEX)
     }
 
-    pub fn upload(&mut self, context: &WGPUContext, content: &[Content]) {
+    // Replaces the contents. If they don't fit, the buffer is recreated with more room first and
+    // true is returned, since bind groups using the old buffer then have to be rebuilt.
+    pub fn upload(&mut self, context: &WGPUContext, content: &[Content]) -> bool {
+        let required = content.len() as u32;
+        let recreated = required > self.capacity;
+        if recreated {
+            self.capacity = grown_capacity(self.capacity, required);
+            self.buffer = Self::create(context, self.capacity, self.buffer.usage());
+        }
+
         context
             .queue
             .write_buffer(&self.buffer, 0, cast_slice(content));
+        self.len = required;
+
+        recreated
+    }
+
+    // Overwrites elements starting at `offset`, which have to be within the capacity.
+    pub fn write(&self, context: &WGPUContext, offset: u32, content: &[Content]) {
+        debug_assert!(offset as usize + content.len() <= self.capacity as usize);
+        context.queue.write_buffer(
+            &self.buffer,
+            offset as u64 * size_of::<Content>() as u64,
+            cast_slice(content),
+        );
+    }
+
+    // Recreates the buffer with room for `capacity` elements, keeping its contents. Only for
+    // buffers created `with_capacity`. Writes queued before are copied along.
+    pub fn grow(&mut self, context: &WGPUContext, capacity: u32) {
+        if capacity <= self.capacity {
+            return;
+        }
+
+        let buffer = Self::create(context, capacity, self.buffer.usage());
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(&self.buffer, 0, &buffer, 0, self.buffer.size());
+        context.queue.submit([encoder.finish()]);
+
+        self.buffer = buffer;
+        self.capacity = capacity;
     }
 
     pub fn buffer(&self) -> &wgpu::Buffer {
@@ -82,4 +158,74 @@ where
     pub fn len(&self) -> u32 {
         self.len
     }
+
+    #[cfg(test)]
+    pub fn capacity(&self) -> u32 {
+        self.capacity
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capacity_doubles_unless_more_is_required() {
+        assert_eq!(grown_capacity(0, 1), MIN_CAPACITY);
+        assert_eq!(grown_capacity(100, 101), 200);
+        assert_eq!(grown_capacity(100, 500), 500);
+
+        // Uploading one more element at a time recreates the buffer only a handful of times.
+        let mut capacity = 0;
+        let mut recreated = 0;
+        for len in 1..=10_000 {
+            if len > capacity {
+                capacity = grown_capacity(capacity, len);
+                recreated += 1;
+            }
+        }
+        assert_eq!((capacity, recreated), (16_384, 11));
+        assert_eq!(grown_capacity(u32::MAX - 1, u32::MAX), u32::MAX);
+    }
+
+    fn read(context: &WGPUContext, buffer: &Buffer<u32>) -> Vec<u32> {
+        cast_slice(&context.read_buffer(buffer.buffer())).to_vec()
+    }
+
+    #[test]
+    fn growing_keeps_the_contents() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let mut buffer = Buffer::<u32>::with_capacity(&context, 4, BufferUsages::STORAGE);
+        buffer.write(&context, 0, &[1, 2, 3, 4]);
+
+        let before = buffer.buffer().clone();
+        buffer.grow(&context, 8);
+        assert_ne!(buffer.buffer(), &before);
+        assert_eq!(buffer.capacity(), 8);
+        buffer.write(&context, 4, &[5, 6, 7, 8]);
+
+        assert_eq!(read(&context, &buffer), [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn uploads_that_dont_fit_recreate_the_buffer() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let usage = BufferUsages::STORAGE | BufferUsages::COPY_DST | BufferUsages::COPY_SRC;
+        let mut buffer = Buffer::new(&context, Some(&[1u32, 2]), usage);
+
+        let before = buffer.buffer().clone();
+        assert!(!buffer.upload(&context, &[3, 4]));
+        assert_eq!(buffer.buffer(), &before);
+
+        // The old buffer is left as it was, so whatever bound it has to be rebuilt.
+        assert!(buffer.upload(&context, &[5, 6, 7]));
+        assert_ne!(buffer.buffer(), &before);
+        assert_eq!((buffer.len(), buffer.capacity()), (3, MIN_CAPACITY));
+        assert_eq!(read(&context, &buffer)[..3], [5, 6, 7]);
+        assert_eq!(cast_slice::<u8, u32>(&context.read_buffer(&before)), [3, 4]);
+    }
 }
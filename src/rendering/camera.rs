@@ -1,13 +1,6 @@
+use crate::rendering::frustum::Frustum;
 use bytemuck::{Pod, Zeroable};
-use glam::{Mat4, Vec3, Vec4};
-
-#[rustfmt::skip]
-pub const OPENGL_TO_WGPU_MATRIX: Mat4 = Mat4::from_cols(
-    Vec4::new(1.0, 0.0, 0.0, 0.0),
-    Vec4::new(0.0, 1.0, 0.0, 0.0),
-    Vec4::new(0.0, 0.0, 0.5, 0.0),
-    Vec4::new(0.0, 0.0, 0.5, 1.0),
-);
+use glam::{Mat4, Vec3};
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -28,9 +21,14 @@ pub struct Camera {
 impl Camera {
     pub fn build_view_projection_matrix(&self) -> Mat4 {
         let view = Mat4::look_at_rh(self.eye, self.target, self.up);
+        // Already maps depth to wgpu's 0 to 1 range.
         let proj = Mat4::perspective_rh(self.fov, self.aspect, self.near_clip, self.far_clip);
 
-        OPENGL_TO_WGPU_MATRIX * proj * view
+        proj * view
+    }
+
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(&self.build_view_projection_matrix())
     }
 
     pub fn fill_buffer_context(&self) -> CameraBufferContext {
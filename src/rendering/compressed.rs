@@ -0,0 +1,722 @@
+use crate::rendering::mipmap::mip_level_count;
+use crate::rendering::wgpu_context::CreateTextureError;
+use image::{Rgba, RgbaImage};
+use wgpu::{AstcBlock, AstcChannel, Extent3d, Features, TextureDimension, TextureFormat};
+
+// Requested from the adapter whenever it has them, so KTX2 textures can stay compressed in VRAM.
+pub const TEXTURE_COMPRESSION_FEATURES: Features = Features::TEXTURE_COMPRESSION_BC
+    .union(Features::TEXTURE_COMPRESSION_ETC2)
+    .union(Features::TEXTURE_COMPRESSION_ASTC);
+
+// In the order of the KTX2 (Vulkan) format numbers, which have an UNORM and SRGB variant each.
+const ASTC_BLOCKS: [AstcBlock; 14] = [
+    AstcBlock::B4x4,
+    AstcBlock::B5x4,
+    AstcBlock::B5x5,
+    AstcBlock::B6x5,
+    AstcBlock::B6x6,
+    AstcBlock::B8x5,
+    AstcBlock::B8x6,
+    AstcBlock::B8x8,
+    AstcBlock::B10x5,
+    AstcBlock::B10x6,
+    AstcBlock::B10x8,
+    AstcBlock::B10x10,
+    AstcBlock::B12x10,
+    AstcBlock::B12x12,
+];
+
+// Decodes one 4x4 block into its texels, row by row.
+type BlockDecoder = fn(&[u8]) -> [Rgba<u8>; 16];
+
+// A texture from a KTX2 container, with its mip levels as they're stored in the file.
+#[derive(Debug)]
+pub struct CompressedImage {
+    pub format: TextureFormat,
+    pub width: u32,
+    pub height: u32,
+    pub levels: Vec<Vec<u8>>, // tightly packed blocks, largest level first
+}
+
+impl CompressedImage {
+    // Only plain 2D textures are supported, without Basis Universal or other supercompression.
+    // Formats `decompress` can't handle (BC4/BC5 SNORM, BC6H, BC7, ETC2 RGB8A1 and ASTC) are
+    // only accepted when `features` has them.
+    pub fn from_ktx2(bytes: &[u8], features: Features) -> Result<Self, CreateTextureError> {
+        let reader = ktx2::Reader::new(bytes)?;
+        let header = reader.header();
+
+        if let Some(scheme) = header.supercompression_scheme {
+            return Err(CreateTextureError::UnsupportedSupercompression(format!(
+                "{scheme:?}"
+            )));
+        }
+        if header.pixel_height == 0
+            || header.pixel_depth > 1
+            || header.layer_count > 1
+            || header.face_count != 1
+        {
+            return Err(CreateTextureError::UnsupportedLayout(format!(
+                "{}x{}x{} with {} layers and {} faces",
+                header.pixel_width,
+                header.pixel_height,
+                header.pixel_depth,
+                header.layer_count,
+                header.face_count
+            )));
+        }
+
+        // No format means the data is described by the DFD alone, which is how Basis Universal
+        // files look.
+        let format = header
+            .format
+            .ok_or_else(|| CreateTextureError::UnsupportedFormat("undefined".to_owned()))?;
+        let format = wgpu_format(format)
+            .ok_or_else(|| CreateTextureError::UnsupportedFormat(format!("{format:?}")))?;
+        if !features.contains(format.required_features()) && block_decoder(format).is_none() {
+            return Err(CreateTextureError::NoFallback(format));
+        }
+
+        let (width, height) = (header.pixel_width, header.pixel_height);
+        let (block_width, block_height) = format.block_dimensions();
+        if width % block_width != 0 || height % block_height != 0 {
+            return Err(CreateTextureError::UnsupportedLayout(format!(
+                "{width}x{height}, which isn't a multiple of the {block_width}x{block_height} blocks"
+            )));
+        }
+        if reader.levels().len() as u32 > mip_level_count(width, height) {
+            return Err(CreateTextureError::UnsupportedLayout(format!(
+                "{} mip levels for {width}x{height}",
+                reader.levels().len()
+            )));
+        }
+
+        let mut levels = vec![];
+        for (level, data) in reader.levels().enumerate() {
+            let expected = level_byte_size(format, width, height, level as u32);
+            if data.data.len() != expected {
+                return Err(CreateTextureError::LevelSizeMismatch {
+                    level: level as u32,
+                    expected,
+                    found: data.data.len(),
+                });
+            }
+            levels.push(data.data.to_vec());
+        }
+
+        Ok(Self {
+            format,
+            width,
+            height,
+            levels,
+        })
+    }
+
+    // Whether the levels can be uploaded as they are, otherwise they have to be decompressed.
+    pub fn is_supported(&self, features: Features) -> bool {
+        features.contains(self.format.required_features())
+    }
+
+    // RGBA8 in the same color space, for devices without the format's feature. Only the formats
+    // `block_decoder` knows can be decompressed.
+    pub fn decompress(&self) -> Result<(TextureFormat, Vec<RgbaImage>), CreateTextureError> {
+        let (decode, block_size) =
+            block_decoder(self.format).ok_or(CreateTextureError::NoFallback(self.format))?;
+
+        let levels = self
+            .levels
+            .iter()
+            .enumerate()
+            .map(|(level, data)| {
+                let width = (self.width >> level).max(1);
+                let height = (self.height >> level).max(1);
+                let blocks_wide = width.div_ceil(4) as usize;
+
+                let mut image = RgbaImage::new(width, height);
+                for (index, block) in data.chunks_exact(block_size).enumerate() {
+                    let (block_x, block_y) = ((index % blocks_wide) * 4, (index / blocks_wide) * 4);
+                    for (texel, color) in decode(block).into_iter().enumerate() {
+                        let x = (block_x + texel % 4) as u32;
+                        let y = (block_y + texel / 4) as u32;
+                        if x < width && y < height {
+                            image.put_pixel(x, y, color);
+                        }
+                    }
+                }
+
+                image
+            })
+            .collect();
+
+        let format = if self.format.is_srgb() {
+            TextureFormat::Rgba8UnormSrgb
+        } else {
+            TextureFormat::Rgba8Unorm
+        };
+
+        Ok((format, levels))
+    }
+}
+
+// Bytes of a tightly packed mip level, rounded up to whole blocks.
+pub fn level_byte_size(format: TextureFormat, width: u32, height: u32, level: u32) -> usize {
+    let size = Extent3d {
+        width,
+        height,
+        depth_or_array_layers: 1,
+    }
+    .mip_level_size(level, TextureDimension::D2)
+    .physical_size(format);
+    let (block_width, block_height) = format.block_dimensions();
+    let block_size = format.block_copy_size(None).unwrap_or(0);
+
+    ((size.width / block_width) * (size.height / block_height) * block_size) as usize
+}
+
+fn wgpu_format(format: ktx2::Format) -> Option<TextureFormat> {
+    use ktx2::Format as F;
+
+    let format = match format {
+        F::R8G8B8A8_UNORM => TextureFormat::Rgba8Unorm,
+        F::R8G8B8A8_SRGB => TextureFormat::Rgba8UnormSrgb,
+        // BC1 without alpha decodes the same, apart from the rarely used transparent texels.
+        F::BC1_RGB_UNORM_BLOCK | F::BC1_RGBA_UNORM_BLOCK => TextureFormat::Bc1RgbaUnorm,
+        F::BC1_RGB_SRGB_BLOCK | F::BC1_RGBA_SRGB_BLOCK => TextureFormat::Bc1RgbaUnormSrgb,
+        F::BC2_UNORM_BLOCK => TextureFormat::Bc2RgbaUnorm,
+        F::BC2_SRGB_BLOCK => TextureFormat::Bc2RgbaUnormSrgb,
+        F::BC3_UNORM_BLOCK => TextureFormat::Bc3RgbaUnorm,
+        F::BC3_SRGB_BLOCK => TextureFormat::Bc3RgbaUnormSrgb,
+        F::BC4_UNORM_BLOCK => TextureFormat::Bc4RUnorm,
+        F::BC4_SNORM_BLOCK => TextureFormat::Bc4RSnorm,
+        F::BC5_UNORM_BLOCK => TextureFormat::Bc5RgUnorm,
+        F::BC5_SNORM_BLOCK => TextureFormat::Bc5RgSnorm,
+        F::BC6H_UFLOAT_BLOCK => TextureFormat::Bc6hRgbUfloat,
+        F::BC6H_SFLOAT_BLOCK => TextureFormat::Bc6hRgbFloat,
+        F::BC7_UNORM_BLOCK => TextureFormat::Bc7RgbaUnorm,
+        F::BC7_SRGB_BLOCK => TextureFormat::Bc7RgbaUnormSrgb,
+        F::ETC2_R8G8B8_UNORM_BLOCK => TextureFormat::Etc2Rgb8Unorm,
+        F::ETC2_R8G8B8_SRGB_BLOCK => TextureFormat::Etc2Rgb8UnormSrgb,
+        F::ETC2_R8G8B8A1_UNORM_BLOCK => TextureFormat::Etc2Rgb8A1Unorm,
+        F::ETC2_R8G8B8A1_SRGB_BLOCK => TextureFormat::Etc2Rgb8A1UnormSrgb,
+        F::ETC2_R8G8B8A8_UNORM_BLOCK => TextureFormat::Etc2Rgba8Unorm,
+        F::ETC2_R8G8B8A8_SRGB_BLOCK => TextureFormat::Etc2Rgba8UnormSrgb,
+        format => {
+            let index = format
+                .value()
+                .checked_sub(F::ASTC_4x4_UNORM_BLOCK.value())?;
+            let block = *ASTC_BLOCKS.get(index as usize / 2)?;
+            let channel = if index % 2 == 0 {
+                AstcChannel::Unorm
+            } else {
+                AstcChannel::UnormSrgb
+            };
+
+            TextureFormat::Astc { block, channel }
+        }
+    };
+
+    Some(format)
+}
+
+// The decoder and block size in bytes for formats that can be decompressed on the CPU, which
+// are BC1-5 and ETC2 RGB8/RGBA8.
+fn block_decoder(format: TextureFormat) -> Option<(BlockDecoder, usize)> {
+    Some(match format {
+        TextureFormat::Bc1RgbaUnorm | TextureFormat::Bc1RgbaUnormSrgb => (decode_bc1, 8),
+        TextureFormat::Bc2RgbaUnorm | TextureFormat::Bc2RgbaUnormSrgb => (decode_bc2, 16),
+        TextureFormat::Bc3RgbaUnorm | TextureFormat::Bc3RgbaUnormSrgb => (decode_bc3, 16),
+        TextureFormat::Bc4RUnorm => (decode_bc4, 8),
+        TextureFormat::Bc5RgUnorm => (decode_bc5, 16),
+        TextureFormat::Etc2Rgb8Unorm | TextureFormat::Etc2Rgb8UnormSrgb => (decode_etc2_rgb, 8),
+        TextureFormat::Etc2Rgba8Unorm | TextureFormat::Etc2Rgba8UnormSrgb => (decode_etc2_rgba, 16),
+        _ => return None,
+    })
+}
+
+fn decode_bc1(block: &[u8]) -> [Rgba<u8>; 16] {
+    decode_bc1_color(block, false)
+}
+
+// BC2 and BC3 always use the four color mode for their color block.
+fn decode_bc1_color(block: &[u8], four_colors: bool) -> [Rgba<u8>; 16] {
+    let color0 = u16::from_le_bytes([block[0], block[1]]);
+    let color1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(color0), rgb565(color1));
+
+    let palette = if color0 > color1 || four_colors {
+        [a, b, mix(a, b, 2, 1), mix(a, b, 1, 2)]
+    } else {
+        [a, b, mix(a, b, 1, 1), Rgba([0, 0, 0, 0])]
+    };
+
+    let indices = u32::from_le_bytes(block[4..8].try_into().unwrap());
+    std::array::from_fn(|texel| palette[(indices >> (2 * texel)) as usize & 3])
+}
+
+fn decode_bc2(block: &[u8]) -> [Rgba<u8>; 16] {
+    let alpha = u64::from_le_bytes(block[..8].try_into().unwrap());
+    let mut texels = decode_bc1_color(&block[8..], true);
+    for (texel, color) in texels.iter_mut().enumerate() {
+        color[3] = ((alpha >> (4 * texel)) & 15) as u8 * 17;
+    }
+
+    texels
+}
+
+fn decode_bc3(block: &[u8]) -> [Rgba<u8>; 16] {
+    let alpha = decode_bc3_channel(&block[..8]);
+    let mut texels = decode_bc1_color(&block[8..], true);
+    for (color, alpha) in texels.iter_mut().zip(alpha) {
+        color[3] = alpha;
+    }
+
+    texels
+}
+
+fn decode_bc4(block: &[u8]) -> [Rgba<u8>; 16] {
+    decode_bc3_channel(block).map(|red| Rgba([red, 0, 0, 255]))
+}
+
+fn decode_bc5(block: &[u8]) -> [Rgba<u8>; 16] {
+    let red = decode_bc3_channel(&block[..8]);
+    let green = decode_bc3_channel(&block[8..]);
+
+    std::array::from_fn(|texel| Rgba([red[texel], green[texel], 0, 255]))
+}
+
+// The interpolated single channel block used for BC3 alpha and BC4/BC5.
+fn decode_bc3_channel(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (block[0] as u32, block[1] as u32);
+    let palette: [u8; 8] = std::array::from_fn(|index| {
+        let index = index as u32;
+        (match index {
+            0 => a0,
+            1 => a1,
+            _ if a0 > a1 => ((8 - index) * a0 + (index - 1) * a1) / 7,
+            6 => 0,
+            7 => 255,
+            _ => ((6 - index) * a0 + (index - 1) * a1) / 5,
+        }) as u8
+    });
+
+    let mut bytes = [0; 8];
+    bytes[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bytes);
+    std::array::from_fn(|texel| palette[(indices >> (3 * texel)) as usize & 7])
+}
+
+fn rgb565(color: u16) -> Rgba<u8> {
+    let (r, g, b) = ((color >> 11) & 31, (color >> 5) & 63, color & 31);
+
+    Rgba([
+        extend(r as i32, 5),
+        extend(g as i32, 6),
+        extend(b as i32, 5),
+        255,
+    ])
+}
+
+// (weight_a * a + weight_b * b) / (weight_a + weight_b), opaque.
+fn mix(a: Rgba<u8>, b: Rgba<u8>, weight_a: u32, weight_b: u32) -> Rgba<u8> {
+    let channel = |index: usize| {
+        ((a[index] as u32 * weight_a + b[index] as u32 * weight_b) / (weight_a + weight_b)) as u8
+    };
+
+    Rgba([channel(0), channel(1), channel(2), 255])
+}
+
+// Repeats the high bits of an n bit value to fill 8 bits.
+fn extend(value: i32, bits: u32) -> u8 {
+    ((value << (8 - bits)) | (value >> (2 * bits as i32 - 8).max(0))) as u8
+}
+
+const ETC1_MODIFIERS: [[i32; 2]; 8] = [
+    [2, 8],
+    [5, 17],
+    [9, 29],
+    [13, 42],
+    [18, 60],
+    [24, 80],
+    [33, 106],
+    [47, 183],
+];
+
+const ETC2_DISTANCES: [i32; 8] = [3, 6, 11, 16, 23, 32, 41, 64];
+
+const EAC_MODIFIERS: [[i32; 8]; 16] = [
+    [-3, -6, -9, -15, 2, 5, 8, 14],
+    [-3, -7, -10, -13, 2, 6, 9, 12],
+    [-2, -5, -8, -13, 1, 4, 7, 12],
+    [-2, -4, -6, -13, 1, 3, 5, 12],
+    [-3, -6, -8, -12, 2, 5, 7, 11],
+    [-3, -7, -9, -11, 2, 6, 8, 10],
+    [-4, -7, -8, -11, 3, 6, 7, 10],
+    [-3, -5, -8, -11, 2, 4, 7, 10],
+    [-2, -6, -8, -10, 1, 5, 7, 9],
+    [-2, -5, -8, -10, 1, 4, 7, 9],
+    [-2, -4, -8, -10, 1, 3, 7, 9],
+    [-2, -5, -7, -10, 1, 4, 6, 9],
+    [-3, -4, -7, -10, 2, 3, 6, 9],
+    [-1, -2, -3, -10, 0, 1, 2, 9],
+    [-4, -6, -8, -9, 3, 5, 7, 8],
+    [-3, -5, -7, -9, 2, 4, 6, 8],
+];
+
+// `len` bits of the big endian ETC block, starting at bit `shift` from the least significant.
+fn bits(block: u64, shift: u32, len: u32) -> i32 {
+    ((block >> shift) & ((1 << len) - 1)) as i32
+}
+
+fn rgb(color: [i32; 3]) -> Rgba<u8> {
+    let [r, g, b] = color.map(|channel| channel.clamp(0, 255) as u8);
+
+    Rgba([r, g, b, 255])
+}
+
+fn decode_etc2_rgb(block: &[u8]) -> [Rgba<u8>; 16] {
+    let block = u64::from_be_bytes(block[..8].try_into().unwrap());
+
+    // ETC texels are stored column by column, as an MSB and an LSB plane.
+    let index = |texel: usize| {
+        let i = (texel % 4 * 4 + texel / 4) as u32;
+        (bits(block, 16 + i, 1) << 1 | bits(block, i, 1)) as usize
+    };
+
+    if bits(block, 33, 1) == 0 {
+        let base =
+            |shift: u32| [60, 52, 44].map(|channel| extend(bits(block, channel - shift, 4), 4));
+        return decode_etc1_subblocks(block, [base(0), base(4)].map(|c| c.map(i32::from)), index);
+    }
+
+    let base = [59, 51, 43].map(|shift| bits(block, shift, 5));
+    let delta = [56, 48, 40].map(|shift| (bits(block, shift, 3) << 29) >> 29);
+    let second = [0, 1, 2].map(|channel| base[channel] + delta[channel]);
+
+    let overflow = |channel: usize| !(0..32).contains(&second[channel]);
+    let extend4 = |value| extend(value, 4) as i32;
+    if overflow(0) {
+        // T mode: one color on its own and three around the other.
+        let first = [
+            bits(block, 59, 2) << 2 | bits(block, 56, 2),
+            bits(block, 52, 4),
+            bits(block, 48, 4),
+        ]
+        .map(extend4);
+        let second = [44, 40, 36].map(|shift| extend4(bits(block, shift, 4)));
+        let distance = ETC2_DISTANCES[(bits(block, 34, 2) << 1 | bits(block, 32, 1)) as usize];
+        let paint = [
+            rgb(first),
+            rgb(second.map(|c| c + distance)),
+            rgb(second),
+            rgb(second.map(|c| c - distance)),
+        ];
+
+        std::array::from_fn(|texel| paint[index(texel)])
+    } else if overflow(1) {
+        // H mode: two colors, each split in two.
+        let first = [
+            bits(block, 59, 4),
+            bits(block, 56, 3) << 1 | bits(block, 52, 1),
+            bits(block, 51, 1) << 3 | bits(block, 48, 2) << 1 | bits(block, 47, 1),
+        ]
+        .map(extend4);
+        let second = [
+            bits(block, 43, 4),
+            bits(block, 40, 3) << 1 | bits(block, 39, 1),
+            bits(block, 35, 4),
+        ]
+        .map(extend4);
+        let value = |[r, g, b]: [i32; 3]| r << 16 | g << 8 | b;
+        let distance = ETC2_DISTANCES[(bits(block, 34, 1) << 2
+            | bits(block, 32, 1) << 1
+            | (value(first) >= value(second)) as i32)
+            as usize];
+        let paint = [
+            rgb(first.map(|c| c + distance)),
+            rgb(first.map(|c| c - distance)),
+            rgb(second.map(|c| c + distance)),
+            rgb(second.map(|c| c - distance)),
+        ];
+
+        std::array::from_fn(|texel| paint[index(texel)])
+    } else if overflow(2) {
+        // Planar mode: a gradient between an origin, a horizontal and a vertical color.
+        let origin = [
+            extend(bits(block, 57, 6), 6),
+            extend(bits(block, 56, 1) << 6 | bits(block, 49, 6), 7),
+            extend(
+                bits(block, 48, 1) << 5
+                    | bits(block, 43, 2) << 3
+                    | bits(block, 40, 2) << 1
+                    | bits(block, 39, 1),
+                6,
+            ),
+        ]
+        .map(i32::from);
+        let horizontal = [
+            extend(bits(block, 34, 5) << 1 | bits(block, 32, 1), 6),
+            extend(bits(block, 25, 7), 7),
+            extend(bits(block, 24, 1) << 5 | bits(block, 19, 5), 6),
+        ]
+        .map(i32::from);
+        let vertical = [
+            extend(bits(block, 16, 3) << 3 | bits(block, 13, 3), 6),
+            extend(bits(block, 8, 5) << 2 | bits(block, 6, 2), 7),
+            extend(bits(block, 0, 6), 6),
+        ]
+        .map(i32::from);
+
+        std::array::from_fn(|texel| {
+            let (x, y) = ((texel % 4) as i32, (texel / 4) as i32);
+            rgb([0, 1, 2].map(|c| {
+                (x * (horizontal[c] - origin[c])
+                    + y * (vertical[c] - origin[c])
+                    + 4 * origin[c]
+                    + 2)
+                    >> 2
+            }))
+        })
+    } else {
+        let base = [base, second].map(|color| color.map(|c| extend(c, 5) as i32));
+        decode_etc1_subblocks(block, base, index)
+    }
+}
+
+// Two 4x2 or 2x4 halves with a base color and modifier table each.
+fn decode_etc1_subblocks(
+    block: u64,
+    base: [[i32; 3]; 2],
+    index: impl Fn(usize) -> usize,
+) -> [Rgba<u8>; 16] {
+    let flip = bits(block, 32, 1) == 1;
+    let tables =
+        [bits(block, 37, 3), bits(block, 34, 3)].map(|table| ETC1_MODIFIERS[table as usize]);
+
+    std::array::from_fn(|texel| {
+        let (x, y) = (texel % 4, texel / 4);
+        let half = if flip { y >= 2 } else { x >= 2 } as usize;
+        let [small, large] = tables[half];
+        let modifier = [small, large, -small, -large][index(texel)];
+
+        rgb(base[half].map(|c| c + modifier))
+    })
+}
+
+// An EAC alpha block followed by an ETC2 RGB block.
+fn decode_etc2_rgba(block: &[u8]) -> [Rgba<u8>; 16] {
+    let alpha = u64::from_be_bytes(block[..8].try_into().unwrap());
+    let mut texels = decode_etc2_rgb(&block[8..]);
+
+    let base = bits(alpha, 56, 8);
+    let multiplier = bits(alpha, 52, 4);
+    let modifiers = EAC_MODIFIERS[bits(alpha, 48, 4) as usize];
+    for (texel, color) in texels.iter_mut().enumerate() {
+        let i = (texel % 4 * 4 + texel / 4) as u32;
+        let modifier = modifiers[bits(alpha, 45 - 3 * i, 3) as usize];
+        color[3] = (base + modifier * multiplier).clamp(0, 255) as u8;
+    }
+
+    texels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ktx2::{Header, Index, LevelIndex};
+
+    // A minimal KTX2 file with the levels stored back to back.
+    fn ktx2_file(format: ktx2::Format, width: u32, height: u32, levels: &[Vec<u8>]) -> Vec<u8> {
+        let level_index_end = Header::LENGTH + levels.len() * LevelIndex::LENGTH;
+        // The reader wants a DFD, which can be just its own length.
+        let dfd_offset = level_index_end as u32;
+        let header = Header {
+            format: Some(format),
+            type_size: 1,
+            pixel_width: width,
+            pixel_height: height,
+            pixel_depth: 0,
+            layer_count: 0,
+            face_count: 1,
+            level_count: levels.len() as u32,
+            supercompression_scheme: None,
+            index: Index {
+                dfd_byte_offset: dfd_offset,
+                dfd_byte_length: 4,
+                kvd_byte_offset: 0,
+                kvd_byte_length: 0,
+                sgd_byte_offset: 0,
+                sgd_byte_length: 0,
+            },
+        };
+
+        let mut file = header.as_bytes().to_vec();
+        let mut offset = level_index_end as u64 + 4;
+        for level in levels {
+            let index = LevelIndex {
+                byte_offset: offset,
+                byte_length: level.len() as u64,
+                uncompressed_byte_length: level.len() as u64,
+            };
+            file.extend(index.as_bytes());
+            offset += level.len() as u64;
+        }
+        file.extend(4u32.to_le_bytes());
+        for level in levels {
+            file.extend(level);
+        }
+
+        file
+    }
+
+    // BC1 with red and blue endpoints, texel i using palette entry i % 4.
+    fn bc1_block() -> Vec<u8> {
+        let mut block = vec![];
+        block.extend(0xf800u16.to_le_bytes());
+        block.extend(0x001fu16.to_le_bytes());
+        block.extend(0xe4e4e4e4u32.to_le_bytes());
+
+        block
+    }
+
+    #[test]
+    fn ktx2_levels_are_read_and_checked() {
+        let levels = vec![bc1_block().repeat(4), bc1_block(), bc1_block()];
+        let file = ktx2_file(ktx2::Format::BC1_RGBA_SRGB_BLOCK, 8, 8, &levels);
+
+        let image = CompressedImage::from_ktx2(&file, Features::empty()).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc1RgbaUnormSrgb);
+        assert_eq!((image.width, image.height), (8, 8));
+        assert_eq!(image.levels, levels);
+
+        let truncated = ktx2_file(
+            ktx2::Format::BC1_RGBA_SRGB_BLOCK,
+            8,
+            8,
+            &levels[..1]
+                .iter()
+                .map(|level| level[..8].to_vec())
+                .collect::<Vec<_>>(),
+        );
+        assert!(matches!(
+            CompressedImage::from_ktx2(&truncated, Features::empty()),
+            Err(CreateTextureError::LevelSizeMismatch {
+                level: 0,
+                expected: 32,
+                found: 8
+            })
+        ));
+
+        let unsupported = ktx2_file(ktx2::Format::R16_UNORM, 4, 4, &[vec![0; 32]]);
+        assert!(matches!(
+            CompressedImage::from_ktx2(&unsupported, Features::empty()),
+            Err(CreateTextureError::UnsupportedFormat(_))
+        ));
+    }
+
+    #[test]
+    fn formats_without_a_decoder_need_their_feature() {
+        let file = ktx2_file(ktx2::Format::BC7_SRGB_BLOCK, 4, 4, &[vec![0; 16]]);
+        assert!(matches!(
+            CompressedImage::from_ktx2(&file, Features::TEXTURE_COMPRESSION_ETC2),
+            Err(CreateTextureError::NoFallback(
+                TextureFormat::Bc7RgbaUnormSrgb
+            ))
+        ));
+
+        let image = CompressedImage::from_ktx2(&file, Features::TEXTURE_COMPRESSION_BC).unwrap();
+        assert_eq!(image.format, TextureFormat::Bc7RgbaUnormSrgb);
+
+        let file = ktx2_file(ktx2::Format::ASTC_4x4_UNORM_BLOCK, 4, 4, &[vec![0; 16]]);
+        assert!(matches!(
+            CompressedImage::from_ktx2(&file, Features::empty()),
+            Err(CreateTextureError::NoFallback(_))
+        ));
+
+        // BC1 can always be decompressed instead.
+        let file = ktx2_file(ktx2::Format::BC1_RGBA_UNORM_BLOCK, 4, 4, &[bc1_block()]);
+        assert!(CompressedImage::from_ktx2(&file, Features::empty()).is_ok());
+    }
+
+    #[test]
+    fn formats_fall_back_to_rgba8_without_their_feature() {
+        let image = CompressedImage {
+            format: TextureFormat::Bc1RgbaUnormSrgb,
+            width: 2,
+            height: 2,
+            levels: vec![bc1_block()],
+        };
+        assert!(image.is_supported(Features::TEXTURE_COMPRESSION_BC));
+        assert!(!image.is_supported(Features::TEXTURE_COMPRESSION_ETC2));
+
+        let (format, levels) = image.decompress().unwrap();
+        assert_eq!(format, TextureFormat::Rgba8UnormSrgb);
+        assert_eq!(levels[0].dimensions(), (2, 2));
+        assert_eq!(levels[0].get_pixel(1, 0), &Rgba([0, 0, 255, 255]));
+
+        let astc = CompressedImage {
+            format: wgpu_format(ktx2::Format::ASTC_6x6_SRGB_BLOCK).unwrap(),
+            ..image
+        };
+        assert_eq!(
+            astc.format,
+            TextureFormat::Astc {
+                block: AstcBlock::B6x6,
+                channel: AstcChannel::UnormSrgb
+            }
+        );
+        assert!(matches!(
+            astc.decompress(),
+            Err(CreateTextureError::NoFallback(_))
+        ));
+    }
+
+    #[test]
+    fn bc_blocks_decode() {
+        let texels = decode_bc1(&bc1_block());
+        assert_eq!(texels[0], Rgba([255, 0, 0, 255]));
+        assert_eq!(texels[1], Rgba([0, 0, 255, 255]));
+        assert_eq!(texels[2], Rgba([170, 0, 85, 255]));
+        assert_eq!(texels[3], Rgba([85, 0, 170, 255]));
+
+        // Endpoints in the other order switch to three colors and transparent black.
+        let mut block = bc1_block();
+        block.swap(0, 2);
+        block.swap(1, 3);
+        let texels = decode_bc1(&block);
+        assert_eq!(texels[2], Rgba([127, 0, 127, 255]));
+        assert_eq!(texels[3], Rgba([0, 0, 0, 0]));
+
+        // Alpha from 255 to 0, with every texel using entry 2.
+        let mut block = vec![255, 0];
+        block.extend(&0x492492492492u64.to_le_bytes()[..6]);
+        block.extend(bc1_block());
+        let texels = decode_bc3(&block);
+        assert!(texels.iter().all(|texel| texel[3] == 218));
+        assert_eq!(decode_bc4(&block[..8])[5], Rgba([218, 0, 0, 255]));
+    }
+
+    #[test]
+    fn etc2_blocks_decode() {
+        // Individual mode, 8/4 as base colors for the left/right half with table 0.
+        let individual: u64 = 0x84_8484 << 40;
+        let texels = decode_etc2_rgb(&individual.to_be_bytes());
+        assert_eq!(texels[0], Rgba([138, 138, 138, 255]));
+        assert_eq!(texels[3], Rgba([70, 70, 70, 255]));
+
+        // Differential mode, with every texel using the negative large modifier of table 1.
+        let differential: u64 =
+            (16 << 59 | 16 << 51 | 16 << 43) | 1 << 33 | 1 << 37 | 1 << 34 | 0xffff_ffff;
+        let texels = decode_etc2_rgb(&differential.to_be_bytes());
+        assert!(
+            texels
+                .iter()
+                .all(|texel| *texel == Rgba([115, 115, 115, 255]))
+        );
+
+        // An opaque alpha block, then the same color block.
+        let mut block = (255u64 << 56 | 1 << 52).to_be_bytes().to_vec();
+        block.extend(differential.to_be_bytes());
+        let texels = decode_etc2_rgba(&block);
+        assert_eq!(texels[7], Rgba([115, 115, 115, 252]));
+    }
+}
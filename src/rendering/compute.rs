@@ -0,0 +1,406 @@
+use crate::rendering::reflection::{BindingMismatchError, ShaderReflection};
+use crate::rendering::render_graph::{FrameData, GraphPass, PassResources};
+use crate::rendering::utils::bind_group_builder::BindGroupBuilder;
+use crate::rendering::wgpu_context::WGPUContext;
+use wgpu::{
+    BindGroup, BindGroupLayout, CommandEncoder, ComputePassDescriptor, ComputePipeline, Label,
+};
+
+// When a dispatch pushed to the `Renderer` runs, relative to the frame's render passes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ComputeStage {
+    // E.g. simulations and culling whose results are drawn this frame.
+    BeforeGraphics,
+    // E.g. work reading the frame's depth, for use next frame.
+    AfterGraphics,
+}
+
+// A compute pipeline whose bind group layouts are reflected from the shader, one per group from 0
+// up to the highest it declares.
+#[derive(Clone, Debug)]
+pub struct ComputeShader {
+    pub(crate) pipeline: ComputePipeline,
+    pub(crate) layouts: Vec<BindGroupLayout>,
+    pub(crate) reflection: ShaderReflection,
+    pub(crate) workgroup_size: [u32; 3],
+}
+
+impl ComputeShader {
+    pub fn layout(&self, group: u32) -> Option<&BindGroupLayout> {
+        self.layouts.get(group as usize)
+    }
+
+    // Fails if the resources in `bind_group` don't line up with what the shader declares for
+    // `group`. The layouts are reflected, so arrays without a size take a single element.
+    pub fn create_bind_group(
+        &self,
+        context: &WGPUContext,
+        group: u32,
+        bind_group: BindGroupBuilder,
+        label: Label,
+    ) -> Result<BindGroup, BindingMismatchError> {
+        let layout = self
+            .layout(group)
+            .ok_or(BindingMismatchError::UndeclaredGroup(group))?;
+        self.reflection
+            .check_reflected_resources(group, bind_group.resources())?;
+
+        Ok(bind_group.build(context, layout, label))
+    }
+
+    // `bind_groups` holds one bind group per layout, in group order.
+    pub fn dispatch(&self, bind_groups: &[BindGroup], workgroups: [u32; 3]) -> ComputeDispatch {
+        debug_assert_eq!(
+            bind_groups.len(),
+            self.layouts.len(),
+            "every group needs a bind group"
+        );
+
+        ComputeDispatch {
+            pipeline: self.pipeline.clone(),
+            bind_groups: bind_groups.to_vec(),
+            workgroups,
+        }
+    }
+
+    // Enough workgroups for one invocation per thread. Shaders have to skip the extra invocations
+    // when `threads` isn't a multiple of the workgroup size.
+    pub fn dispatch_threads(
+        &self,
+        bind_groups: &[BindGroup],
+        threads: [u32; 3],
+    ) -> ComputeDispatch {
+        let workgroups = [0, 1, 2].map(|axis| threads[axis].div_ceil(self.workgroup_size[axis]));
+        self.dispatch(bind_groups, workgroups)
+    }
+}
+
+// One dispatch of a `ComputeShader` with its bind groups, recorded into a compute pass later.
+#[derive(Clone, Debug)]
+pub struct ComputeDispatch {
+    pipeline: ComputePipeline,
+    bind_groups: Vec<BindGroup>,
+    workgroups: [u32; 3],
+}
+
+impl ComputeDispatch {
+    pub fn is_empty(&self) -> bool {
+        self.workgroups.contains(&0)
+    }
+}
+
+// Records `dispatches` in order into one compute pass, or none if there's nothing to dispatch.
+// wgpu synchronizes resource usage between dispatches and passes of an encoder, so buffers and
+// textures written by a dispatch are visible to every later dispatch and pass.
+pub(crate) fn record_compute_pass<'a>(
+    encoder: &mut CommandEncoder,
+    label: &str,
+    dispatches: impl IntoIterator<Item = &'a ComputeDispatch>,
+) {
+    let mut dispatches = dispatches
+        .into_iter()
+        .filter(|dispatch| !dispatch.is_empty())
+        .peekable();
+    if dispatches.peek().is_none() {
+        return;
+    }
+
+    let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+        label: Some(label),
+        timestamp_writes: None,
+    });
+    for dispatch in dispatches {
+        compute_pass.set_pipeline(&dispatch.pipeline);
+        for (group, bind_group) in dispatch.bind_groups.iter().enumerate() {
+            compute_pass.set_bind_group(group as u32, bind_group, &[]);
+        }
+        let [x, y, z] = dispatch.workgroups;
+        compute_pass.dispatch_workgroups(x, y, z);
+    }
+}
+
+// Records the frame's dispatches for one stage, as a pass of the render graph.
+pub struct ComputeStagePass(pub ComputeStage);
+
+impl GraphPass for ComputeStagePass {
+    fn record(&mut self, encoder: &mut CommandEncoder, _: &PassResources, frame: &FrameData) {
+        let stage = self.0;
+        let dispatches = frame
+            .compute
+            .iter()
+            .filter(|(dispatch_stage, _)| *dispatch_stage == stage)
+            .map(|(_, dispatch)| dispatch);
+
+        let label = match stage {
+            ComputeStage::BeforeGraphics => "Compute Pass: Before Graphics",
+            ComputeStage::AfterGraphics => "Compute Pass: After Graphics",
+        };
+        record_compute_pass(encoder, label, dispatches);
+    }
+}
+
+// Runs `dispatches` right away instead of as part of a frame.
+#[cfg(test)]
+pub fn submit_compute(context: &WGPUContext, dispatches: &[ComputeDispatch]) {
+    let mut encoder = context.device.create_command_encoder(&Default::default());
+    record_compute_pass(&mut encoder, "Compute Pass", dispatches);
+    context.queue.submit([encoder.finish()]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::source::{AssetSource, EmbeddedAssets};
+    use crate::rendering::buffer::Buffer;
+    use crate::rendering::preprocessor::ShaderDefines;
+    use crate::rendering::wgpu_context::CreateShaderError;
+    use wgpu::{
+        BufferUsages, Color, Extent3d, LoadOp, Operations, RenderPassColorAttachment,
+        RenderPassDescriptor, StorageTextureAccess, StoreOp, TexelCopyBufferInfo,
+        TexelCopyBufferLayout, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+        TextureUsages, TextureViewDimension,
+    };
+
+    const WIDTH: u32 = 64; // a row of RGBA8 texels is exactly the 256 bytes copies align to
+    const HEIGHT: u32 = 4;
+
+    const SHADERS: EmbeddedAssets = &[
+        (
+            "/fill.wgsl",
+            b"
+            @group(0) @binding(0) var<storage, read_write> values: array<u32>;
+
+            @compute @workgroup_size(8)
+            fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+                if id.x < arrayLength(&values) {
+                    values[id.x] = id.x * 3u;
+                }
+            }
+            ",
+        ),
+        (
+            // Leaves group 0 empty, so the pipeline layout has a gap.
+            "/paint.wgsl",
+            b"
+            @group(1) @binding(0) var<storage, read> values: array<u32>;
+            @group(1) @binding(1) var output: texture_storage_2d<rgba8unorm, write>;
+
+            @compute @workgroup_size(8, 8)
+            fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+                if all(id.xy < textureDimensions(output)) {
+                    let red = f32(values[id.x]) / 255.0;
+                    textureStore(output, id.xy, vec4<f32>(red, 0.0, 0.0, 1.0));
+                }
+            }
+            ",
+        ),
+        (
+            "/sample.wgsl",
+            b"
+            @group(0) @binding(0) var drawn: texture_2d<f32>;
+            @group(0) @binding(1) var<storage, read_write> greens: array<u32>;
+
+            @compute @workgroup_size(64)
+            fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+                if id.x < arrayLength(&greens) {
+                    let texel = textureLoad(drawn, vec2<u32>(id.x, 0u), 0);
+                    greens[id.x] = u32(round(texel.g * 255.0));
+                }
+            }
+            ",
+        ),
+    ];
+
+    fn create_shader(context: &WGPUContext, path: &str) -> ComputeShader {
+        context
+            .create_compute_shader_from(
+                &AssetSource::new().with_embedded(SHADERS),
+                path,
+                "main",
+                &ShaderDefines::new(),
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn invalid_pipelines_are_errors() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        // Valid WGSL, but larger than any device's workgroup limit.
+        let assets = AssetSource::new()
+            .with_embedded(&[("/huge.wgsl", b"@compute @workgroup_size(4096) fn main() {}")]);
+
+        let result = context.create_compute_shader_from(
+            &assets,
+            "/huge.wgsl",
+            "main",
+            &ShaderDefines::new(),
+        );
+        assert!(
+            matches!(&result, Err(CreateShaderError::Compile { file, .. }) if file == "/huge.wgsl"),
+            "{:?}",
+            result.err()
+        );
+    }
+
+    fn create_texture(context: &WGPUContext, usage: TextureUsages) -> Texture {
+        context.device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: WIDTH,
+                height: HEIGHT,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: usage | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        })
+    }
+
+    fn read_texture(context: &WGPUContext, texture: &Texture) -> Vec<u8> {
+        let buffer =
+            Buffer::<u8>::with_capacity(context, WIDTH * HEIGHT * 4, BufferUsages::empty());
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            TexelCopyBufferInfo {
+                buffer: buffer.buffer(),
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(WIDTH * 4),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        context.queue.submit([encoder.finish()]);
+
+        context.read_buffer(buffer.buffer())
+    }
+
+    #[test]
+    fn dispatches_see_what_earlier_ones_wrote() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let fill = create_shader(&context, "/fill.wgsl");
+        let paint = create_shader(&context, "/paint.wgsl");
+        assert_eq!(fill.workgroup_size, [8, 1, 1]);
+        assert_eq!(paint.layouts.len(), 2);
+
+        let values = Buffer::<u32>::with_capacity(&context, WIDTH, BufferUsages::STORAGE);
+        let texture = create_texture(&context, TextureUsages::STORAGE_BINDING);
+        let view = texture.create_view(&Default::default());
+
+        let fill_group = fill
+            .create_bind_group(
+                &context,
+                0,
+                BindGroupBuilder::new().with_read_write_storage_buffer(0, values.buffer()),
+                None,
+            )
+            .unwrap();
+        let paint_groups = [
+            paint
+                .create_bind_group(&context, 0, BindGroupBuilder::new(), None)
+                .unwrap(),
+            paint
+                .create_bind_group(
+                    &context,
+                    1,
+                    BindGroupBuilder::new()
+                        .with_storage_buffer(0, values.buffer())
+                        .with_storage_texture(
+                            1,
+                            &view,
+                            TextureFormat::Rgba8Unorm,
+                            StorageTextureAccess::WriteOnly,
+                            TextureViewDimension::D2,
+                        ),
+                    None,
+                )
+                .unwrap(),
+        ];
+        assert_eq!(
+            paint
+                .create_bind_group(&context, 2, BindGroupBuilder::new(), None)
+                .unwrap_err(),
+            BindingMismatchError::UndeclaredGroup(2)
+        );
+
+        submit_compute(
+            &context,
+            &[
+                fill.dispatch_threads(&[fill_group], [WIDTH, 1, 1]),
+                paint.dispatch_threads(&paint_groups, [WIDTH, HEIGHT, 1]),
+            ],
+        );
+
+        let values: Vec<u32> = bytemuck::cast_slice(&context.read_buffer(values.buffer())).to_vec();
+        assert_eq!(values, (0..WIDTH).map(|x| x * 3).collect::<Vec<_>>());
+        let texels = read_texture(&context, &texture);
+        for (index, texel) in texels.chunks(4).enumerate() {
+            let x = index as u32 % WIDTH;
+            assert_eq!(texel, [(x * 3) as u8, 0, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn dispatches_after_graphics_see_what_was_drawn() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let sample = create_shader(&context, "/sample.wgsl");
+        let texture = create_texture(
+            &context,
+            TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+        );
+        let view = texture.create_view(&Default::default());
+        let greens = Buffer::<u32>::with_capacity(&context, WIDTH, BufferUsages::STORAGE);
+        let bind_group = sample
+            .create_bind_group(
+                &context,
+                0,
+                BindGroupBuilder::new()
+                    .with_texture2d(0, &view)
+                    .with_read_write_storage_buffer(1, greens.buffer()),
+                None,
+            )
+            .unwrap();
+
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        encoder.begin_render_pass(&RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: &view,
+                depth_slice: None,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color {
+                        r: 0.0,
+                        g: 0.2,
+                        b: 0.0,
+                        a: 1.0,
+                    }),
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        record_compute_pass(
+            &mut encoder,
+            "After Graphics",
+            &[sample.dispatch_threads(&[bind_group], [WIDTH, 1, 1])],
+        );
+        context.queue.submit([encoder.finish()]);
+
+        let greens: Vec<u32> = bytemuck::cast_slice(&context.read_buffer(greens.buffer())).to_vec();
+        assert_eq!(greens, vec![51; WIDTH as usize]);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::aabb::Aabb;
+use glam::{Mat4, Vec3, Vec4, Vec4Swizzles};
+
+// How many pushed objects the last frame drew and skipped.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct CullStats {
+    pub drawn: u32,
+    pub culled: u32,
+}
+
+// The six planes bounding what a camera sees, with normals pointing inwards.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Frustum {
+    planes: [Vec4; 6], // left, right, bottom, top, near, far as (normal, distance)
+}
+
+impl Frustum {
+    // Extracts the planes from the rows of a matrix projecting to wgpu's clip space, where depth
+    // goes from 0 to 1 (Gribb/Hartmann).
+    pub fn from_view_projection(view_proj: &Mat4) -> Self {
+        let [x, y, z, w] = [0, 1, 2, 3].map(|row| view_proj.row(row));
+        let planes =
+            [w + x, w - x, w + y, w - y, z, w - z].map(|plane| plane / plane.xyz().length());
+
+        Self { planes }
+    }
+
+    pub fn planes(&self) -> &[Vec4; 6] {
+        &self.planes
+    }
+
+    #[cfg(test)]
+    pub fn contains_point(&self, point: Vec3) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.xyz().dot(point) + plane.w >= 0.0)
+    }
+
+    // Conservative: boxes near the frustum's corners can pass without being visible, but visible
+    // boxes never fail.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner furthest along the plane's normal.
+            let normal = plane.xyz();
+            let corner = Vec3::select(normal.cmpge(Vec3::ZERO), aabb.max, aabb.min);
+
+            normal.dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::camera::Camera;
+    use std::f32::consts::FRAC_PI_2;
+
+    // At the origin looking down -Z, seeing 45 degrees to each side.
+    fn frustum() -> Frustum {
+        Camera {
+            eye: Vec3::ZERO,
+            target: Vec3::NEG_Z,
+            up: Vec3::Y,
+            aspect: 1.0,
+            fov: FRAC_PI_2,
+            near_clip: 0.1,
+            far_clip: 100.0,
+        }
+        .frustum()
+    }
+
+    #[test]
+    fn planes_are_extracted_from_the_view_projection() {
+        let frustum = frustum();
+
+        let near = frustum.planes()[4];
+        assert!(near.xyz().abs_diff_eq(Vec3::NEG_Z, 1e-5));
+        assert!((near.w + 0.1).abs() < 1e-5);
+        let far = frustum.planes()[5];
+        assert!(far.xyz().abs_diff_eq(Vec3::Z, 1e-5));
+        assert!((far.w - 100.0).abs() < 0.1);
+
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -1.0)));
+        assert!(frustum.contains_point(Vec3::new(0.9, -0.9, -1.0)));
+        assert!(!frustum.contains_point(Vec3::new(1.1, 0.0, -1.0)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, 1.0)));
+        assert!(frustum.contains_point(Vec3::new(0.0, 0.0, -0.11)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -0.09)));
+        assert!(!frustum.contains_point(Vec3::new(0.0, 0.0, -101.0)));
+    }
+
+    #[test]
+    fn aabbs_outside_any_plane_are_culled() {
+        let frustum = frustum();
+        let unit = |min: Vec3| Aabb::new(min, min + Vec3::ONE);
+
+        assert!(frustum.intersects_aabb(&unit(Vec3::new(-0.5, -0.5, -5.0))));
+        // Straddling the right plane, and surrounding the camera.
+        assert!(frustum.intersects_aabb(&unit(Vec3::new(4.5, 0.0, -5.0))));
+        assert!(frustum.intersects_aabb(&Aabb::new(Vec3::splat(-1.0), Vec3::ONE)));
+
+        assert!(!frustum.intersects_aabb(&unit(Vec3::new(6.0, 0.0, -5.0))));
+        assert!(!frustum.intersects_aabb(&unit(Vec3::new(0.0, -7.0, -5.0))));
+        assert!(!frustum.intersects_aabb(&unit(Vec3::new(0.0, 0.0, 1.0))));
+        assert!(!frustum.intersects_aabb(&unit(Vec3::new(0.0, 0.0, -150.0))));
+    }
+
+    #[test]
+    fn transformed_aabbs_cover_the_rotated_box() {
+        let aabb = Aabb::from_points([Vec3::ZERO, Vec3::new(2.0, 1.0, 1.0)]);
+        let rotated = aabb.transform(&Mat4::from_rotation_y(FRAC_PI_2));
+
+        assert!(rotated.min.abs_diff_eq(Vec3::new(0.0, 0.0, -2.0), 1e-5));
+        assert!(rotated.max.abs_diff_eq(Vec3::new(1.0, 1.0, 0.0), 1e-5));
+        assert_eq!(
+            aabb.transform(&Mat4::from_translation(Vec3::X)),
+            aabb.translate(Vec3::X)
+        );
+    }
+}
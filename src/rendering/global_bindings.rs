@@ -7,11 +7,12 @@ use crate::rendering::wgpu_context::WGPUContext;
 use bytemuck::{Pod, Zeroable};
 use wgpu::AddressMode::ClampToEdge;
 use wgpu::FilterMode::{Linear, Nearest};
-use wgpu::{BindGroup, BindGroupLayout, BufferBindingType, ShaderStages};
+use wgpu::{BindGroup, BindGroupLayout, BindGroupLayoutEntry, ShaderStages};
 
 // inspired by https://github.com/Wumpf/blub/blob/master/src/global_bindings.rs
 pub struct GlobalBindings {
     layout: BindGroupLayout,
+    layout_entries: Vec<BindGroupLayoutEntry>,
     bind_group: BindGroup,
     global_buffer: Buffer<GlobalBufferContext>,
 }
@@ -32,11 +33,12 @@ impl GlobalBufferContext {
 
 impl GlobalBindings {
     pub fn new(context: &WGPUContext, global_data: GlobalBufferContext) -> Self {
-        let layout = BindGroupLayoutBuilder::new()
-            .with_buffer(ShaderStages::VERTEX_FRAGMENT, BufferBindingType::Uniform)
-            .with_sampler(ShaderStages::FRAGMENT)
-            .with_sampler(ShaderStages::FRAGMENT)
-            .build(context, Some("Global Bind Group Layout"));
+        let layout_builder = BindGroupLayoutBuilder::new()
+            .with_uniform_buffer(0, ShaderStages::VERTEX_FRAGMENT)
+            .with_sampler(1, ShaderStages::FRAGMENT)
+            .with_sampler(2, ShaderStages::FRAGMENT);
+        let layout_entries = layout_builder.entries.clone();
+        let layout = layout_builder.build(context, Some("Global Bind Group Layout"));
 
         let trilinear_sampler = SamplerBuilder::new()
             .with_mode(ClampToEdge)
@@ -51,13 +53,14 @@ impl GlobalBindings {
         let global_buffer = Buffer::new_uniform(context, Some(&[global_data]));
 
         let bind_group = BindGroupBuilder::new()
-            .with_buffer(&global_buffer.buffer())
-            .with_sampler(&trilinear_sampler)
-            .with_sampler(&point_sampler)
+            .with_uniform_buffer(0, global_buffer.buffer())
+            .with_sampler(1, &trilinear_sampler)
+            .with_sampler(2, &point_sampler)
             .build(context, &layout, Some("Global Bind Group"));
 
         Self {
             layout,
+            layout_entries,
             bind_group,
             global_buffer,
         }
@@ -78,4 +81,36 @@ impl GlobalBindings {
     pub fn bind_group_layout(&self) -> &BindGroupLayout {
         &self.layout
     }
+
+    // What shaders are checked against for @group(0).
+    pub fn layout_entries(&self) -> &[BindGroupLayoutEntry] {
+        &self.layout_entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::source::AssetSource;
+    use crate::rendering::preprocessor::{ShaderDefines, preprocess};
+    use crate::rendering::reflection::wgsl_struct;
+    use std::mem::{offset_of, size_of};
+
+    #[test]
+    fn wgsl_globals_match_the_buffer_layout() {
+        let shader = preprocess(
+            "/res/shaders/include/globals.wgsl",
+            &ShaderDefines::new(),
+            &AssetSource::new().with_directory(env!("CARGO_MANIFEST_DIR")),
+        )
+        .unwrap();
+        let module = naga::front::wgsl::parse_str(&shader.source).unwrap();
+
+        let (span, offsets) = wgsl_struct(&module, "GlobalBufferContext");
+        assert_eq!(span as usize, size_of::<GlobalBufferContext>());
+        assert_eq!(offsets, [offset_of!(GlobalBufferContext, camera) as u32]);
+
+        let (span, _) = wgsl_struct(&module, "CameraBufferContext");
+        assert_eq!(span as usize, size_of::<CameraBufferContext>());
+    }
 }
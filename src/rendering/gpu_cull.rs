@@ -0,0 +1,233 @@
+use crate::aabb::Aabb;
+use crate::assets::source::AssetSource;
+use crate::rendering::buffer::Buffer;
+use crate::rendering::compute::{ComputeDispatch, ComputeShader};
+use crate::rendering::frustum::Frustum;
+use crate::rendering::indirect::{create_indirect_buffer, reserve};
+use crate::rendering::preprocessor::{BUILTIN_SHADERS, ShaderDefines};
+use crate::rendering::reflection::BindingMismatchError;
+use crate::rendering::utils::bind_group_builder::BindGroupBuilder;
+use crate::rendering::wgpu_context::{CreateShaderError, WGPUContext};
+use bytemuck::{Pod, Zeroable};
+use glam::Vec4;
+use std::slice;
+use wgpu::util::DrawIndexedIndirectArgs;
+use wgpu::{BindGroup, BufferUsages};
+
+const CULL_SHADER: &str = "/res/shaders/cull_chunks.wgsl";
+const WORKGROUP_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct CullParams {
+    planes: [Vec4; 6],
+    count: u32,
+    _padding: [u32; 3],
+}
+
+// A mesh drawn only if its bounds intersect the frustum.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+pub struct CullCandidate {
+    min: [f32; 3],
+    index_count: u32,
+    max: [f32; 3],
+    first_index: u32,
+    base_vertex: i32,
+    _padding: [u32; 3],
+}
+
+impl CullCandidate {
+    pub fn new(draw: &DrawIndexedIndirectArgs, bounds: &Aabb) -> Self {
+        Self {
+            min: bounds.min.into(),
+            index_count: draw.index_count,
+            max: bounds.max.into(),
+            first_index: draw.first_index,
+            base_vertex: draw.base_vertex,
+            _padding: [0; 3],
+        }
+    }
+}
+
+// Frustum culls meshes in a compute pass, writing one indirect draw per candidate. Culled ones
+// keep their slot with no instances, so the draw count is known up front.
+pub struct GpuCuller {
+    shader: ComputeShader,
+    params: Buffer<CullParams>,
+    candidates: Buffer<CullCandidate>,
+    draws: wgpu::Buffer,
+    bind_group: BindGroup,
+}
+
+impl GpuCuller {
+    pub fn new(context: &WGPUContext) -> Result<Self, CreateShaderError> {
+        let shader = context.create_compute_shader_from(
+            &AssetSource::new().with_embedded(BUILTIN_SHADERS),
+            CULL_SHADER,
+            "main",
+            &ShaderDefines::new(),
+        )?;
+
+        let params = Buffer::new_uniform(context, Some(&[CullParams::zeroed()]));
+        let candidates = Buffer::with_capacity(context, WORKGROUP_SIZE, BufferUsages::STORAGE);
+        let draws = create_indirect_buffer(
+            context,
+            "Culled Draws",
+            WORKGROUP_SIZE as u64 * size_of::<DrawIndexedIndirectArgs>() as u64,
+        );
+        let bind_group = Self::bind_group(context, &shader, &params, &candidates, &draws)?;
+
+        Ok(Self {
+            shader,
+            params,
+            candidates,
+            draws,
+            bind_group,
+        })
+    }
+
+    // Uploads this frame's candidates. The returned dispatch has to run before `draws` is drawn
+    // from, e.g. by pushing it to the `Renderer` at `ComputeStage::BeforeGraphics`.
+    pub fn prepare(
+        &mut self,
+        context: &WGPUContext,
+        frustum: &Frustum,
+        candidates: &[CullCandidate],
+    ) -> ComputeDispatch {
+        let count = candidates.len() as u32;
+        self.params.upload(
+            context,
+            &[CullParams {
+                planes: *frustum.planes(),
+                count,
+                _padding: [0; 3],
+            }],
+        );
+
+        let draw_bytes = count as u64 * size_of::<DrawIndexedIndirectArgs>() as u64;
+        let grew = self.candidates.upload(context, candidates)
+            | reserve(context, &mut self.draws, draw_bytes, "Culled Draws");
+        if grew {
+            self.bind_group = Self::bind_group(
+                context,
+                &self.shader,
+                &self.params,
+                &self.candidates,
+                &self.draws,
+            )
+            .expect("the cull bindings were checked on creation");
+        }
+
+        self.shader
+            .dispatch_threads(slice::from_ref(&self.bind_group), [count, 1, 1])
+    }
+
+    // The indirect buffer the dispatch writes, one draw per candidate.
+    pub fn draws(&self) -> &wgpu::Buffer {
+        &self.draws
+    }
+
+    fn bind_group(
+        context: &WGPUContext,
+        shader: &ComputeShader,
+        params: &Buffer<CullParams>,
+        candidates: &Buffer<CullCandidate>,
+        draws: &wgpu::Buffer,
+    ) -> Result<BindGroup, BindingMismatchError> {
+        shader.create_bind_group(
+            context,
+            0,
+            BindGroupBuilder::new()
+                .with_uniform_buffer(0, params.buffer())
+                .with_storage_buffer(1, candidates.buffer())
+                .with_read_write_storage_buffer(2, draws),
+            Some("Cull Bind Group"),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::compute::submit_compute;
+    use crate::rendering::preprocessor::preprocess;
+    use crate::rendering::reflection::wgsl_struct;
+    use glam::{Mat4, Vec3};
+    use std::mem::offset_of;
+
+    #[test]
+    fn wgsl_structs_match_the_buffer_layouts() {
+        let src = preprocess(
+            CULL_SHADER,
+            &ShaderDefines::new(),
+            &AssetSource::new().with_embedded(BUILTIN_SHADERS),
+        )
+        .unwrap();
+        let module = naga::front::wgsl::parse_str(&src.source).unwrap();
+
+        let (span, offsets) = wgsl_struct(&module, "CullParams");
+        assert_eq!(span as usize, size_of::<CullParams>());
+        assert_eq!(
+            offsets,
+            [
+                offset_of!(CullParams, planes),
+                offset_of!(CullParams, count)
+            ]
+            .map(|o| o as u32)
+        );
+
+        let (span, offsets) = wgsl_struct(&module, "CullCandidate");
+        assert_eq!(span as usize, size_of::<CullCandidate>());
+        assert_eq!(
+            offsets,
+            [
+                offset_of!(CullCandidate, min),
+                offset_of!(CullCandidate, index_count),
+                offset_of!(CullCandidate, max),
+                offset_of!(CullCandidate, first_index),
+                offset_of!(CullCandidate, base_vertex),
+            ]
+            .map(|o| o as u32)
+        );
+
+        let (span, _) = wgsl_struct(&module, "DrawArgs");
+        assert_eq!(span as usize, size_of::<DrawIndexedIndirectArgs>());
+    }
+
+    #[test]
+    fn draws_outside_the_frustum_get_no_instances() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let frustum = Frustum::from_view_projection(&Mat4::perspective_rh(1.0, 1.0, 0.1, 100.0));
+        let draw = |first_index| DrawIndexedIndirectArgs {
+            index_count: 36,
+            instance_count: 1,
+            first_index,
+            base_vertex: 0,
+            first_instance: 0,
+        };
+        let in_front = Aabb::new(Vec3::new(-1.0, -1.0, -6.0), Vec3::new(1.0, 1.0, -4.0));
+        let behind = in_front.translate(Vec3::Z * 10.0);
+        // More candidates than the initial capacity, so the buffers grow.
+        let candidates: Vec<CullCandidate> = (0..100)
+            .map(|i| {
+                let bounds = if i % 2 == 0 { in_front } else { behind };
+                CullCandidate::new(&draw(i * 36), &bounds)
+            })
+            .collect();
+
+        let mut culler = GpuCuller::new(&context).unwrap();
+        let dispatch = culler.prepare(&context, &frustum, &candidates);
+        submit_compute(&context, &[dispatch]);
+
+        let bytes = context.read_buffer(culler.draws());
+        let draws: &[u32] =
+            bytemuck::cast_slice(&bytes[..100 * size_of::<DrawIndexedIndirectArgs>()]);
+        for (i, args) in draws.chunks(5).enumerate() {
+            let instances = if i % 2 == 0 { 1 } else { 0 };
+            assert_eq!(args, [36, instances, i as u32 * 36, 0, 0]);
+        }
+    }
+}
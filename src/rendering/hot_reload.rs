@@ -0,0 +1,143 @@
+use crate::assets::source::AssetSource;
+use crate::rendering::pipeline::PipelineDescriptor;
+use crate::rendering::preprocessor::ShaderDefines;
+use crate::rendering::shader::{Shader, ShaderProgram};
+use crate::rendering::wgpu_context::WGPUContext;
+use log::{error, info};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, channel};
+use std::sync::{Arc, RwLock, Weak};
+use wgpu::BindGroupLayout;
+
+// Relative to each asset directory.
+const SHADER_DIR: &str = "res/shaders";
+
+// A shader without the strong reference to its program, so watching doesn't keep dropped shaders
+// alive.
+struct WatchedShader {
+    path: String,
+    defines: ShaderDefines,
+    program: Weak<RwLock<ShaderProgram>>,
+    descriptor: PipelineDescriptor,
+    global_layout: BindGroupLayout,
+    material_layout: BindGroupLayout,
+}
+
+impl WatchedShader {
+    fn upgrade(&self) -> Option<Shader> {
+        Some(Shader {
+            path: Some(self.path.clone()),
+            defines: self.defines.clone(),
+            program: self.program.upgrade()?,
+            descriptor: self.descriptor.clone(),
+            global_layout: self.global_layout.clone(),
+            material_layout: self.material_layout.clone(),
+        })
+    }
+}
+
+// Watches the shader directories of the asset source and recompiles the shaders loaded from files
+// that change. Only exists in debug builds.
+pub struct ShaderHotReload {
+    _watcher: RecommendedWatcher,
+    changes: Receiver<PathBuf>,
+    shaders: Vec<WatchedShader>,
+}
+
+impl ShaderHotReload {
+    pub fn new(assets: &AssetSource) -> notify::Result<Self> {
+        let (sender, changes) = channel();
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+            if let Ok(event) = event
+                && matches!(event.kind, EventKind::Modify(_) | EventKind::Create(_))
+            {
+                for path in event.paths {
+                    let _ = sender.send(path);
+                }
+            }
+        })?;
+        for dir in assets.directories() {
+            let shaders = dir.join(SHADER_DIR);
+            if shaders.is_dir() {
+                watcher.watch(&shaders, RecursiveMode::Recursive)?;
+            }
+        }
+
+        Ok(Self {
+            _watcher: watcher,
+            changes,
+            shaders: vec![],
+        })
+    }
+
+    // Shaders without a path can't be reloaded and are ignored.
+    pub(crate) fn watch(&mut self, shader: &Shader) {
+        let Some(path) = &shader.path else {
+            return;
+        };
+
+        self.shaders.push(WatchedShader {
+            path: path.clone(),
+            defines: shader.defines.clone(),
+            program: Arc::downgrade(&shader.program),
+            descriptor: shader.descriptor.clone(),
+            global_layout: shader.global_layout.clone(),
+            material_layout: shader.material_layout.clone(),
+        });
+    }
+
+    // Recompiles every watched shader whose file changed since the last call. Failed shaders keep
+    // their previous pipeline.
+    pub(crate) fn reload_changed(&mut self, context: &WGPUContext) {
+        let changed: HashSet<PathBuf> = self.changes.try_iter().collect();
+        if changed.is_empty() {
+            return;
+        }
+
+        self.shaders
+            .retain(|watched| watched.program.strong_count() > 0);
+
+        for file in changed {
+            // Changing an include reloads every shader that includes it.
+            let affected = self
+                .shaders
+                .iter()
+                .filter_map(WatchedShader::upgrade)
+                .filter(|shader| {
+                    let program = shader.program();
+                    program.files.iter().any(|path| is_source_of(&file, path))
+                });
+
+            for shader in affected {
+                match context.reload_shader(&shader) {
+                    Ok(()) => info!("Reloaded shader {}", file.display()),
+                    Err(err) => error!("Failed to reload shader, keeping the old one: {}", err),
+                }
+            }
+        }
+    }
+}
+
+// Whether `file` is where the shader loaded from resource path `path` (like
+// "/res/shaders/default.wgsl") comes from.
+fn is_source_of(file: &Path, path: &str) -> bool {
+    file.ends_with(path.trim_start_matches('/'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changed_files_are_matched_to_resource_paths() {
+        let file = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join(SHADER_DIR)
+            .join("default.wgsl");
+
+        assert!(is_source_of(&file, "/res/shaders/default.wgsl"));
+        assert!(!is_source_of(&file, "/res/shaders/fallback.wgsl"));
+        assert!(!is_source_of(&file, "/res/shaders/lib/default.wgsl"));
+    }
+}
@@ -0,0 +1,120 @@
+use crate::rendering::material::Material;
+use crate::rendering::wgpu_context::WGPUContext;
+use wgpu::util::DrawIndexedIndirectArgs;
+use wgpu::{BindGroup, BufferDescriptor, IndexFormat, RenderPass};
+
+const DRAW_ARGS_SIZE: u64 = size_of::<DrawIndexedIndirectArgs>() as u64;
+
+// Meshes sharing one vertex and index buffer (see `MeshPool`) drawn with the same material from
+// an indirect buffer. Batches are drawn in the main pass, after its render objects.
+#[derive(Clone, Debug)]
+pub struct IndirectBatch {
+    pub material: Material,
+    pub vertices: wgpu::Buffer,
+    pub indices: wgpu::Buffer, // u32
+    pub instances: wgpu::Buffer,
+    pub draws: wgpu::Buffer, // `DrawIndexedIndirectArgs`
+    pub draw_count: u32,
+}
+
+impl IndirectBatch {
+    // With `multi_draw`, the device has to support `Features::MULTI_DRAW_INDIRECT`. Otherwise each
+    // draw is issued on its own.
+    pub(crate) fn record(
+        &self,
+        render_pass: &mut RenderPass,
+        global_bind_group: &BindGroup,
+        multi_draw: bool,
+    ) {
+        render_pass.set_pipeline(&self.material.shader.pipeline());
+        render_pass.set_bind_group(0, global_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.material.bind_group, &[]);
+
+        render_pass.set_vertex_buffer(0, self.vertices.slice(..));
+        render_pass.set_vertex_buffer(1, self.instances.slice(..));
+        render_pass.set_index_buffer(self.indices.slice(..), IndexFormat::Uint32);
+
+        if multi_draw {
+            render_pass.multi_draw_indexed_indirect(&self.draws, 0, self.draw_count);
+        } else {
+            for draw in 0..self.draw_count as u64 {
+                render_pass.draw_indexed_indirect(&self.draws, draw * DRAW_ARGS_SIZE);
+            }
+        }
+    }
+}
+
+// An indirect buffer filled from the CPU, kept between frames and recreated when it's too small.
+pub struct IndirectDraws {
+    buffer: wgpu::Buffer,
+    len: u32,
+}
+
+impl IndirectDraws {
+    pub fn new(context: &WGPUContext) -> Self {
+        Self {
+            buffer: create_indirect_buffer(context, "Indirect Draws", 64 * DRAW_ARGS_SIZE),
+            len: 0,
+        }
+    }
+
+    pub fn upload(&mut self, context: &WGPUContext, draws: &[DrawIndexedIndirectArgs]) {
+        let bytes: Vec<u8> = draws
+            .iter()
+            .flat_map(|draw| draw.as_bytes().iter().copied())
+            .collect();
+        reserve(
+            context,
+            &mut self.buffer,
+            bytes.len() as u64,
+            "Indirect Draws",
+        );
+        context.queue.write_buffer(&self.buffer, 0, &bytes);
+        self.len = draws.len() as u32;
+    }
+
+    pub fn buffer(&self) -> &wgpu::Buffer {
+        &self.buffer
+    }
+
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+}
+
+pub(crate) fn create_indirect_buffer(
+    context: &WGPUContext,
+    label: &str,
+    size: u64,
+) -> wgpu::Buffer {
+    context.device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size,
+        usage: wgpu::BufferUsages::INDIRECT
+            | wgpu::BufferUsages::STORAGE
+            | wgpu::BufferUsages::COPY_SRC
+            | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+// Recreates `buffer` with at least `size` bytes if it's smaller, dropping its contents. Returns
+// whether it did, since bind groups referring to the old buffer then have to be rebuilt.
+pub(crate) fn reserve(
+    context: &WGPUContext,
+    buffer: &mut wgpu::Buffer,
+    size: u64,
+    label: &str,
+) -> bool {
+    if size <= buffer.size() {
+        return false;
+    }
+
+    *buffer = context.device.create_buffer(&BufferDescriptor {
+        label: Some(label),
+        size: size.max(buffer.size().saturating_mul(2)),
+        usage: buffer.usage(),
+        mapped_at_creation: false,
+    });
+    true
+}
@@ -0,0 +1,107 @@
+use crate::rendering::buffer::Buffer;
+use crate::rendering::material::Material;
+use crate::rendering::mesh::Mesh;
+use crate::rendering::render_object::{PassType, RenderObject};
+use crate::rendering::renderer::Renderer;
+use bytemuck::{Pod, Zeroable};
+use glam::Mat4;
+use std::collections::HashMap;
+use std::hash::Hash;
+use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexStepMode, vertex_attr_array};
+
+// Per-instance data fed to the vertex shader alongside each mesh vertex.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+pub struct InstanceData {
+    pub model: Mat4,
+}
+
+impl InstanceData {
+    pub(crate) fn desc() -> VertexBufferLayout<'static> {
+        const ATTRIBS: [VertexAttribute; 4] =
+            vertex_attr_array![5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4];
+
+        VertexBufferLayout {
+            array_stride: size_of::<InstanceData>() as BufferAddress,
+            step_mode: VertexStepMode::Instance,
+            attributes: &ATTRIBS,
+        }
+    }
+}
+
+struct InstanceBatch {
+    data: Vec<InstanceData>,      // cpu side
+    buffer: Buffer<InstanceData>, // gpu side
+}
+
+// Collects instances per key (typically a mesh + material pair) over a frame and submits one
+// instanced draw per key. Buffers are kept between frames and only recreated when they grow.
+pub struct InstanceBatcher<Key> {
+    batches: HashMap<Key, InstanceBatch>,
+}
+
+impl<Key> InstanceBatcher<Key>
+where
+    Key: Copy + Eq + Hash,
+{
+    pub fn new() -> Self {
+        Self {
+            batches: HashMap::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for batch in self.batches.values_mut() {
+            batch.data.clear();
+        }
+    }
+
+    pub fn push(&mut self, renderer: &Renderer, key: Key, instance: InstanceData) {
+        self.batches
+            .entry(key)
+            .or_insert_with(|| InstanceBatch {
+                data: vec![],
+                buffer: Buffer::new_instance(renderer.context(), None),
+            })
+            .data
+            .push(instance);
+    }
+
+    // Uploads every non-empty batch and pushes it as a render object. `resolve` maps a key to what
+    // it should be drawn with; keys it can't resolve are skipped.
+    pub fn submit(
+        &mut self,
+        renderer: &mut Renderer,
+        pass: PassType,
+        resolve: impl Fn(Key) -> Option<(Mesh, Material)>,
+    ) {
+        for (key, batch) in &mut self.batches {
+            if batch.data.is_empty() {
+                continue;
+            }
+            let Some((mesh, material)) = resolve(*key) else {
+                continue;
+            };
+
+            batch.buffer.upload(renderer.context(), &batch.data);
+
+            renderer.push_object(&RenderObject {
+                bounds: mesh.instance_bounds(&batch.data),
+                mesh,
+                material,
+                pass,
+                instances: batch.buffer.buffer().clone(),
+                instances_len: batch.data.len() as u32,
+            });
+        }
+    }
+}
+
+impl<Key> Default for InstanceBatcher<Key>
+where
+    Key: Copy + Eq + Hash,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,27 +1,23 @@
-use crate::rendering::render_object::{PassType, RenderObject};
+use crate::rendering::render_graph::{DEPTH, FrameData, GraphPass, PassResources, SCENE};
 use wgpu::{
-    BindGroup, Color, CommandEncoder, IndexFormat, LoadOp, Operations, RenderPassColorAttachment,
-    RenderPassDescriptor, StoreOp, TextureView,
+    Color, CommandEncoder, IndexFormat, LoadOp, Operations, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp,
 };
 
-pub struct FrameData<'a> {
-    pub color: &'a TextureView,
-    pub global_bind_group: &'a BindGroup,
-}
-
+// Clears the scene and depth, then draws opaque objects and indirect batches.
 pub struct MainRenderPass;
 
-impl MainRenderPass {
-    pub fn record(
+impl GraphPass for MainRenderPass {
+    fn record(
         &mut self,
         encoder: &mut CommandEncoder,
-        data: &FrameData,
-        objects: &[&RenderObject],
+        resources: &PassResources,
+        frame: &FrameData,
     ) {
         let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
             label: Some("Main Render Pass"),
             color_attachments: &[Some(RenderPassColorAttachment {
-                view: data.color,
+                view: resources.view(SCENE),
                 depth_slice: None,
                 resolve_target: None,
                 ops: Operations {
@@ -34,24 +30,31 @@ impl MainRenderPass {
                     store: StoreOp::Store,
                 },
             })],
-            depth_stencil_attachment: None,
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: resources.view(DEPTH),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Clear(1.0),
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
             timestamp_writes: None,
             occlusion_query_set: None,
         });
 
-        for &object in objects {
+        for &object in frame.opaque {
             let material = &object.material;
             let shader = &material.shader;
             let mesh = &object.mesh;
 
-            render_pass.set_pipeline(&shader.pipeline);
+            render_pass.set_pipeline(&shader.pipeline());
 
-            render_pass.set_bind_group(0, data.global_bind_group, &[]);
+            render_pass.set_bind_group(0, frame.global_bind_group, &[]);
             render_pass.set_bind_group(1, &material.bind_group, &[]);
 
             render_pass.set_vertex_buffer(0, mesh.vertices.buffer().slice(..));
             render_pass.set_vertex_buffer(1, object.instances.slice(..));
-            render_pass.set_index_buffer(mesh.indices.buffer().slice(..), IndexFormat::Uint16);
+            render_pass.set_index_buffer(mesh.indices.buffer().slice(..), IndexFormat::Uint32);
 
             render_pass.draw_indexed(
                 mesh.start_index..mesh.num_indices,
@@ -59,9 +62,9 @@ impl MainRenderPass {
                 0..object.instances_len,
             );
         }
-    }
 
-    pub fn pass_type(&self) -> PassType {
-        PassType::Opaque
+        for batch in frame.batches {
+            batch.record(&mut render_pass, frame.global_bind_group, frame.multi_draw);
+        }
     }
 }
@@ -1,10 +1,34 @@
+use crate::aabb::Aabb;
 use crate::rendering::buffer::Buffer;
+use crate::rendering::instance::InstanceData;
 use crate::rendering::vertex::Vertex;
+use crate::rendering::wgpu_context::WGPUContext;
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Mesh {
     pub(crate) vertices: Buffer<Vertex>,
-    pub(crate) indices: Buffer<u16>,
+    pub(crate) indices: Buffer<u32>,
     pub(crate) num_indices: u32,
     pub(crate) start_index: u32,
+    pub(crate) bounds: Aabb, // in model space
+}
+
+impl Mesh {
+    pub fn new(context: &WGPUContext, vertices: &[Vertex], indices: &[u32]) -> Self {
+        Self {
+            vertices: Buffer::new_vertex(context, Some(vertices)),
+            indices: Buffer::new_index(context, Some(indices)),
+            num_indices: indices.len() as u32,
+            start_index: 0,
+            bounds: Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into())),
+        }
+    }
+
+    // World space bounds of every instance, None without instances.
+    pub fn instance_bounds(&self, instances: &[InstanceData]) -> Option<Aabb> {
+        instances
+            .iter()
+            .map(|instance| self.bounds.transform(&instance.model))
+            .reduce(|a, b| a.union(&b))
+    }
 }
@@ -0,0 +1,151 @@
+use crate::rendering::allocator::BufferAllocator;
+use crate::rendering::vertex::Vertex;
+use crate::rendering::wgpu_context::WGPUContext;
+use std::ops::Range;
+use wgpu::BufferUsages;
+use wgpu::util::DrawIndexedIndirectArgs;
+
+// Where a mesh lives in a `MeshPool`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolAllocation {
+    pub vertices: Range<u32>,
+    pub indices: Range<u32>,
+}
+
+impl PoolAllocation {
+    // One instance of the mesh, as an entry of an indirect buffer.
+    pub fn draw_args(&self) -> DrawIndexedIndirectArgs {
+        DrawIndexedIndirectArgs {
+            index_count: self.indices.end - self.indices.start,
+            instance_count: 1,
+            first_index: self.indices.start,
+            base_vertex: self.vertices.start as i32,
+            first_instance: 0,
+        }
+    }
+}
+
+// Many meshes packed into one vertex and one index buffer, so they can be drawn without rebinding
+// anything in between, e.g. with a single multi draw. Both buffers grow as meshes are added.
+pub struct MeshPool {
+    vertices: BufferAllocator<Vertex>,
+    indices: BufferAllocator<u32>,
+}
+
+impl MeshPool {
+    // Initial capacities, in vertices and indices.
+    pub fn new(context: &WGPUContext, vertex_capacity: u32, index_capacity: u32) -> Self {
+        Self {
+            vertices: BufferAllocator::new(context, vertex_capacity, BufferUsages::VERTEX),
+            indices: BufferAllocator::new(context, index_capacity, BufferUsages::INDEX),
+        }
+    }
+
+    // Uploads a mesh, or returns None if a buffer would have to grow larger than the device
+    // allows to fit it.
+    pub fn insert(
+        &mut self,
+        context: &WGPUContext,
+        vertices: &[Vertex],
+        indices: &[u32],
+    ) -> Option<PoolAllocation> {
+        let vertex_range = self.vertices.allocate(context, vertices)?;
+
+        let Some(index_range) = self.indices.allocate(context, indices) else {
+            self.vertices.free(vertex_range);
+            return None;
+        };
+
+        Some(PoolAllocation {
+            vertices: vertex_range,
+            indices: index_range,
+        })
+    }
+
+    pub fn remove(&mut self, allocation: PoolAllocation) {
+        self.vertices.free(allocation.vertices);
+        self.indices.free(allocation.indices);
+    }
+
+    // Both buffers are recreated when they grow, so draws should fetch them again after inserts.
+    pub fn vertex_buffer(&self) -> &wgpu::Buffer {
+        self.vertices.buffer()
+    }
+
+    pub fn index_buffer(&self) -> &wgpu::Buffer {
+        self.indices.buffer()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(layer: u32) -> Vec<Vertex> {
+        (0..3)
+            .map(|i| Vertex {
+                position: [i as f32, 0.0, 0.0],
+                tex_coords: [0.0; 2],
+                layer,
+            })
+            .collect()
+    }
+
+    // The vertices and indices `args` draws, read back from the pool's buffers.
+    fn drawn(
+        context: &WGPUContext,
+        pool: &MeshPool,
+        args: &DrawIndexedIndirectArgs,
+    ) -> (Vec<Vertex>, Vec<u32>) {
+        let indices = context.read_buffer(pool.index_buffer());
+        let indices: &[u32] = bytemuck::cast_slice(&indices);
+        let indices = &indices[args.first_index as usize..][..args.index_count as usize];
+
+        let vertices = context.read_buffer(pool.vertex_buffer());
+        let vertices: &[Vertex] = bytemuck::cast_slice(&vertices);
+        let vertices = indices
+            .iter()
+            .map(|&index| vertices[args.base_vertex as usize + index as usize])
+            .collect();
+
+        (vertices, indices.to_vec())
+    }
+
+    #[test]
+    fn draws_point_at_the_uploaded_meshes_and_freed_ranges_are_reused() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let mut pool = MeshPool::new(&context, 8, 8);
+        let a = pool.insert(&context, &triangle(1), &[0, 1, 2]).unwrap();
+        let b = pool.insert(&context, &triangle(2), &[2, 1, 0]).unwrap();
+
+        assert_eq!((a.indices.clone(), b.indices.clone()), (0..3, 3..6));
+        assert_eq!(a.draw_args().index_count, 3);
+        let (vertices, indices) = drawn(&context, &pool, &b.draw_args());
+        assert_eq!(indices, [2, 1, 0]);
+        assert_eq!(vertices, [2, 1, 0].map(|i| triangle(2)[i]));
+
+        pool.remove(a.clone());
+        let c = pool.insert(&context, &triangle(3), &[1, 2, 0]).unwrap();
+        assert_eq!(c, a);
+        let (vertices, indices) = drawn(&context, &pool, &c.draw_args());
+        assert_eq!(indices, [1, 2, 0]);
+        assert_eq!(vertices, [1, 2, 0].map(|i| triangle(3)[i]));
+
+        // B survived the reinsert, and still does once the buffers grow for a larger mesh.
+        let d = pool
+            .insert(
+                &context,
+                &[triangle(4), triangle(4)].concat(),
+                &[0, 1, 2, 3, 4, 5],
+            )
+            .unwrap();
+        assert_eq!(d.draw_args().base_vertex, 6);
+        let (vertices, _) = drawn(&context, &pool, &b.draw_args());
+        assert_eq!(vertices, [2, 1, 0].map(|i| triangle(2)[i]));
+        let (vertices, indices) = drawn(&context, &pool, &d.draw_args());
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(vertices, [triangle(4), triangle(4)].concat());
+    }
+}
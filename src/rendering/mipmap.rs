@@ -0,0 +1,108 @@
+use image::{Rgba, RgbaImage};
+use std::sync::LazyLock;
+
+// sRGB byte to linear intensity, so averaging happens in linear space. Averaging the bytes
+// directly darkens every level.
+static SRGB_TO_LINEAR: LazyLock<[f32; 256]> = LazyLock::new(|| {
+    std::array::from_fn(|value| {
+        let value = value as f32 / 255.0;
+        if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        }
+    })
+});
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+// Levels in a full chain, down to 1x1.
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    width.max(height).max(1).ilog2() + 1
+}
+
+// `image` followed by every smaller level down to 1x1.
+pub fn mip_chain(image: RgbaImage) -> Vec<RgbaImage> {
+    let count = mip_level_count(image.width(), image.height());
+
+    let mut levels = Vec::with_capacity(count as usize);
+    levels.push(image);
+    for _ in 1..count {
+        let next = downsample(levels.last().unwrap());
+        levels.push(next);
+    }
+
+    levels
+}
+
+// Halves both dimensions (rounding down, but at least 1), averaging each 2x2 block. Colors are
+// treated as sRGB and alpha as linear.
+pub fn downsample(image: &RgbaImage) -> RgbaImage {
+    let (width, height) = image.dimensions();
+
+    RgbaImage::from_fn((width / 2).max(1), (height / 2).max(1), |x, y| {
+        let mut sum = [0.0f32; 4];
+        for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+            let pixel = image.get_pixel((2 * x + dx).min(width - 1), (2 * y + dy).min(height - 1));
+            for channel in 0..3 {
+                sum[channel] += SRGB_TO_LINEAR[pixel[channel] as usize];
+            }
+            sum[3] += pixel[3] as f32;
+        }
+
+        Rgba([
+            linear_to_srgb(sum[0] / 4.0),
+            linear_to_srgb(sum[1] / 4.0),
+            linear_to_srgb(sum[2] / 4.0),
+            (sum[3] / 4.0).round() as u8,
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_go_down_to_a_single_texel() {
+        let sizes: Vec<(u32, u32)> = mip_chain(RgbaImage::new(16, 4))
+            .iter()
+            .map(RgbaImage::dimensions)
+            .collect();
+        assert_eq!(sizes, [(16, 4), (8, 2), (4, 1), (2, 1), (1, 1)]);
+
+        assert_eq!(mip_level_count(5, 3), 3);
+        assert_eq!(mip_chain(RgbaImage::new(1, 1)).len(), 1);
+    }
+
+    #[test]
+    fn averages_are_gamma_correct() {
+        let checker = RgbaImage::from_fn(2, 2, |x, y| {
+            if (x + y) % 2 == 0 {
+                Rgba([0, 0, 0, 0])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+
+        // Half the light of white, which is brighter than the byte halfway between.
+        assert_eq!(
+            downsample(&checker).get_pixel(0, 0),
+            &Rgba([188, 188, 188, 128])
+        );
+
+        let solid = RgbaImage::from_pixel(4, 4, Rgba([93, 17, 200, 255]));
+        assert_eq!(
+            downsample(&solid).get_pixel(1, 1),
+            &Rgba([93, 17, 200, 255])
+        );
+    }
+}
@@ -1,13 +1,32 @@
+pub mod allocator;
+pub mod atlas;
 pub mod buffer;
 pub mod camera;
+pub mod compressed;
+pub mod compute;
+pub mod frustum;
 pub mod global_bindings;
+pub mod gpu_cull;
+#[cfg(debug_assertions)]
+pub mod hot_reload;
+pub mod indirect;
+pub mod instance;
 pub mod main_pass;
 pub mod material;
 pub mod mesh;
+pub mod mesh_pool;
+pub mod mipmap;
+pub mod pipeline;
+pub mod post_process;
+pub mod preprocessor;
+pub mod reflection;
+pub mod render_graph;
 pub mod render_object;
 pub mod renderer;
 pub mod shader;
+pub mod svo_volume;
 pub mod texture;
+pub mod transparent_pass;
 pub mod utils;
 pub mod vertex;
-mod wgpu_context;
+pub(crate) mod wgpu_context;
@@ -0,0 +1,220 @@
+use crate::rendering::instance::InstanceData;
+use crate::rendering::texture::DEPTH_FORMAT;
+use crate::rendering::vertex::Vertex;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use wgpu::{
+    BindGroupLayout, BlendState, ColorWrites, CompareFunction, Face, FrontFace, PrimitiveTopology,
+    RenderPipeline, ShaderModule, TextureFormat, VertexBufferLayout,
+};
+
+// Everything about how a shader is drawn apart from the shader module and its bind group layouts.
+// The default matches the standard mesh setup: `Vertex` + `InstanceData` buffers, back-face
+// culling, depth tested and written, and a single opaque target in the surface format.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct PipelineDescriptor {
+    pub vertex_layouts: Vec<VertexBufferLayout<'static>>,
+    pub vertex_entry: String,
+    pub fragment_entry: Option<String>, // None for depth-only pipelines
+    pub topology: PrimitiveTopology,
+    pub front_face: FrontFace,
+    pub cull_mode: Option<Face>,
+    pub depth: Option<DepthState>,
+    pub targets: Vec<ColorTarget>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DepthState {
+    pub format: TextureFormat,
+    pub write: bool,
+    pub compare: CompareFunction,
+}
+
+impl Default for DepthState {
+    fn default() -> Self {
+        Self {
+            format: DEPTH_FORMAT,
+            write: true,
+            compare: CompareFunction::Less,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ColorTarget {
+    pub format: Option<TextureFormat>, // None uses the surface format
+    pub blend: Option<BlendState>,
+    pub write_mask: ColorWrites,
+}
+
+impl Default for ColorTarget {
+    fn default() -> Self {
+        Self {
+            format: None,
+            blend: Some(BlendState::REPLACE),
+            write_mask: ColorWrites::ALL,
+        }
+    }
+}
+
+impl Default for PipelineDescriptor {
+    fn default() -> Self {
+        Self {
+            vertex_layouts: vec![Vertex::desc(), InstanceData::desc()],
+            vertex_entry: "vs_main".to_owned(),
+            fragment_entry: Some("fs_main".to_owned()),
+            topology: PrimitiveTopology::TriangleList,
+            front_face: FrontFace::Ccw,
+            cull_mode: Some(Face::Back),
+            depth: Some(DepthState::default()),
+            targets: vec![ColorTarget::default()],
+        }
+    }
+}
+
+impl PipelineDescriptor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Alpha blended and depth tested, but not depth written, so that blended surfaces behind each
+    // other all show up when drawn back to front.
+    pub fn transparent() -> Self {
+        Self::new()
+            .with_blend(BlendState::ALPHA_BLENDING)
+            .with_depth_write(false)
+    }
+
+    pub fn with_vertex_layouts(mut self, layouts: Vec<VertexBufferLayout<'static>>) -> Self {
+        self.vertex_layouts = layouts;
+
+        self
+    }
+
+    pub fn with_cull_mode(mut self, cull_mode: Option<Face>) -> Self {
+        self.cull_mode = cull_mode;
+
+        self
+    }
+
+    pub fn with_depth(mut self, depth: Option<DepthState>) -> Self {
+        self.depth = depth;
+
+        self
+    }
+
+    pub fn with_depth_write(mut self, write: bool) -> Self {
+        if let Some(depth) = &mut self.depth {
+            depth.write = write;
+        }
+
+        self
+    }
+
+    // Applies to every color target.
+    pub fn with_blend(mut self, blend: BlendState) -> Self {
+        for target in &mut self.targets {
+            target.blend = Some(blend);
+        }
+
+        self
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct PipelineKey {
+    module: ShaderModule,
+    layouts: Vec<BindGroupLayout>,
+    descriptor: PipelineDescriptor,
+}
+
+impl PipelineKey {
+    fn new(
+        module: &ShaderModule,
+        layouts: &[&BindGroupLayout],
+        descriptor: &PipelineDescriptor,
+    ) -> Self {
+        Self {
+            module: module.clone(),
+            layouts: layouts.iter().map(|&layout| layout.clone()).collect(),
+            descriptor: descriptor.clone(),
+        }
+    }
+}
+
+// Shares render pipelines between shaders and materials that ask for the same module, layouts
+// and descriptor.
+#[derive(Default)]
+pub(crate) struct PipelineCache {
+    pipelines: RefCell<HashMap<PipelineKey, RenderPipeline>>,
+}
+
+impl PipelineCache {
+    pub(crate) fn get_or_create(
+        &self,
+        module: &ShaderModule,
+        layouts: &[&BindGroupLayout],
+        descriptor: &PipelineDescriptor,
+        create: impl FnOnce() -> RenderPipeline,
+    ) -> RenderPipeline {
+        self.pipelines
+            .borrow_mut()
+            .entry(PipelineKey::new(module, layouts, descriptor))
+            .or_insert_with(create)
+            .clone()
+    }
+
+    // Drops the pipeline for one configuration, e.g. because it failed to create.
+    pub(crate) fn remove(
+        &self,
+        module: &ShaderModule,
+        layouts: &[&BindGroupLayout],
+        descriptor: &PipelineDescriptor,
+    ) {
+        self.pipelines
+            .borrow_mut()
+            .remove(&PipelineKey::new(module, layouts, descriptor));
+    }
+
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.pipelines.borrow().len()
+    }
+
+    // Drops every pipeline created for `module`, once it's been replaced.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub(crate) fn remove_module(&self, module: &ShaderModule) {
+        self.pipelines
+            .borrow_mut()
+            .retain(|key, _| key.module != *module);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::hash::{BuildHasher, RandomState};
+
+    #[test]
+    fn identical_descriptors_hash_the_same() {
+        let hasher = RandomState::new();
+        let transparent = PipelineDescriptor::transparent();
+
+        assert_eq!(
+            hasher.hash_one(&transparent),
+            hasher.hash_one(PipelineDescriptor::transparent())
+        );
+        assert_ne!(transparent, PipelineDescriptor::new());
+        assert_ne!(
+            PipelineDescriptor::new().with_cull_mode(None),
+            PipelineDescriptor::new()
+        );
+    }
+
+    #[test]
+    fn transparent_keeps_depth_testing() {
+        let depth = PipelineDescriptor::transparent().depth.unwrap();
+        assert!(!depth.write);
+        assert_eq!(depth.compare, CompareFunction::Less);
+    }
+}
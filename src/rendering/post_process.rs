@@ -0,0 +1,96 @@
+use crate::assets::source::AssetSource;
+use crate::rendering::pipeline::PipelineDescriptor;
+use crate::rendering::preprocessor::{BUILTIN_SHADERS, ShaderDefines, preprocess};
+use crate::rendering::render_graph::{FrameData, GraphPass, PassResources, SCENE, SURFACE};
+use crate::rendering::utils::bind_group_builder::BindGroupBuilder;
+use crate::rendering::wgpu_context::{CreateShaderError, WGPUContext};
+use wgpu::{
+    BindGroup, BindGroupLayout, BindGroupLayoutDescriptor, Color, CommandEncoder, LoadOp,
+    Operations, RenderPassColorAttachment, RenderPassDescriptor, RenderPipeline, StoreOp,
+    TextureView,
+};
+
+pub const POST_PROCESS_SHADER: &str = "/res/shaders/post_process.wgsl";
+
+// Draws the scene to the surface through a full screen effect.
+pub struct PostProcessPass {
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    // Rebuilt when the graph gives the scene a new texture, e.g. after a resize.
+    bind_group: Option<(TextureView, BindGroup)>,
+}
+
+impl PostProcessPass {
+    pub fn new(context: &WGPUContext) -> Result<Self, CreateShaderError> {
+        let src = preprocess(
+            POST_PROCESS_SHADER,
+            &ShaderDefines::new(),
+            &AssetSource::new().with_embedded(BUILTIN_SHADERS),
+        )?;
+        let (module, reflection) = context.compile_shader(POST_PROCESS_SHADER, &src)?;
+
+        let layout = context
+            .device
+            .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some("Post Process Layout"),
+                entries: reflection.group(0),
+            });
+        let pipeline = context.create_render_pipeline(
+            &module,
+            &[&layout],
+            &PipelineDescriptor::new()
+                .with_vertex_layouts(vec![])
+                .with_cull_mode(None)
+                .with_depth(None),
+        );
+
+        Ok(Self {
+            pipeline,
+            layout,
+            bind_group: None,
+        })
+    }
+}
+
+impl GraphPass for PostProcessPass {
+    fn record(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        resources: &PassResources,
+        frame: &FrameData,
+    ) {
+        let scene = resources.view(SCENE);
+        if self
+            .bind_group
+            .as_ref()
+            .is_none_or(|(view, _)| view != scene)
+        {
+            let bind_group = BindGroupBuilder::new().with_texture2d(0, scene).build(
+                frame.context,
+                &self.layout,
+                Some("Post Process Bind Group"),
+            );
+            self.bind_group = Some((scene.clone(), bind_group));
+        }
+        let (_, bind_group) = self.bind_group.as_ref().unwrap();
+
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Post Process Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: resources.view(SURFACE),
+                depth_slice: None,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Clear(Color::BLACK), // every pixel is drawn over
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
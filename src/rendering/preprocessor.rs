@@ -0,0 +1,377 @@
+use crate::assets::source::{AssetError, AssetSource, EmbeddedAssets};
+use std::collections::{BTreeMap, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PreprocessError {
+    #[error(transparent)]
+    Asset(#[from] AssetError),
+    #[error("{file}:{line}: Failed to include '{include}': {source}")]
+    Include {
+        file: String,
+        line: u32,
+        include: String,
+        source: AssetError,
+    },
+    #[error("{file}:{line}: {message}")]
+    Directive {
+        file: String,
+        line: u32,
+        message: String,
+    },
+}
+
+// Shaders built into the binary, so the fallback and the cull and post-processing passes work even
+// without the asset directories.
+pub const BUILTIN_SHADERS: EmbeddedAssets = &[
+    (
+        "/res/shaders/cull_chunks.wgsl",
+        include_bytes!("../../res/shaders/cull_chunks.wgsl"),
+    ),
+    (
+        "/res/shaders/fallback.wgsl",
+        include_bytes!("../../res/shaders/fallback.wgsl"),
+    ),
+    (
+        "/res/shaders/include/globals.wgsl",
+        include_bytes!("../../res/shaders/include/globals.wgsl"),
+    ),
+    (
+        "/res/shaders/include/mesh.wgsl",
+        include_bytes!("../../res/shaders/include/mesh.wgsl"),
+    ),
+    (
+        "/res/shaders/post_process.wgsl",
+        include_bytes!("../../res/shaders/post_process.wgsl"),
+    ),
+];
+
+// Compile-time switches for `#ifdef` and values substituted into the source, passed from Rust.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ShaderDefines {
+    defines: BTreeMap<String, String>,
+}
+
+impl ShaderDefines {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    pub fn with(self, name: &str) -> Self {
+        self.with_value(name, "")
+    }
+
+    #[cfg(test)]
+    pub fn with_value(mut self, name: &str, value: &str) -> Self {
+        self.defines.insert(name.to_owned(), value.to_owned());
+
+        self
+    }
+}
+
+// Where a line of preprocessed output came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceLine {
+    pub file: String,
+    pub line: u32, // 1-based
+}
+
+#[derive(Clone, Debug)]
+pub struct PreprocessedShader {
+    pub source: String,
+    pub files: Vec<String>, // the shader itself and everything it includes
+    lines: Vec<SourceLine>,
+}
+
+impl PreprocessedShader {
+    // Maps a 1-based line of `source` back to the file it was written in.
+    pub fn origin(&self, line: u32) -> Option<&SourceLine> {
+        self.lines.get(line.checked_sub(1)? as usize)
+    }
+}
+
+// Expands `#include "file"`, `#define NAME [value]`, `#ifdef NAME`, `#ifndef NAME`, `#else` and
+// `#endif`. Included paths are relative to the including file, and each file is only included
+// once, so shared declarations can be included from anywhere.
+pub fn preprocess(
+    path: &str,
+    defines: &ShaderDefines,
+    assets: &AssetSource,
+) -> Result<PreprocessedShader, PreprocessError> {
+    let src = assets.read_to_string(path)?;
+
+    let mut state = State {
+        defines: defines.defines.clone(),
+        included: HashSet::from([path.to_owned()]),
+        output: PreprocessedShader {
+            source: String::new(),
+            files: vec![path.to_owned()],
+            lines: vec![],
+        },
+    };
+    state.process(path, &src, assets)?;
+
+    Ok(state.output)
+}
+
+struct State {
+    defines: BTreeMap<String, String>,
+    included: HashSet<String>,
+    output: PreprocessedShader,
+}
+
+struct Conditional {
+    active: bool,        // whether lines in the current branch are emitted
+    parent_active: bool, // whether the enclosing block is emitted at all
+    seen_else: bool,
+}
+
+impl State {
+    fn process(
+        &mut self,
+        file: &str,
+        src: &str,
+        assets: &AssetSource,
+    ) -> Result<(), PreprocessError> {
+        let mut conditionals: Vec<Conditional> = vec![];
+
+        for (index, text) in src.lines().enumerate() {
+            let line = index as u32 + 1;
+            let error = |message: &str| PreprocessError::Directive {
+                file: file.to_owned(),
+                line,
+                message: message.to_owned(),
+            };
+            let active = conditionals.last().is_none_or(|c| c.active);
+
+            let Some(directive) = text.trim_start().strip_prefix('#') else {
+                if active {
+                    self.emit(file, line, &self.substitute(text));
+                }
+                continue;
+            };
+
+            let (name, argument) = directive
+                .split_once(char::is_whitespace)
+                .map_or((directive, ""), |(name, argument)| (name, argument.trim()));
+
+            match name {
+                "ifdef" | "ifndef" => {
+                    if argument.is_empty() {
+                        return Err(error(&format!("#{name} needs a name")));
+                    }
+                    let defined = self.defines.contains_key(argument);
+                    conditionals.push(Conditional {
+                        active: active && defined == (name == "ifdef"),
+                        parent_active: active,
+                        seen_else: false,
+                    });
+                }
+                "else" => {
+                    let conditional = conditionals
+                        .last_mut()
+                        .filter(|c| !c.seen_else)
+                        .ok_or_else(|| error("#else without #ifdef"))?;
+                    conditional.active = conditional.parent_active && !conditional.active;
+                    conditional.seen_else = true;
+                }
+                "endif" => {
+                    conditionals
+                        .pop()
+                        .ok_or_else(|| error("#endif without #ifdef"))?;
+                }
+                _ if !active => {} // anything else in a disabled branch is skipped unchecked
+                "define" => {
+                    let (name, value) = argument
+                        .split_once(char::is_whitespace)
+                        .map_or((argument, ""), |(name, value)| (name, value.trim()));
+                    if name.is_empty() {
+                        return Err(error("#define needs a name"));
+                    }
+                    self.defines.insert(name.to_owned(), value.to_owned());
+                }
+                "include" => {
+                    let include = argument
+                        .strip_prefix('"')
+                        .and_then(|argument| argument.strip_suffix('"'))
+                        .ok_or_else(|| error("#include expects a quoted path"))?;
+                    let path = resolve(file, include);
+                    if !self.included.insert(path.clone()) {
+                        continue;
+                    }
+
+                    let src = assets.read_to_string(&path).map_err(|source| {
+                        PreprocessError::Include {
+                            file: file.to_owned(),
+                            line,
+                            include: include.to_owned(),
+                            source,
+                        }
+                    })?;
+                    self.output.files.push(path.clone());
+                    self.process(&path, &src, assets)?;
+                }
+                _ => return Err(error(&format!("Unknown directive #{name}"))),
+            }
+        }
+
+        if !conditionals.is_empty() {
+            return Err(PreprocessError::Directive {
+                file: file.to_owned(),
+                line: src.lines().count() as u32,
+                message: "#ifdef without #endif".to_owned(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn emit(&mut self, file: &str, line: u32, text: &str) {
+        self.output.source += text;
+        self.output.source.push('\n');
+        self.output.lines.push(SourceLine {
+            file: file.to_owned(),
+            line,
+        });
+    }
+
+    // Replaces whole identifiers that have a defined value.
+    fn substitute(&self, text: &str) -> String {
+        if self.defines.values().all(String::is_empty) {
+            return text.to_owned();
+        }
+
+        let mut output = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(is_identifier_start) {
+            output += &rest[..start];
+            rest = &rest[start..];
+
+            let end = rest.find(|c: char| !is_identifier(c)).unwrap_or(rest.len());
+            let identifier = &rest[..end];
+            match self.defines.get(identifier) {
+                Some(value) if !value.is_empty() => output += value,
+                _ => output += identifier,
+            }
+            rest = &rest[end..];
+        }
+        output += rest;
+
+        output
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_'
+}
+
+fn is_identifier(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+// Include paths are relative to the including file's directory.
+fn resolve(file: &str, include: &str) -> String {
+    let dir = file.rsplit_once('/').map_or("", |(dir, _)| dir);
+
+    let mut parts: Vec<&str> = dir.split('/').collect();
+    for part in include.split('/') {
+        match part {
+            "." => {}
+            ".." => {
+                parts.pop();
+            }
+            part => parts.push(part),
+        }
+    }
+
+    parts.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files(files: &[(&'static str, &'static str)]) -> AssetSource {
+        let files: Vec<_> = files
+            .iter()
+            .map(|(path, src)| (*path, src.as_bytes()))
+            .collect();
+
+        AssetSource::new().with_embedded(files.leak())
+    }
+
+    #[test]
+    fn includes_are_expanded_once_and_mapped_back() {
+        let assets = files(&[
+            (
+                "/shaders/main.wgsl",
+                "#include \"lib/a.wgsl\"\n#include \"lib/b.wgsl\"\nmain",
+            ),
+            ("/shaders/lib/a.wgsl", "#include \"common.wgsl\"\na"),
+            ("/shaders/lib/b.wgsl", "#include \"./common.wgsl\"\nb"),
+            ("/shaders/lib/common.wgsl", "common"),
+        ]);
+
+        let shader = preprocess("/shaders/main.wgsl", &ShaderDefines::new(), &assets).unwrap();
+        assert_eq!(shader.source, "common\na\nb\nmain\n");
+        assert_eq!(
+            shader.origin(3),
+            Some(&SourceLine {
+                file: "/shaders/lib/b.wgsl".to_owned(),
+                line: 2
+            })
+        );
+        assert_eq!(shader.files.len(), 4);
+    }
+
+    #[test]
+    fn ifdef_follows_defines() {
+        let assets = files(&[(
+            "/main.wgsl",
+            "#ifdef FOG\nfog\n#ifndef CHEAP\nfancy\n#endif\n#else\nplain\n#endif\nend",
+        )]);
+
+        let plain = preprocess("/main.wgsl", &ShaderDefines::new(), &assets).unwrap();
+        assert_eq!(plain.source, "plain\nend\n");
+
+        let fog = preprocess("/main.wgsl", &ShaderDefines::new().with("FOG"), &assets).unwrap();
+        assert_eq!(fog.source, "fog\nfancy\nend\n");
+
+        let cheap = ShaderDefines::new().with("FOG").with("CHEAP");
+        assert_eq!(
+            preprocess("/main.wgsl", &cheap, &assets).unwrap().source,
+            "fog\nend\n"
+        );
+    }
+
+    #[test]
+    fn define_values_are_substituted() {
+        let assets = files(&[(
+            "/main.wgsl",
+            "#define KERNEL 4\nconst size = KERNEL * SCALE;\nlet kernel_size = 1;",
+        )]);
+
+        let defines = ShaderDefines::new().with_value("SCALE", "2.0");
+        let shader = preprocess("/main.wgsl", &defines, &assets).unwrap();
+        assert_eq!(
+            shader.source,
+            "const size = 4 * 2.0;\nlet kernel_size = 1;\n"
+        );
+    }
+
+    #[test]
+    fn malformed_directives_are_errors() {
+        let unterminated = files(&[("/main.wgsl", "#ifdef A\na")]);
+        assert!(matches!(
+            preprocess("/main.wgsl", &ShaderDefines::new(), &unterminated),
+            Err(PreprocessError::Directive { .. })
+        ));
+
+        let missing = files(&[("/main.wgsl", "a\n#include \"missing.wgsl\"")]);
+        assert!(matches!(
+            preprocess("/main.wgsl", &ShaderDefines::new(), &missing),
+            Err(PreprocessError::Include { line: 2, .. })
+        ));
+    }
+}
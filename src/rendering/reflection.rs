@@ -0,0 +1,958 @@
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+use naga::{
+    AddressSpace, ArraySize, Binding, ImageClass, ImageDimension, Module, ScalarKind,
+    StorageFormat, Type, TypeInner, VectorSize,
+};
+use naga::{SourceLocation, Span};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::num::NonZeroU32;
+use thiserror::Error;
+use wgpu::{
+    BindGroupLayoutEntry, BindingType, BufferBindingType, SamplerBindingType, ShaderStages,
+    StorageTextureAccess, TextureFormat, TextureSampleType, TextureViewDimension,
+    VertexBufferLayout, VertexFormat,
+};
+
+#[derive(Error, Debug)]
+pub enum ReflectionError {
+    // Syntax and validation errors. Line and column are 1-based, or 0 if naga couldn't tell.
+    #[error("{line}:{column}: {message}")]
+    Compile {
+        line: u32,
+        column: u32,
+        message: String,
+    },
+    #[error("Binding @group({group}) @binding({binding}) has a type that can't be bound: {ty}.")]
+    UnsupportedBinding {
+        group: u32,
+        binding: u32,
+        ty: String,
+    },
+}
+
+// Differences between what a shader declares and what it is being used with.
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BindingMismatchError {
+    #[error(
+        "Shader declares @group({group}) @binding({binding}) as {expected}, but nothing is bound there."
+    )]
+    MissingBinding {
+        group: u32,
+        binding: u32,
+        expected: String,
+    },
+    #[error("@group({group}) @binding({binding}) is supplied, but the shader doesn't declare it.")]
+    UnexpectedBinding { group: u32, binding: u32 },
+    #[error(
+        "Shader declares @group({group}) @binding({binding}) as {expected}, but {found} is bound."
+    )]
+    WrongType {
+        group: u32,
+        binding: u32,
+        expected: String,
+        found: String,
+    },
+    #[error(
+        "Shader declares @group({group}) @binding({binding}) as {expected}, but {found} is bound."
+    )]
+    WrongCount {
+        group: u32,
+        binding: u32,
+        expected: String,
+        found: String,
+    },
+    #[error("Shader uses @group({0}), but only groups 0 (global) and 1 (material) are supported.")]
+    UnsupportedGroup(u32),
+    #[error("Shader has no vertex entry point named '{0}'.")]
+    MissingEntryPoint(String),
+    #[error("Shader has no compute entry point named '{0}'.")]
+    MissingComputeEntryPoint(String),
+    #[error("Shader uses @group({group}), but the device only supports {max} bind groups.")]
+    TooManyGroups { group: u32, max: u32 },
+    #[error("Shader doesn't declare @group({0}).")]
+    UndeclaredGroup(u32),
+    #[error(
+        "Shader expects vertex input @location({location}) as {expected:?}, but no vertex buffer provides it."
+    )]
+    MissingVertexInput {
+        location: u32,
+        expected: VertexFormat,
+    },
+    #[error(
+        "Shader expects vertex input @location({location}) as {expected:?}, but it is provided as {found:?}."
+    )]
+    VertexFormatMismatch {
+        location: u32,
+        expected: VertexFormat,
+        found: VertexFormat,
+    },
+}
+
+// A resource in a bind group, as the `BindGroupBuilder` method that added it describes it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BoundResource {
+    pub binding: u32,
+    pub ty: BindingType,
+    pub count: Option<NonZeroU32>, // for `binding_array`s
+}
+
+// What a WGSL module declares: its resource bindings per group, the vertex inputs of each vertex
+// entry point and the workgroup size of each compute entry point.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    groups: BTreeMap<u32, Vec<BindGroupLayoutEntry>>,
+    // (group, binding) of `binding_array`s without a size. They're reflected with a count of 1,
+    // but any count can be bound.
+    runtime_sized: HashSet<(u32, u32)>,
+    vertex_inputs: HashMap<String, BTreeMap<u32, VertexFormat>>,
+    workgroup_sizes: HashMap<String, [u32; 3]>,
+}
+
+impl ShaderReflection {
+    pub fn from_wgsl(src: &str) -> Result<Self, ReflectionError> {
+        let module = naga::front::wgsl::parse_str(src).map_err(|err| {
+            let location = first_location(src, err.labels().map(|(span, _)| span));
+            compile_error(location, err.message().to_owned())
+        })?;
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|err| {
+                let location = first_location(src, err.spans().map(|(span, _)| *span));
+                compile_error(location, error_chain(err.as_inner()))
+            })?;
+
+        let all_stages = module
+            .entry_points
+            .iter()
+            .fold(ShaderStages::NONE, |stages, entry_point| {
+                stages | stage_flag(entry_point.stage)
+            });
+
+        let mut groups: BTreeMap<u32, Vec<BindGroupLayoutEntry>> = BTreeMap::new();
+        let mut runtime_sized = HashSet::new();
+        for (handle, global) in module.global_variables.iter() {
+            let Some(binding) = &global.binding else {
+                continue;
+            };
+
+            // Only stages that actually touch the resource see it. Unused bindings are still part
+            // of the layout, so they're made visible everywhere.
+            let mut visibility = ShaderStages::NONE;
+            for (index, entry_point) in module.entry_points.iter().enumerate() {
+                if !info.get_entry_point(index)[handle].is_empty() {
+                    visibility |= stage_flag(entry_point.stage);
+                }
+            }
+            if visibility.is_empty() {
+                visibility = all_stages;
+            }
+
+            let (ty, size) = binding_type(&module, global.space, &module.types[global.ty])
+                .ok_or_else(|| ReflectionError::UnsupportedBinding {
+                    group: binding.group,
+                    binding: binding.binding,
+                    ty: format!("{:?}", module.types[global.ty].inner),
+                })?;
+            let count = match size {
+                None => None,
+                Some(ArraySize::Constant(count)) => Some(count),
+                Some(_) => {
+                    runtime_sized.insert((binding.group, binding.binding));
+                    NonZeroU32::new(1)
+                }
+            };
+
+            groups
+                .entry(binding.group)
+                .or_default()
+                .push(BindGroupLayoutEntry {
+                    binding: binding.binding,
+                    visibility,
+                    ty,
+                    count,
+                });
+        }
+        for entries in groups.values_mut() {
+            entries.sort_by_key(|entry| entry.binding);
+        }
+
+        let vertex_inputs = module
+            .entry_points
+            .iter()
+            .filter(|entry_point| entry_point.stage == naga::ShaderStage::Vertex)
+            .map(|entry_point| {
+                let mut inputs = BTreeMap::new();
+                for argument in &entry_point.function.arguments {
+                    collect_vertex_inputs(&module, argument.ty, &argument.binding, &mut inputs);
+                }
+                (entry_point.name.clone(), inputs)
+            })
+            .collect();
+
+        let workgroup_sizes = module
+            .entry_points
+            .iter()
+            .filter(|entry_point| entry_point.stage == naga::ShaderStage::Compute)
+            .map(|entry_point| (entry_point.name.clone(), entry_point.workgroup_size))
+            .collect();
+
+        Ok(Self {
+            groups,
+            runtime_sized,
+            vertex_inputs,
+            workgroup_sizes,
+        })
+    }
+
+    // The layout entries the shader declares for `group`, sorted by binding.
+    pub fn group(&self, group: u32) -> &[BindGroupLayoutEntry] {
+        self.groups.get(&group).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn groups(&self) -> impl Iterator<Item = u32> + '_ {
+        self.groups.keys().copied()
+    }
+
+    pub fn vertex_inputs(&self, entry_point: &str) -> Option<&BTreeMap<u32, VertexFormat>> {
+        self.vertex_inputs.get(entry_point)
+    }
+
+    // The `@workgroup_size` of a compute entry point, with unspecified dimensions as 1.
+    pub fn workgroup_size(&self, entry_point: &str) -> Result<[u32; 3], BindingMismatchError> {
+        self.workgroup_sizes
+            .get(entry_point)
+            .copied()
+            .ok_or_else(|| BindingMismatchError::MissingComputeEntryPoint(entry_point.to_owned()))
+    }
+
+    // Checks a hand-built layout against the shader. Every declared binding has to be present with
+    // a compatible type, the same array count and at least the stages that use it; extra bindings
+    // are not allowed. Arrays without a size in the shader take any count.
+    pub fn check_layout(
+        &self,
+        group: u32,
+        supplied: &[BindGroupLayoutEntry],
+    ) -> Result<(), BindingMismatchError> {
+        let declared = self.group(group);
+
+        for expected in declared {
+            let Some(found) = supplied.iter().find(|e| e.binding == expected.binding) else {
+                return Err(BindingMismatchError::MissingBinding {
+                    group,
+                    binding: expected.binding,
+                    expected: describe(&expected.ty),
+                });
+            };
+
+            let count_matches = if self.runtime_sized.contains(&(group, expected.binding)) {
+                found.count.is_some()
+            } else {
+                expected.count == found.count
+            };
+            if !types_compatible(&expected.ty, &found.ty)
+                || !count_matches
+                || !found.visibility.contains(expected.visibility)
+            {
+                return Err(BindingMismatchError::WrongType {
+                    group,
+                    binding: expected.binding,
+                    expected: describe(&expected.ty),
+                    found: describe(&found.ty),
+                });
+            }
+        }
+
+        Self::check_unexpected(group, declared, supplied.iter().map(|entry| entry.binding))
+    }
+
+    // Checks the resources a material binds against what the shader declares for `group`: each
+    // declared binding needs one of a compatible type and, for arrays, with as many elements.
+    pub fn check_resources(
+        &self,
+        group: u32,
+        supplied: &[BoundResource],
+    ) -> Result<(), BindingMismatchError> {
+        let declared = self.group(group);
+
+        for expected in declared {
+            let Some(found) = supplied
+                .iter()
+                .find(|resource| resource.binding == expected.binding)
+            else {
+                return Err(BindingMismatchError::MissingBinding {
+                    group,
+                    binding: expected.binding,
+                    expected: describe(&expected.ty),
+                });
+            };
+
+            if !types_compatible(&expected.ty, &found.ty) {
+                return Err(BindingMismatchError::WrongType {
+                    group,
+                    binding: expected.binding,
+                    expected: describe(&expected.ty),
+                    found: describe(&found.ty),
+                });
+            }
+
+            let runtime_sized = self.runtime_sized.contains(&(group, expected.binding));
+            let count_matches = match (runtime_sized, found.count) {
+                (true, found) => found.is_some(),
+                (false, found) => found == expected.count,
+            };
+            if !count_matches {
+                return Err(BindingMismatchError::WrongCount {
+                    group,
+                    binding: expected.binding,
+                    expected: match runtime_sized {
+                        true => "an array".to_owned(),
+                        false => describe_count(expected.count),
+                    },
+                    found: describe_count(found.count),
+                });
+            }
+        }
+
+        Self::check_unexpected(
+            group,
+            declared,
+            supplied.iter().map(|resource| resource.binding),
+        )
+    }
+
+    // `check_resources` for a layout created from `group` as it is, in which arrays without a size
+    // in the shader hold one element.
+    pub fn check_reflected_resources(
+        &self,
+        group: u32,
+        supplied: &[BoundResource],
+    ) -> Result<(), BindingMismatchError> {
+        self.check_resources(group, supplied)?;
+
+        let Some(sized) = self.sized_group(group, supplied) else {
+            return Ok(());
+        };
+        match sized
+            .iter()
+            .zip(self.group(group))
+            .find(|(sized, reflected)| sized.count != reflected.count)
+        {
+            Some((sized, reflected)) => Err(BindingMismatchError::WrongCount {
+                group,
+                binding: sized.binding,
+                expected: describe_count(reflected.count),
+                found: describe_count(sized.count),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    // The layout entries for `group`, with arrays that have no size in the shader sized to
+    // the resources bound to them. None if there are no such arrays, so the reflected entries
+    // can be used as they are.
+    pub fn sized_group(
+        &self,
+        group: u32,
+        supplied: &[BoundResource],
+    ) -> Option<Vec<BindGroupLayoutEntry>> {
+        let mut entries = self.group(group).to_vec();
+        let mut resized = false;
+        for entry in &mut entries {
+            if !self.runtime_sized.contains(&(group, entry.binding)) {
+                continue;
+            }
+            let count = supplied
+                .iter()
+                .find(|resource| resource.binding == entry.binding)
+                .and_then(|resource| resource.count);
+            if let Some(count) = count {
+                entry.count = Some(count);
+                resized = true;
+            }
+        }
+
+        resized.then_some(entries)
+    }
+
+    // Checks that the vertex buffers provide every input `entry_point` reads, in the same format.
+    pub fn check_vertex_layouts(
+        &self,
+        entry_point: &str,
+        layouts: &[VertexBufferLayout],
+    ) -> Result<(), BindingMismatchError> {
+        let inputs = self
+            .vertex_inputs(entry_point)
+            .ok_or_else(|| BindingMismatchError::MissingEntryPoint(entry_point.to_owned()))?;
+
+        for (&location, &expected) in inputs {
+            let found = layouts
+                .iter()
+                .flat_map(|layout| layout.attributes)
+                .find(|attribute| attribute.shader_location == location)
+                .map(|attribute| attribute.format);
+
+            match found {
+                None => {
+                    return Err(BindingMismatchError::MissingVertexInput { location, expected });
+                }
+                Some(found) if found != expected => {
+                    return Err(BindingMismatchError::VertexFormatMismatch {
+                        location,
+                        expected,
+                        found,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_unexpected(
+        group: u32,
+        declared: &[BindGroupLayoutEntry],
+        supplied: impl Iterator<Item = u32>,
+    ) -> Result<(), BindingMismatchError> {
+        for binding in supplied {
+            if !declared.iter().any(|entry| entry.binding == binding) {
+                return Err(BindingMismatchError::UnexpectedBinding { group, binding });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Some errors lead with a placeholder span, so the first real one is used.
+fn first_location(src: &str, mut spans: impl Iterator<Item = Span>) -> Option<SourceLocation> {
+    spans
+        .find(|span| span.is_defined())
+        .map(|span| span.location(src))
+}
+
+fn compile_error(location: Option<SourceLocation>, message: String) -> ReflectionError {
+    let (line, column) = location.map_or((0, 0), |location| {
+        (location.line_number, location.line_position)
+    });
+
+    ReflectionError::Compile {
+        line,
+        column,
+        message,
+    }
+}
+
+// Validation errors nest the actual cause a few levels deep.
+fn error_chain(err: &dyn Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        message += &format!(": {err}");
+        source = err.source();
+    }
+
+    message
+}
+
+fn stage_flag(stage: naga::ShaderStage) -> ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => ShaderStages::COMPUTE,
+        naga::ShaderStage::Task => ShaderStages::TASK,
+        naga::ShaderStage::Mesh => ShaderStages::MESH,
+    }
+}
+
+// The binding type of a global, with its size if it's a `binding_array`.
+fn binding_type(
+    module: &Module,
+    space: AddressSpace,
+    ty: &Type,
+) -> Option<(BindingType, Option<ArraySize>)> {
+    if let TypeInner::BindingArray { base, size } = ty.inner {
+        let (ty, _) = binding_type(module, space, &module.types[base])?;
+        return Some((ty, Some(size)));
+    }
+
+    let binding = match (space, &ty.inner) {
+        (AddressSpace::Uniform, _) => BindingType::Buffer {
+            ty: BufferBindingType::Uniform,
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        (AddressSpace::Storage { access }, _) => BindingType::Buffer {
+            ty: BufferBindingType::Storage {
+                read_only: !access.contains(naga::StorageAccess::STORE),
+            },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+        (AddressSpace::Handle, TypeInner::Sampler { comparison }) => {
+            BindingType::Sampler(if *comparison {
+                SamplerBindingType::Comparison
+            } else {
+                SamplerBindingType::Filtering
+            })
+        }
+        (
+            AddressSpace::Handle,
+            TypeInner::Image {
+                dim,
+                arrayed,
+                class,
+            },
+        ) => {
+            let view_dimension = view_dimension(*dim, *arrayed);
+            match class {
+                ImageClass::Sampled { kind, multi } => BindingType::Texture {
+                    sample_type: match kind {
+                        ScalarKind::Sint => TextureSampleType::Sint,
+                        ScalarKind::Uint => TextureSampleType::Uint,
+                        _ => TextureSampleType::Float { filterable: true },
+                    },
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                ImageClass::Depth { multi } => BindingType::Texture {
+                    sample_type: TextureSampleType::Depth,
+                    view_dimension,
+                    multisampled: *multi,
+                },
+                ImageClass::Storage { format, access } => BindingType::StorageTexture {
+                    access: match (
+                        access.contains(naga::StorageAccess::LOAD),
+                        access.contains(naga::StorageAccess::STORE),
+                    ) {
+                        (true, true) => StorageTextureAccess::ReadWrite,
+                        (true, false) => StorageTextureAccess::ReadOnly,
+                        _ => StorageTextureAccess::WriteOnly,
+                    },
+                    format: storage_format(*format)?,
+                    view_dimension,
+                },
+            }
+        }
+        _ => return None,
+    };
+
+    Some((binding, None))
+}
+
+fn view_dimension(dim: ImageDimension, arrayed: bool) -> TextureViewDimension {
+    match (dim, arrayed) {
+        (ImageDimension::D1, _) => TextureViewDimension::D1,
+        (ImageDimension::D2, false) => TextureViewDimension::D2,
+        (ImageDimension::D2, true) => TextureViewDimension::D2Array,
+        (ImageDimension::D3, _) => TextureViewDimension::D3,
+        (ImageDimension::Cube, false) => TextureViewDimension::Cube,
+        (ImageDimension::Cube, true) => TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_format(format: StorageFormat) -> Option<TextureFormat> {
+    Some(match format {
+        StorageFormat::R32Uint => TextureFormat::R32Uint,
+        StorageFormat::R32Sint => TextureFormat::R32Sint,
+        StorageFormat::R32Float => TextureFormat::R32Float,
+        StorageFormat::Rg32Float => TextureFormat::Rg32Float,
+        StorageFormat::Rgba8Unorm => TextureFormat::Rgba8Unorm,
+        StorageFormat::Rgba8Snorm => TextureFormat::Rgba8Snorm,
+        StorageFormat::Rgba8Uint => TextureFormat::Rgba8Uint,
+        StorageFormat::Rgba8Sint => TextureFormat::Rgba8Sint,
+        StorageFormat::Bgra8Unorm => TextureFormat::Bgra8Unorm,
+        StorageFormat::Rgba16Float => TextureFormat::Rgba16Float,
+        StorageFormat::Rgba16Uint => TextureFormat::Rgba16Uint,
+        StorageFormat::Rgba16Sint => TextureFormat::Rgba16Sint,
+        StorageFormat::Rgba32Float => TextureFormat::Rgba32Float,
+        StorageFormat::Rgba32Uint => TextureFormat::Rgba32Uint,
+        StorageFormat::Rgba32Sint => TextureFormat::Rgba32Sint,
+        _ => return None,
+    })
+}
+
+fn collect_vertex_inputs(
+    module: &Module,
+    ty: naga::Handle<Type>,
+    binding: &Option<Binding>,
+    inputs: &mut BTreeMap<u32, VertexFormat>,
+) {
+    match (binding, &module.types[ty].inner) {
+        (Some(Binding::Location { location, .. }), inner) => {
+            if let Some(format) = vertex_format(inner) {
+                inputs.insert(*location, format);
+            }
+        }
+        (None, TypeInner::Struct { members, .. }) => {
+            for member in members {
+                collect_vertex_inputs(module, member.ty, &member.binding, inputs);
+            }
+        }
+        _ => {} // builtins
+    }
+}
+
+fn vertex_format(inner: &TypeInner) -> Option<VertexFormat> {
+    let (size, scalar) = match inner {
+        TypeInner::Scalar(scalar) => (None, scalar),
+        TypeInner::Vector { size, scalar } => (Some(*size), scalar),
+        _ => return None,
+    };
+
+    use VertexFormat::*;
+    Some(match (scalar.kind, scalar.width, size) {
+        (ScalarKind::Float, 4, None) => Float32,
+        (ScalarKind::Float, 4, Some(VectorSize::Bi)) => Float32x2,
+        (ScalarKind::Float, 4, Some(VectorSize::Tri)) => Float32x3,
+        (ScalarKind::Float, 4, Some(VectorSize::Quad)) => Float32x4,
+        (ScalarKind::Float, 2, Some(VectorSize::Bi)) => Float16x2,
+        (ScalarKind::Float, 2, Some(VectorSize::Quad)) => Float16x4,
+        (ScalarKind::Uint, 4, None) => Uint32,
+        (ScalarKind::Uint, 4, Some(VectorSize::Bi)) => Uint32x2,
+        (ScalarKind::Uint, 4, Some(VectorSize::Tri)) => Uint32x3,
+        (ScalarKind::Uint, 4, Some(VectorSize::Quad)) => Uint32x4,
+        (ScalarKind::Sint, 4, None) => Sint32,
+        (ScalarKind::Sint, 4, Some(VectorSize::Bi)) => Sint32x2,
+        (ScalarKind::Sint, 4, Some(VectorSize::Tri)) => Sint32x3,
+        (ScalarKind::Sint, 4, Some(VectorSize::Quad)) => Sint32x4,
+        _ => return None,
+    })
+}
+
+// Filterability can't be read from WGSL, so float textures match either way. The same goes for
+// filtering vs non-filtering samplers.
+fn types_compatible(declared: &BindingType, supplied: &BindingType) -> bool {
+    match (declared, supplied) {
+        (
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { .. },
+                view_dimension: a_dim,
+                multisampled: a_multi,
+            },
+            BindingType::Texture {
+                sample_type: TextureSampleType::Float { .. },
+                view_dimension: b_dim,
+                multisampled: b_multi,
+            },
+        ) => a_dim == b_dim && a_multi == b_multi,
+        (BindingType::Sampler(a), BindingType::Sampler(b)) => {
+            (*a == SamplerBindingType::Comparison) == (*b == SamplerBindingType::Comparison)
+        }
+        (BindingType::Buffer { ty: a, .. }, BindingType::Buffer { ty: b, .. }) => a == b,
+        (a, b) => a == b,
+    }
+}
+
+fn describe_count(count: Option<NonZeroU32>) -> String {
+    match count {
+        Some(count) => format!("an array of {count}"),
+        None => "a single resource".to_owned(),
+    }
+}
+
+fn describe(ty: &BindingType) -> String {
+    match ty {
+        BindingType::Buffer { ty, .. } => format!("a {ty:?} buffer").to_lowercase(),
+        BindingType::Sampler(ty) => format!("a {ty:?} sampler").to_lowercase(),
+        BindingType::Texture { view_dimension, .. } => format!("a {view_dimension:?} texture"),
+        BindingType::StorageTexture {
+            format,
+            view_dimension,
+            ..
+        } => format!("a {view_dimension:?} {format:?} storage texture"),
+        ty => format!("{ty:?}"),
+    }
+}
+
+// Size and member offsets of the WGSL struct called `name`, for tests that keep #[repr(C)]
+// structs in sync with their shader copies.
+#[cfg(test)]
+pub(crate) fn wgsl_struct(module: &Module, name: &str) -> (u32, Vec<u32>) {
+    let (_, ty) = module
+        .types
+        .iter()
+        .find(|(_, ty)| ty.name.as_deref() == Some(name))
+        .unwrap_or_else(|| panic!("{name} isn't declared"));
+    let TypeInner::Struct { members, span } = &ty.inner else {
+        panic!("{name} isn't a struct");
+    };
+
+    (*span, members.iter().map(|member| member.offset).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::source::AssetSource;
+    use crate::rendering::instance::InstanceData;
+    use crate::rendering::preprocessor::{ShaderDefines, preprocess};
+    use crate::rendering::utils::bind_group_layout_builder::BindGroupLayoutBuilder;
+    use crate::rendering::vertex::Vertex;
+
+    // The resources a bind group built to match `entries` would report.
+    fn bound(entries: &[BindGroupLayoutEntry]) -> Vec<BoundResource> {
+        entries
+            .iter()
+            .map(|entry| BoundResource {
+                binding: entry.binding,
+                ty: entry.ty,
+                count: entry.count,
+            })
+            .collect()
+    }
+
+    fn reflect(path: &str) -> ShaderReflection {
+        let assets = AssetSource::new().with_directory(env!("CARGO_MANIFEST_DIR"));
+        let shader = preprocess(path, &ShaderDefines::new(), &assets).unwrap();
+        ShaderReflection::from_wgsl(&shader.source).unwrap()
+    }
+
+    const STORAGE_SHADER: &str = "
+        @group(1) @binding(0) var<storage, read> positions: array<vec4<f32>>;
+        @group(1) @binding(2) var output: texture_storage_2d<rgba8unorm, write>;
+
+        @compute @workgroup_size(8, 8)
+        fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+            textureStore(output, id.xy, positions[id.x]);
+        }
+    ";
+
+    #[test]
+    fn default_shader_declares_its_material_texture() {
+        let reflection = reflect("/res/shaders/default.wgsl");
+
+        let material = reflection.group(1);
+        assert_eq!(material.len(), 1);
+        assert_eq!(material[0].binding, 0);
+        assert!(matches!(material[0].ty, BindingType::Texture { .. }));
+
+        let globals = reflection.group(0);
+        assert_eq!(globals.len(), 3);
+        assert!(globals[0].visibility.contains(ShaderStages::VERTEX));
+    }
+
+    #[test]
+    fn default_shader_matches_the_mesh_vertex_layouts() {
+        let reflection = reflect("/res/shaders/default.wgsl");
+
+        reflection
+            .check_vertex_layouts("vs_main", &[Vertex::desc(), InstanceData::desc()])
+            .unwrap();
+        assert_eq!(
+            reflection.check_vertex_layouts("vs_main", &[Vertex::desc()]),
+            Err(BindingMismatchError::MissingVertexInput {
+                location: 5,
+                expected: VertexFormat::Float32x4
+            })
+        );
+    }
+
+    #[test]
+    fn array_shader_matches_the_array_bindings() {
+        let reflection = reflect("/res/shaders/blocks_array.wgsl");
+        reflection
+            .check_vertex_layouts("vs_main", &[Vertex::desc(), InstanceData::desc()])
+            .unwrap();
+
+        let expected =
+            BindGroupLayoutBuilder::new().with_texture2d_array(0, ShaderStages::FRAGMENT);
+        reflection.check_layout(1, &expected.entries).unwrap();
+        assert!(matches!(
+            reflection.check_layout(
+                1,
+                &BindGroupLayoutBuilder::new()
+                    .with_texture2d(0, ShaderStages::FRAGMENT)
+                    .entries
+            ),
+            Err(BindingMismatchError::WrongType { binding: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn storage_bindings_are_reflected() {
+        let reflection = ShaderReflection::from_wgsl(STORAGE_SHADER).unwrap();
+        let entries = reflection.group(1);
+
+        assert_eq!(
+            entries[0].ty,
+            BindingType::Buffer {
+                ty: BufferBindingType::Storage { read_only: true },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            }
+        );
+        assert_eq!(entries[1].binding, 2);
+        assert_eq!(entries[1].visibility, ShaderStages::COMPUTE);
+        assert!(matches!(
+            entries[1].ty,
+            BindingType::StorageTexture {
+                access: StorageTextureAccess::WriteOnly,
+                format: TextureFormat::Rgba8Unorm,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn unsized_binding_arrays_take_any_count() {
+        let reflection = ShaderReflection::from_wgsl(
+            "
+            @group(1) @binding(0) var layers: binding_array<texture_2d<f32>>;
+            @group(1) @binding(1) var four: binding_array<texture_2d<f32>, 4>;
+
+            @fragment
+            fn fs_main() -> @location(0) vec4<f32> {
+                return textureLoad(layers[1], vec2<i32>(0), 0) + textureLoad(four[1], vec2<i32>(0), 0);
+            }
+            ",
+        )
+        .unwrap();
+        let layout = |layers: Option<u32>, four: Option<u32>| {
+            let mut entries = BindGroupLayoutBuilder::new()
+                .with_texture2d(0, ShaderStages::FRAGMENT)
+                .with_texture2d(1, ShaderStages::FRAGMENT)
+                .entries;
+            entries[0].count = layers.and_then(NonZeroU32::new);
+            entries[1].count = four.and_then(NonZeroU32::new);
+            entries
+        };
+
+        assert_eq!(
+            reflection.check_layout(1, &layout(Some(16), Some(4))),
+            Ok(())
+        );
+        assert_eq!(
+            reflection.check_layout(1, &layout(Some(1), Some(4))),
+            Ok(())
+        );
+        // It still has to be an array, and sized ones keep their size.
+        assert!(reflection.check_layout(1, &layout(None, Some(4))).is_err());
+        assert!(
+            reflection
+                .check_layout(1, &layout(Some(16), Some(16)))
+                .is_err()
+        );
+
+        // Bound resources are held to the same counts, and size the layout.
+        let resources = bound(&layout(Some(3), Some(4)));
+        reflection.check_resources(1, &resources).unwrap();
+        let sized = reflection.sized_group(1, &resources).unwrap();
+        assert_eq!(sized[0].count, NonZeroU32::new(3));
+        assert_eq!(sized[1].count, NonZeroU32::new(4));
+        assert_eq!(
+            reflection.check_resources(1, &bound(&layout(Some(3), Some(3)))),
+            Err(BindingMismatchError::WrongCount {
+                group: 1,
+                binding: 1,
+                expected: "an array of 4".to_owned(),
+                found: "an array of 3".to_owned(),
+            })
+        );
+        assert_eq!(
+            reflection.check_resources(1, &bound(&layout(None, Some(4)))),
+            Err(BindingMismatchError::WrongCount {
+                group: 1,
+                binding: 0,
+                expected: "an array".to_owned(),
+                found: "a single resource".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn workgroup_sizes_are_reflected() {
+        let reflection = ShaderReflection::from_wgsl(STORAGE_SHADER).unwrap();
+
+        assert_eq!(reflection.workgroup_size("main"), Ok([8, 8, 1]));
+        assert_eq!(
+            reflection.workgroup_size("fs_main"),
+            Err(BindingMismatchError::MissingComputeEntryPoint(
+                "fs_main".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn mismatched_resources_are_reported() {
+        let reflection = reflect("/res/shaders/default.wgsl");
+
+        assert_eq!(
+            reflection.check_resources(1, &[]),
+            Err(BindingMismatchError::MissingBinding {
+                group: 1,
+                binding: 0,
+                expected: "a D2 texture".to_owned(),
+            })
+        );
+        let fragment = ShaderStages::FRAGMENT;
+        let texture = BindGroupLayoutBuilder::new().with_texture2d(0, fragment);
+        reflection
+            .check_resources(1, &bound(&texture.entries))
+            .unwrap();
+        assert_eq!(
+            reflection.check_resources(1, &bound(&texture.with_sampler(1, fragment).entries)),
+            Err(BindingMismatchError::UnexpectedBinding {
+                group: 1,
+                binding: 1
+            })
+        );
+
+        // Resources of the right kind but the wrong type are caught too.
+        for wrong in [
+            BindGroupLayoutBuilder::new().with_sampler(0, fragment),
+            BindGroupLayoutBuilder::new().with_texture2d_array(0, fragment),
+            BindGroupLayoutBuilder::new().with_depth_texture(0, fragment),
+        ] {
+            assert!(matches!(
+                reflection.check_resources(1, &bound(&wrong.entries)),
+                Err(BindingMismatchError::WrongType { binding: 0, .. })
+            ));
+        }
+        let storage = ShaderReflection::from_wgsl(STORAGE_SHADER).unwrap();
+        let uniform = BindGroupLayoutBuilder::new().with_uniform_buffer(0, ShaderStages::COMPUTE);
+        assert_eq!(
+            storage.check_resources(1, &bound(&uniform.entries)),
+            Err(BindingMismatchError::WrongType {
+                group: 1,
+                binding: 0,
+                expected: "a storage { read_only: true } buffer".to_owned(),
+                found: "a uniform buffer".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn syntax_errors_are_located() {
+        let src = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return vec4<f32>(1.0) +;\n}\n";
+
+        let Err(ReflectionError::Compile { line, column, .. }) = ShaderReflection::from_wgsl(src)
+        else {
+            panic!("expected a compile error");
+        };
+        assert_eq!((line, column), (3, 28));
+    }
+
+    #[test]
+    fn type_errors_are_located() {
+        let src = "@fragment\nfn fs_main() -> @location(0) vec4<f32> {\n    return 1.0;\n}\n";
+
+        let Err(ReflectionError::Compile { line, message, .. }) = ShaderReflection::from_wgsl(src)
+        else {
+            panic!("expected a compile error");
+        };
+        assert_eq!(line, 3);
+        assert!(message.contains("vec4<f32>"), "{message}");
+    }
+
+    #[test]
+    fn fallback_shader_needs_no_material_resources() {
+        let reflection = reflect("/res/shaders/fallback.wgsl");
+
+        assert!(reflection.group(1).is_empty());
+        reflection
+            .check_vertex_layouts("vs_main", &[Vertex::desc(), InstanceData::desc()])
+            .unwrap();
+    }
+}
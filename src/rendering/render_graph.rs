@@ -0,0 +1,847 @@
+use crate::rendering::compute::{ComputeDispatch, ComputeStage};
+use crate::rendering::indirect::IndirectBatch;
+use crate::rendering::render_object::RenderObject;
+use crate::rendering::wgpu_context::WGPUContext;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use thiserror::Error;
+use wgpu::{
+    BindGroup, CommandEncoder, Extent3d, Texture, TextureDescriptor, TextureDimension,
+    TextureFormat, TextureUsages, TextureView, TextureViewDescriptor,
+};
+
+// The resources and passes of the renderer's graph, see `Renderer::new`.
+pub const SURFACE: &str = "surface"; // imported, the texture presented at the end of the frame
+pub const SCENE: &str = "scene";
+pub const DEPTH: &str = "depth";
+pub const COMPUTE_BEFORE_PASS: &str = "compute_before";
+pub const MAIN_PASS: &str = "main";
+pub const TRANSPARENT_PASS: &str = "transparent";
+pub const POST_PROCESS_PASS: &str = "post_process";
+pub const COMPUTE_AFTER_PASS: &str = "compute_after";
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum RenderGraphError {
+    #[error("Pass '{pass}' uses '{resource}', which is neither a transient texture nor imported.")]
+    UnknownResource { pass: String, resource: String },
+    #[error("Pass '{pass}' reads '{resource}', but no enabled pass writes it.")]
+    Unwritten { pass: String, resource: String },
+    #[error("Passes {0:?} depend on each other in a cycle.")]
+    Cycle(Vec<String>),
+    #[error("There's no pass named '{0}'.")]
+    UnknownPass(String),
+    #[error("Imported resource '{0}' wasn't supplied.")]
+    MissingImport(String),
+}
+
+// What passes draw in a frame, apart from the graph's textures.
+pub struct FrameData<'a> {
+    pub context: &'a WGPUContext,
+    pub global_bind_group: &'a BindGroup,
+    pub opaque: &'a [&'a RenderObject],
+    pub transparent: &'a [&'a RenderObject], // sorted back to front
+    pub batches: &'a [IndirectBatch],
+    pub multi_draw: bool, // see `IndirectBatch::record`
+    pub compute: &'a [(ComputeStage, ComputeDispatch)],
+}
+
+pub trait GraphPass {
+    // `resources` holds exactly the textures the pass declared.
+    fn record(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        resources: &PassResources,
+        frame: &FrameData,
+    );
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureSize {
+    Relative(f32), // to the surface, e.g. 0.5 for half resolution
+}
+
+impl TextureSize {
+    fn resolve(self, surface: (u32, u32)) -> (u32, u32) {
+        match self {
+            TextureSize::Relative(scale) => (
+                ((surface.0 as f32 * scale).round() as u32).max(1),
+                ((surface.1 as f32 * scale).round() as u32).max(1),
+            ),
+        }
+    }
+}
+
+// A texture that only lives for the frame. Its usages are whatever the passes declare for it.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TransientTexture {
+    pub size: TextureSize,
+    pub format: Option<TextureFormat>, // None uses the surface format
+}
+
+impl TransientTexture {
+    pub fn surface_sized(format: Option<TextureFormat>) -> Self {
+        Self {
+            size: TextureSize::Relative(1.0),
+            format,
+        }
+    }
+}
+
+// What a pass reads and writes. Writers of a resource run in the order they were added, and
+// readers only once every writer has, so passes can be added in any order otherwise.
+#[derive(Clone, Debug)]
+pub struct PassDesc {
+    name: String,
+    reads: Vec<(String, TextureUsages)>,
+    writes: Vec<(String, TextureUsages)>,
+    bypass: Option<(String, String)>,
+}
+
+impl PassDesc {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            reads: vec![],
+            writes: vec![],
+            bypass: None,
+        }
+    }
+
+    pub fn reads(mut self, resource: &str, usage: TextureUsages) -> Self {
+        self.reads.push((resource.to_owned(), usage));
+
+        self
+    }
+
+    pub fn writes(mut self, resource: &str, usage: TextureUsages) -> Self {
+        self.writes.push((resource.to_owned(), usage));
+
+        self
+    }
+
+    // While the pass is disabled, `input` is an alias of `output`, so the passes writing `input`
+    // write `output` directly. E.g. post-processing reads the scene and writes the surface; without
+    // it the scene is drawn straight to the surface.
+    pub fn bypass(mut self, input: &str, output: &str) -> Self {
+        self.bypass = Some((input.to_owned(), output.to_owned()));
+
+        self
+    }
+
+    fn uses(&self) -> impl Iterator<Item = &(String, TextureUsages)> {
+        self.reads.iter().chain(&self.writes)
+    }
+}
+
+struct Node {
+    desc: PassDesc,
+    pass: Box<dyn GraphPass>,
+    enabled: bool,
+}
+
+// The order passes run in this configuration and which textures back the transient resources.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GraphPlan {
+    order: Vec<usize>,
+    aliases: HashMap<String, String>,
+    slots: Vec<(TransientTexture, TextureUsages)>,
+    assignments: HashMap<String, usize>, // transient resource to slot
+}
+
+impl GraphPlan {
+    fn resolve<'a>(&'a self, mut resource: &'a str) -> &'a str {
+        // Bounded, in case bypasses alias resources to each other in a loop.
+        for _ in 0..=self.aliases.len() {
+            match self.aliases.get(resource) {
+                Some(alias) => resource = alias,
+                None => break,
+            }
+        }
+
+        resource
+    }
+}
+
+struct Allocation {
+    surface: (u32, u32, TextureFormat),
+    textures: Vec<(Texture, TextureView)>, // one per slot
+}
+
+// Passes and the resources they share, run in an order derived from what they read and write.
+// Transient textures are only created for resources used by enabled passes, and resources whose
+// lifetimes don't overlap share a texture.
+pub struct RenderGraph {
+    nodes: Vec<Node>,
+    textures: BTreeMap<String, TransientTexture>,
+    imports: BTreeSet<String>,
+    plan: Option<GraphPlan>,
+    allocation: Option<Allocation>,
+}
+
+impl RenderGraph {
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![],
+            textures: BTreeMap::new(),
+            imports: BTreeSet::new(),
+            plan: None,
+            allocation: None,
+        }
+    }
+
+    pub fn add_texture(&mut self, name: &str, texture: TransientTexture) {
+        debug_assert!(!self.is_resource(name), "'{name}' is already declared");
+        self.textures.insert(name.to_owned(), texture);
+        self.invalidate();
+    }
+
+    // A resource supplied to `execute` every frame, like the surface texture.
+    pub fn import(&mut self, name: &str) {
+        debug_assert!(!self.is_resource(name), "'{name}' is already declared");
+        self.imports.insert(name.to_owned());
+        self.invalidate();
+    }
+
+    pub fn add_pass(&mut self, desc: PassDesc, pass: impl GraphPass + 'static) {
+        debug_assert!(
+            self.node(&desc.name).is_none(),
+            "pass '{}' is already added",
+            desc.name
+        );
+        self.nodes.push(Node {
+            desc,
+            pass: Box::new(pass),
+            enabled: true,
+        });
+        self.invalidate();
+    }
+
+    pub fn is_enabled(&self, pass: &str) -> bool {
+        self.node(pass).is_some_and(|node| node.enabled)
+    }
+
+    // Leaves the graph as it was if the new configuration is invalid, e.g. because a resource
+    // would no longer be written.
+    pub fn set_enabled(&mut self, pass: &str, enabled: bool) -> Result<(), RenderGraphError> {
+        let index = self
+            .nodes
+            .iter()
+            .position(|node| node.desc.name == pass)
+            .ok_or_else(|| RenderGraphError::UnknownPass(pass.to_owned()))?;
+        let previous = self.nodes[index].enabled;
+        if previous == enabled {
+            return Ok(());
+        }
+
+        self.nodes[index].enabled = enabled;
+        if let Err(err) = self.compile() {
+            self.nodes[index].enabled = previous;
+            self.compile()?;
+            return Err(err);
+        }
+
+        Ok(())
+    }
+
+    // Plans the current configuration. Textures are created on the next `execute`.
+    pub fn compile(&mut self) -> Result<(), RenderGraphError> {
+        self.invalidate();
+        self.plan = Some(self.plan()?);
+
+        Ok(())
+    }
+
+    // Records every enabled pass into `encoder`. Transient textures are (re)created to match the
+    // surface first, if they don't already.
+    pub fn execute(
+        &mut self,
+        context: &WGPUContext,
+        encoder: &mut CommandEncoder,
+        imports: &[(&str, &Texture)],
+        frame: &FrameData,
+    ) -> Result<(), RenderGraphError> {
+        if self.plan.is_none() {
+            self.compile()?;
+        }
+        let plan = self.plan.as_ref().unwrap();
+
+        let surface = (
+            context.config.width.max(1),
+            context.config.height.max(1),
+            context.config.format,
+        );
+        if self
+            .allocation
+            .as_ref()
+            .is_none_or(|allocation| allocation.surface != surface)
+        {
+            self.allocation = Some(allocate(context, plan, surface));
+        }
+        let allocation = self.allocation.as_ref().unwrap();
+
+        let mut imported = HashMap::new();
+        for name in &self.imports {
+            let (_, texture) = imports
+                .iter()
+                .find(|(import, _)| import == name)
+                .ok_or_else(|| RenderGraphError::MissingImport(name.clone()))?;
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            imported.insert(name.as_str(), (*texture, view));
+        }
+
+        for &index in &plan.order {
+            let node = &mut self.nodes[index];
+            let textures = node
+                .desc
+                .uses()
+                .map(|(resource, _)| {
+                    let resolved = plan.resolve(resource);
+                    let (texture, view) = match plan.assignments.get(resolved) {
+                        Some(&slot) => {
+                            let (texture, view) = &allocation.textures[slot];
+                            (texture, view)
+                        }
+                        None => {
+                            let (texture, view) = &imported[resolved];
+                            (*texture, view)
+                        }
+                    };
+                    (resource.as_str(), (texture, view))
+                })
+                .collect();
+
+            node.pass.record(
+                encoder,
+                &PassResources {
+                    pass: &node.desc.name,
+                    textures,
+                },
+                frame,
+            );
+        }
+
+        Ok(())
+    }
+
+    fn plan(&self) -> Result<GraphPlan, RenderGraphError> {
+        let mut aliases = HashMap::new();
+        for node in self.nodes.iter().filter(|node| !node.enabled) {
+            if let Some((input, output)) = &node.desc.bypass {
+                aliases.insert(input.clone(), output.clone());
+            }
+        }
+        let mut plan = GraphPlan {
+            order: vec![],
+            aliases,
+            slots: vec![],
+            assignments: HashMap::new(),
+        };
+
+        let enabled: Vec<usize> = (0..self.nodes.len())
+            .filter(|&index| self.nodes[index].enabled)
+            .collect();
+        let mut writers: HashMap<&str, Vec<usize>> = HashMap::new();
+        for &index in &enabled {
+            let desc = &self.nodes[index].desc;
+            for (resource, _) in desc.uses() {
+                if !self.is_resource(plan.resolve(resource)) {
+                    return Err(RenderGraphError::UnknownResource {
+                        pass: desc.name.clone(),
+                        resource: resource.clone(),
+                    });
+                }
+            }
+            for (resource, _) in &desc.writes {
+                writers
+                    .entry(plan.resolve(resource))
+                    .or_default()
+                    .push(index);
+            }
+        }
+
+        let mut dependencies = vec![BTreeSet::new(); self.nodes.len()];
+        for indices in writers.values() {
+            for pair in indices.windows(2) {
+                dependencies[pair[1]].insert(pair[0]);
+            }
+        }
+        for &index in &enabled {
+            let desc = &self.nodes[index].desc;
+            for (resource, _) in &desc.reads {
+                let resolved = plan.resolve(resource);
+                match writers.get(resolved) {
+                    Some(indices) => dependencies[index]
+                        .extend(indices.iter().filter(|&&writer| writer != index)),
+                    // Imports arrive with their contents.
+                    None if self.imports.contains(resolved) => {}
+                    None => {
+                        return Err(RenderGraphError::Unwritten {
+                            pass: desc.name.clone(),
+                            resource: resource.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // Whichever pass is ready and was added first runs next, so unrelated passes keep the
+        // order they were added in.
+        let mut remaining: BTreeSet<usize> = enabled.into_iter().collect();
+        while let Some(&next) = remaining.iter().find(|&&index| {
+            dependencies[index]
+                .iter()
+                .all(|dependency| !remaining.contains(dependency))
+        }) {
+            remaining.remove(&next);
+            plan.order.push(next);
+        }
+        if !remaining.is_empty() {
+            return Err(RenderGraphError::Cycle(
+                remaining
+                    .iter()
+                    .map(|&index| self.nodes[index].desc.name.clone())
+                    .collect(),
+            ));
+        }
+
+        // When each transient texture is first and last used, and how.
+        let mut lifetimes: BTreeMap<&str, (usize, usize, TextureUsages)> = BTreeMap::new();
+        for (position, &index) in plan.order.iter().enumerate() {
+            for (resource, usage) in self.nodes[index].desc.uses() {
+                let resolved = plan.resolve(resource);
+                if self.textures.contains_key(resolved) {
+                    let lifetime = lifetimes.entry(resolved).or_insert((
+                        position,
+                        position,
+                        TextureUsages::empty(),
+                    ));
+                    lifetime.1 = position;
+                    lifetime.2 |= *usage;
+                }
+            }
+        }
+
+        // Textures are shared by resources of the same size and format, when one's last use comes
+        // before the other's first.
+        let mut by_first_use: Vec<_> = lifetimes.into_iter().collect();
+        by_first_use.sort_by_key(|(_, (first, _, _))| *first);
+        let mut slots: Vec<(TransientTexture, TextureUsages)> = vec![];
+        let mut slot_ends = vec![];
+        let mut assignments = HashMap::new();
+        for (resource, (first, last, usage)) in by_first_use {
+            let texture = self.textures[resource];
+            let slot =
+                (0..slots.len()).find(|&slot| slots[slot].0 == texture && slot_ends[slot] < first);
+            let slot = match slot {
+                Some(slot) => {
+                    slots[slot].1 |= usage;
+                    slot_ends[slot] = last;
+                    slot
+                }
+                None => {
+                    slots.push((texture, usage));
+                    slot_ends.push(last);
+                    slots.len() - 1
+                }
+            };
+            assignments.insert(resource.to_owned(), slot);
+        }
+        plan.slots = slots;
+        plan.assignments = assignments;
+
+        Ok(plan)
+    }
+
+    fn node(&self, pass: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.desc.name == pass)
+    }
+
+    fn is_resource(&self, name: &str) -> bool {
+        self.textures.contains_key(name) || self.imports.contains(name)
+    }
+
+    fn invalidate(&mut self) {
+        self.plan = None;
+        self.allocation = None;
+    }
+}
+
+fn allocate(
+    context: &WGPUContext,
+    plan: &GraphPlan,
+    surface: (u32, u32, TextureFormat),
+) -> Allocation {
+    let (width, height, surface_format) = surface;
+    let textures = plan
+        .slots
+        .iter()
+        .map(|(transient, usage)| {
+            let (width, height) = transient.size.resolve((width, height));
+            let texture = context.device.create_texture(&TextureDescriptor {
+                label: Some("Transient Texture"),
+                size: Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: TextureDimension::D2,
+                format: transient.format.unwrap_or(surface_format),
+                usage: *usage,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            (texture, view)
+        })
+        .collect();
+
+    Allocation { surface, textures }
+}
+
+// The textures a pass declared, by the names it declared them with.
+pub struct PassResources<'a> {
+    pass: &'a str,
+    textures: HashMap<&'a str, (&'a Texture, &'a TextureView)>,
+}
+
+impl PassResources<'_> {
+    #[cfg(test)]
+    pub fn texture(&self, name: &str) -> &Texture {
+        self.get(name).0
+    }
+
+    pub fn view(&self, name: &str) -> &TextureView {
+        self.get(name).1
+    }
+
+    fn get(&self, name: &str) -> (&Texture, &TextureView) {
+        *self
+            .textures
+            .get(name)
+            .unwrap_or_else(|| panic!("pass '{}' didn't declare '{name}'", self.pass))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wgpu::{
+        BindGroupDescriptor, BindGroupLayoutDescriptor, BufferDescriptor, BufferUsages, Color,
+        LoadOp, Operations, Origin3d, RenderPassColorAttachment, RenderPassDescriptor, StoreOp,
+        TexelCopyBufferInfo, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect,
+    };
+
+    struct Noop;
+
+    impl GraphPass for Noop {
+        fn record(&mut self, _: &mut CommandEncoder, _: &PassResources, _: &FrameData) {}
+    }
+
+    const TARGET: TextureUsages = TextureUsages::RENDER_ATTACHMENT;
+    const SAMPLED: TextureUsages = TextureUsages::TEXTURE_BINDING;
+
+    fn graph(textures: &[&str], passes: Vec<PassDesc>) -> RenderGraph {
+        let mut graph = RenderGraph::new();
+        graph.import(SURFACE);
+        for texture in textures {
+            graph.add_texture(texture, TransientTexture::surface_sized(None));
+        }
+        for desc in passes {
+            graph.add_pass(desc, Noop);
+        }
+
+        graph
+    }
+
+    fn order(graph: &RenderGraph) -> Vec<&str> {
+        graph
+            .plan
+            .as_ref()
+            .unwrap()
+            .order
+            .iter()
+            .map(|&index| graph.nodes[index].desc.name.as_str())
+            .collect()
+    }
+
+    // a -> t1 -> b -> t2 -> c -> t3 -> d -> surface, added back to front.
+    fn chain() -> RenderGraph {
+        graph(
+            &["t1", "t2", "t3"],
+            vec![
+                PassDesc::new("d")
+                    .reads("t3", SAMPLED)
+                    .writes(SURFACE, TARGET)
+                    .bypass("t3", SURFACE),
+                PassDesc::new("c").reads("t2", SAMPLED).writes("t3", TARGET),
+                PassDesc::new("b").reads("t1", SAMPLED).writes("t2", TARGET),
+                PassDesc::new("a").writes("t1", TARGET),
+            ],
+        )
+    }
+
+    #[test]
+    fn passes_run_after_the_passes_writing_what_they_read() {
+        let mut graph = chain();
+        graph.compile().unwrap();
+
+        assert_eq!(order(&graph), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn writers_keep_the_order_they_were_added_in() {
+        let mut graph = graph(
+            &[],
+            vec![
+                PassDesc::new("overlay").writes(SURFACE, TARGET),
+                PassDesc::new("ui").writes(SURFACE, TARGET),
+                PassDesc::new("unrelated"),
+            ],
+        );
+        graph.compile().unwrap();
+
+        assert_eq!(order(&graph), ["overlay", "ui", "unrelated"]);
+    }
+
+    #[test]
+    fn resources_with_disjoint_lifetimes_share_a_texture() {
+        let mut graph = chain();
+        graph.add_texture(
+            "half",
+            TransientTexture {
+                size: TextureSize::Relative(0.5),
+                format: None,
+            },
+        );
+        graph.add_pass(PassDesc::new("e").writes("half", TARGET), Noop);
+        graph.compile().unwrap();
+
+        let plan = graph.plan.as_ref().unwrap();
+        assert_eq!(plan.slots.len(), 3);
+        assert_eq!(plan.assignments["t1"], plan.assignments["t3"]);
+        assert_ne!(plan.assignments["t1"], plan.assignments["t2"]);
+        // A different size can't share, even though it's used last.
+        assert_eq!(plan.assignments["half"], 2);
+        assert_eq!(plan.slots[plan.assignments["t1"]].1, SAMPLED | TARGET);
+    }
+
+    #[test]
+    fn disabling_a_pass_aliases_its_input_to_its_output() {
+        let mut graph = chain();
+        graph.set_enabled("d", false).unwrap();
+
+        assert!(!graph.is_enabled("d"));
+        assert_eq!(order(&graph), ["a", "b", "c"]);
+        let plan = graph.plan.as_ref().unwrap();
+        assert_eq!(plan.resolve("t3"), SURFACE);
+        assert!(!plan.assignments.contains_key("t3"));
+    }
+
+    #[test]
+    fn invalid_configurations_are_rejected_and_reverted() {
+        let mut graph = chain();
+        graph.compile().unwrap();
+
+        assert_eq!(
+            graph.set_enabled("b", false),
+            Err(RenderGraphError::Unwritten {
+                pass: "c".to_owned(),
+                resource: "t2".to_owned(),
+            })
+        );
+        assert!(graph.is_enabled("b"));
+        assert_eq!(order(&graph), ["a", "b", "c", "d"]);
+        assert_eq!(
+            graph.set_enabled("z", false),
+            Err(RenderGraphError::UnknownPass("z".to_owned()))
+        );
+    }
+
+    #[test]
+    fn cycles_and_unknown_resources_are_errors() {
+        let mut cycle = graph(
+            &["t1", "t2"],
+            vec![
+                PassDesc::new("a").reads("t2", SAMPLED).writes("t1", TARGET),
+                PassDesc::new("b").reads("t1", SAMPLED).writes("t2", TARGET),
+            ],
+        );
+        assert_eq!(
+            cycle.compile(),
+            Err(RenderGraphError::Cycle(vec![
+                "a".to_owned(),
+                "b".to_owned()
+            ]))
+        );
+
+        let mut unknown = graph(&[], vec![PassDesc::new("a").writes("t1", TARGET)]);
+        assert_eq!(
+            unknown.compile(),
+            Err(RenderGraphError::UnknownResource {
+                pass: "a".to_owned(),
+                resource: "t1".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn relative_sizes_scale_the_surface() {
+        assert_eq!(TextureSize::Relative(0.5).resolve((1280, 721)), (640, 361));
+        assert_eq!(TextureSize::Relative(0.001).resolve((100, 100)), (1, 1));
+    }
+
+    struct Clear(Color);
+
+    impl GraphPass for Clear {
+        fn record(
+            &mut self,
+            encoder: &mut CommandEncoder,
+            resources: &PassResources,
+            _: &FrameData,
+        ) {
+            encoder.begin_render_pass(&RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: resources.view(SCENE),
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(self.0),
+                        store: StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+        }
+    }
+
+    // Stands in for post-processing.
+    struct CopyToSurface;
+
+    impl GraphPass for CopyToSurface {
+        fn record(
+            &mut self,
+            encoder: &mut CommandEncoder,
+            resources: &PassResources,
+            _: &FrameData,
+        ) {
+            let scene = resources.texture(SCENE);
+            encoder.copy_texture_to_texture(
+                scene.as_image_copy(),
+                resources.texture(SURFACE).as_image_copy(),
+                scene.size(),
+            );
+        }
+    }
+
+    fn read_pixel(context: &WGPUContext, texture: &Texture) -> [u8; 4] {
+        let buffer = context.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: 256,
+            usage: BufferUsages::COPY_DST | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            TexelCopyTextureInfo {
+                texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            TexelCopyBufferInfo {
+                buffer: &buffer,
+                layout: TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(256),
+                    rows_per_image: None,
+                },
+            },
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+        );
+        context.queue.submit([encoder.finish()]);
+
+        context.read_buffer(&buffer)[..4].try_into().unwrap()
+    }
+
+    #[test]
+    fn executes_passes_into_the_imported_surface() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let surface = context.device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: context.config.width,
+                height: context.config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: context.config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let layout = context
+            .device
+            .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: None,
+                entries: &[],
+            });
+        let global_bind_group = context.device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[],
+        });
+        let frame = FrameData {
+            context: &context,
+            global_bind_group: &global_bind_group,
+            opaque: &[],
+            transparent: &[],
+            batches: &[],
+            multi_draw: false,
+            compute: &[],
+        };
+
+        let mut graph = RenderGraph::new();
+        graph.import(SURFACE);
+        graph.add_texture(SCENE, TransientTexture::surface_sized(None));
+        graph.add_pass(
+            PassDesc::new("copy")
+                .reads(SCENE, TextureUsages::COPY_SRC)
+                .writes(SURFACE, TextureUsages::COPY_DST)
+                .bypass(SCENE, SURFACE),
+            CopyToSurface,
+        );
+        graph.add_pass(
+            PassDesc::new("clear").writes(SCENE, TARGET),
+            Clear(Color::RED),
+        );
+        let render = |graph: &mut RenderGraph| {
+            let mut encoder = context.device.create_command_encoder(&Default::default());
+            graph
+                .execute(&context, &mut encoder, &[(SURFACE, &surface)], &frame)
+                .unwrap();
+            context.queue.submit([encoder.finish()]);
+            read_pixel(&context, &surface)
+        };
+
+        assert_eq!(render(&mut graph), [255, 0, 0, 255]);
+        assert_eq!(graph.allocation.as_ref().unwrap().textures.len(), 1);
+
+        // Without the copy the clear goes straight to the surface, and no scene texture is made.
+        graph.set_enabled("copy", false).unwrap();
+        graph.nodes[1].pass = Box::new(Clear(Color::BLUE));
+        assert_eq!(render(&mut graph), [0, 0, 255, 255]);
+        assert!(graph.allocation.as_ref().unwrap().textures.is_empty());
+    }
+}
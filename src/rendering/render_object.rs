@@ -1,3 +1,4 @@
+use crate::aabb::Aabb;
 use crate::rendering::material::Material;
 use crate::rendering::mesh::Mesh;
 
@@ -8,6 +9,7 @@ pub struct RenderObject {
     pub pass: PassType,
     pub instances: wgpu::Buffer,
     pub instances_len: u32,
+    pub bounds: Option<Aabb>, // in world space, covering every instance. None is never culled
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
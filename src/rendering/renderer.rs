@@ -1,31 +1,87 @@
+use crate::assets::source::AssetSource;
+use crate::fatal;
 use crate::rendering::camera::Camera;
+use crate::rendering::compute::{ComputeDispatch, ComputeStage, ComputeStagePass};
+use crate::rendering::frustum::CullStats;
 use crate::rendering::global_bindings::GlobalBindings;
-use crate::rendering::main_pass::{FrameData, MainRenderPass};
+#[cfg(debug_assertions)]
+use crate::rendering::hot_reload::ShaderHotReload;
+use crate::rendering::indirect::IndirectBatch;
+use crate::rendering::main_pass::MainRenderPass;
+use crate::rendering::material::Material;
+use crate::rendering::pipeline::PipelineDescriptor;
+use crate::rendering::post_process::PostProcessPass;
+use crate::rendering::preprocessor::{BUILTIN_SHADERS, ShaderDefines};
+use crate::rendering::render_graph::*;
 use crate::rendering::render_object::*;
 use crate::rendering::shader::Shader;
-use crate::rendering::texture::Texture;
-use crate::rendering::wgpu_context::{CreateShaderError, CreateTextureError, WGPUContext};
+use crate::rendering::texture::DEPTH_FORMAT;
+use crate::rendering::transparent_pass::TransparentRenderPass;
+use crate::rendering::utils::bind_group_builder::BindGroupBuilder;
+use crate::rendering::wgpu_context::{CreateShaderError, CreateWGPUContextError, WGPUContext};
 use glam::Vec3;
+#[cfg(debug_assertions)]
+use log::warn;
+#[cfg(debug_assertions)]
+use std::cell::RefCell;
+use std::process::abort;
 use std::sync::Arc;
-use wgpu::{BindGroupLayout, SurfaceError, TextureViewDescriptor};
+use thiserror::Error;
+use wgpu::{Features, SurfaceError, TextureUsages};
 use winit::window::Window;
 
+#[derive(Error, Debug)]
+pub enum CreateRendererError {
+    #[error(transparent)]
+    Context(#[from] CreateWGPUContextError),
+    #[error(transparent)]
+    Shader(#[from] CreateShaderError),
+    #[error("The render graph is invalid: {0}")]
+    RenderGraph(#[from] RenderGraphError),
+}
+
+#[derive(Error, Debug)]
+pub enum RenderError {
+    #[error(transparent)]
+    Surface(#[from] SurfaceError),
+    #[error("Failed to run the render graph: {0}")]
+    RenderGraph(#[from] RenderGraphError),
+}
+
 pub struct Renderer {
     window: Arc<Window>,
 
     context: WGPUContext,
 
-    main_pass: MainRenderPass,
+    graph: RenderGraph,
 
     render_objects: Vec<RenderObject>,
+    indirect_batches: Vec<IndirectBatch>,
+    compute_dispatches: Vec<(ComputeStage, ComputeDispatch)>,
+    // Whether indirect batches are drawn with one multi draw instead of a draw per mesh.
+    multi_draw_indirect: bool,
     pub camera: Camera,
+    cull_stats: CullStats, // of the last frame
+    culled_elsewhere: u32, // reported with `count_culled` this frame
+
+    // None if the file watcher couldn't be started.
+    #[cfg(debug_assertions)]
+    hot_reload: Option<RefCell<ShaderHotReload>>,
 }
 
 impl Renderer {
-    pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
-        let context = WGPUContext::new(window.clone()).await?;
+    // Assets, including shaders, are read from `assets`.
+    pub async fn new(
+        window: Arc<Window>,
+        assets: AssetSource,
+    ) -> Result<Self, CreateRendererError> {
+        let context = WGPUContext::new(window.clone(), assets).await?;
 
-        let main_pass = MainRenderPass;
+        let graph = Self::create_graph(&context)?;
+        let multi_draw_indirect = context
+            .device
+            .features()
+            .contains(Features::MULTI_DRAW_INDIRECT);
         let camera = Camera {
             eye: (0.0, 1.0, 2.0).into(),
             target: (0.0, 0.0, 0.0).into(),
@@ -36,12 +92,25 @@ impl Renderer {
             far_clip: 100.0,
         };
 
+        #[cfg(debug_assertions)]
+        let hot_reload = ShaderHotReload::new(&context.assets)
+            .inspect_err(|err| warn!("Shader hot reloading is disabled: {}", err))
+            .ok()
+            .map(RefCell::new);
+
         Ok(Self {
             window,
             context,
-            main_pass,
+            graph,
             render_objects: vec![],
+            indirect_batches: vec![],
+            compute_dispatches: vec![],
+            multi_draw_indirect,
             camera,
+            cull_stats: CullStats::default(),
+            culled_elsewhere: 0,
+            #[cfg(debug_assertions)]
+            hot_reload,
         })
     }
 
@@ -53,16 +122,19 @@ impl Renderer {
             return;
         }
 
-        self.context
-            .surface
-            .configure(&self.context.device, &self.context.config);
+        if let Some(surface) = &self.context.surface {
+            surface.configure(&self.context.device, &self.context.config);
+        }
 
         self.camera.aspect = width as f32 / height as f32;
         self.context.is_surface_configured = true;
     }
 
-    pub fn render(&mut self, global_bindings: &GlobalBindings) -> Result<(), SurfaceError> {
+    pub fn render(&mut self, global_bindings: &GlobalBindings) -> Result<(), RenderError> {
         let context = &self.context;
+        let Some(surface) = &context.surface else {
+            return Ok(());
+        };
         if (context.config.width == 0 && context.config.height == 0)
             || !context.is_surface_configured
         {
@@ -71,28 +143,46 @@ impl Renderer {
 
         self.window.request_redraw();
 
-        let output = context.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&TextureViewDescriptor::default());
-
+        let output = surface.get_current_texture()?;
         let mut encoder = context.device.create_command_encoder(&Default::default());
 
-        let frame_data = FrameData {
-            color: &view,
-            global_bind_group: global_bindings.bind_group(),
+        // Objects entirely outside the camera's view are skipped.
+        let frustum = self.camera.frustum();
+        let (visible, culled): (Vec<&RenderObject>, Vec<&RenderObject>) =
+            self.render_objects.iter().partition(|obj| {
+                obj.bounds
+                    .is_none_or(|bounds| frustum.intersects_aabb(&bounds))
+            });
+        // Draws culled on the GPU can't be told apart here, so they count as drawn.
+        let batch_draws: u32 = self.indirect_batches.iter().map(|b| b.draw_count).sum();
+        self.cull_stats = CullStats {
+            drawn: visible.len() as u32 + batch_draws,
+            culled: culled.len() as u32 + self.culled_elsewhere,
         };
+        self.culled_elsewhere = 0;
 
-        let main_objects: Vec<&RenderObject> = self
-            .render_objects
+        let (opaque, transparent): (Vec<&RenderObject>, Vec<&RenderObject>) = visible
             .iter()
-            .filter(|&obj| obj.pass == self.main_pass.pass_type())
-            .collect(); // pass all non-transparent objects into the main pass.
+            .partition(|&obj| obj.pass == PassType::Opaque);
 
-        self.main_pass
-            .record(&mut encoder, &frame_data, &main_objects);
+        let frame = FrameData {
+            context,
+            global_bind_group: global_bindings.bind_group(),
+            opaque: &opaque,
+            transparent: &transparent,
+            batches: &self.indirect_batches,
+            multi_draw: self.multi_draw_indirect,
+            compute: &self.compute_dispatches,
+        };
+        let result =
+            self.graph
+                .execute(context, &mut encoder, &[(SURFACE, &output.texture)], &frame);
 
+        // Cleared even if the frame is dropped, so they don't pile up.
         self.render_objects.clear();
+        self.indirect_batches.clear();
+        self.compute_dispatches.clear();
+        result?;
         context.queue.submit([encoder.finish()]);
         self.window.pre_present_notify();
         output.present();
@@ -100,22 +190,188 @@ impl Renderer {
         Ok(())
     }
 
+    // Compute work before graphics, opaque and then transparent geometry into the scene, the
+    // scene through post-processing onto the surface, and compute work after graphics.
+    fn create_graph(context: &WGPUContext) -> Result<RenderGraph, CreateRendererError> {
+        let attachment = TextureUsages::RENDER_ATTACHMENT;
+        let mut graph = RenderGraph::new();
+        graph.import(SURFACE);
+        graph.add_texture(SCENE, TransientTexture::surface_sized(None));
+        graph.add_texture(DEPTH, TransientTexture::surface_sized(Some(DEPTH_FORMAT)));
+
+        graph.add_pass(
+            PassDesc::new(COMPUTE_BEFORE_PASS),
+            ComputeStagePass(ComputeStage::BeforeGraphics),
+        );
+        graph.add_pass(
+            PassDesc::new(MAIN_PASS)
+                .writes(SCENE, attachment)
+                .writes(DEPTH, attachment),
+            MainRenderPass,
+        );
+        graph.add_pass(
+            PassDesc::new(TRANSPARENT_PASS)
+                .writes(SCENE, attachment)
+                .writes(DEPTH, attachment),
+            TransparentRenderPass,
+        );
+        graph.add_pass(
+            PassDesc::new(POST_PROCESS_PASS)
+                .reads(SCENE, TextureUsages::TEXTURE_BINDING)
+                .writes(SURFACE, attachment)
+                .bypass(SCENE, SURFACE),
+            PostProcessPass::new(context)?,
+        );
+        graph.add_pass(
+            PassDesc::new(COMPUTE_AFTER_PASS),
+            ComputeStagePass(ComputeStage::AfterGraphics),
+        );
+        graph.compile()?;
+
+        Ok(graph)
+    }
+
+    pub fn is_pass_enabled(&self, pass: &str) -> bool {
+        self.graph.is_enabled(pass)
+    }
+
+    // Turns a pass of the render graph, like `POST_PROCESS_PASS`, on or off from the next frame.
+    pub fn set_pass_enabled(&mut self, pass: &str, enabled: bool) -> Result<(), RenderGraphError> {
+        self.graph.set_enabled(pass, enabled)
+    }
+
+    pub fn cull_stats(&self) -> CullStats {
+        self.cull_stats
+    }
+
     pub fn push_object(&mut self, obj: &RenderObject) {
         self.render_objects.push(obj.clone());
     }
 
+    // Drawn in the main pass after the render objects, see `IndirectBatch`.
+    pub fn push_batch(&mut self, batch: &IndirectBatch) {
+        self.indirect_batches.push(batch.clone());
+    }
+
+    // Recorded into this frame at `stage`, in the order pushed. Whatever a dispatch writes is
+    // visible to the passes and dispatches after it.
+    pub fn push_compute(&mut self, dispatch: &ComputeDispatch, stage: ComputeStage) {
+        self.compute_dispatches.push((stage, dispatch.clone()));
+    }
+
+    // For the stats, when something is culled before being pushed.
+    pub fn count_culled(&mut self, count: u32) {
+        self.culled_elsewhere += count;
+    }
+
     pub fn create_shader(
         &self,
         path: &str,
-        material_layout: BindGroupLayout,
         global_bindings: &GlobalBindings,
     ) -> Result<Shader, CreateShaderError> {
-        self.context
-            .create_shader(path, &global_bindings.bind_group_layout(), &material_layout)
+        self.create_shader_with_pipeline(path, global_bindings, &PipelineDescriptor::default())
+    }
+
+    pub fn create_shader_with_pipeline(
+        &self,
+        path: &str,
+        global_bindings: &GlobalBindings,
+        descriptor: &PipelineDescriptor,
+    ) -> Result<Shader, CreateShaderError> {
+        self.create_shader_with_defines(path, global_bindings, descriptor, &ShaderDefines::new())
+    }
+
+    pub fn create_shader_with_defines(
+        &self,
+        path: &str,
+        global_bindings: &GlobalBindings,
+        descriptor: &PipelineDescriptor,
+        defines: &ShaderDefines,
+    ) -> Result<Shader, CreateShaderError> {
+        let shader = self
+            .context
+            .create_shader(path, global_bindings, descriptor, defines)?;
+        self.watch_shader(&shader);
+
+        Ok(shader)
     }
 
-    pub fn create_texture(&self, path: &str) -> Result<Texture, CreateTextureError> {
-        self.context.create_texture(path)
+    // A material that draws a magenta checkerboard and binds nothing, for showing in place of
+    // shaders or materials that failed to load.
+    pub fn create_fallback_material(&self, global_bindings: &GlobalBindings) -> Material {
+        let shader = self
+            .context
+            .create_shader_from(
+                &AssetSource::new().with_embedded(BUILTIN_SHADERS),
+                "/res/shaders/fallback.wgsl",
+                global_bindings,
+                &PipelineDescriptor::default(),
+                &ShaderDefines::new(),
+            )
+            .unwrap_or_else(|err| fatal!("Failed to create fallback shader: {}", err));
+
+        self.create_material(&shader, BindGroupBuilder::new(), Some("Fallback Material"))
+            .unwrap_or_else(|err| fatal!("Failed to create fallback material: {}", err))
+    }
+
+    // Reuses `shader`'s module and layouts with a different pipeline configuration, e.g. to get a
+    // transparent version of an opaque shader.
+    pub fn create_shader_variant(
+        &self,
+        shader: &Shader,
+        descriptor: &PipelineDescriptor,
+    ) -> Result<Shader, CreateShaderError> {
+        let variant = self.context.create_shader_variant(shader, descriptor)?;
+        self.watch_shader(&variant);
+
+        Ok(variant)
+    }
+
+    fn watch_shader(&self, _shader: &Shader) {
+        #[cfg(debug_assertions)]
+        if let Some(hot_reload) = &self.hot_reload {
+            hot_reload.borrow_mut().watch(_shader);
+        }
+    }
+
+    // Recompiles shaders whose source files changed since the last call. Does nothing in release
+    // builds.
+    pub fn reload_changed_shaders(&self) {
+        #[cfg(debug_assertions)]
+        if let Some(hot_reload) = &self.hot_reload {
+            hot_reload.borrow_mut().reload_changed(&self.context);
+        }
+    }
+
+    // Fails if the resources in `bind_group` don't line up with the shader's @group(1). Arrays
+    // without a size in the shader get one from `bind_group`, which takes a copy of the shader
+    // with a layout of that size.
+    pub fn create_material(
+        &self,
+        shader: &Shader,
+        bind_group: BindGroupBuilder,
+        label: Option<&str>,
+    ) -> Result<Material, CreateShaderError> {
+        let sized = {
+            let reflection = &shader.program().reflection;
+            reflection.check_resources(1, bind_group.resources())?;
+            reflection.sized_group(1, bind_group.resources())
+        };
+        let shader = match sized {
+            Some(entries) => {
+                let sized = self
+                    .context
+                    .create_sized_material_shader(shader, &entries)?;
+                self.watch_shader(&sized);
+                sized
+            }
+            None => shader.clone(),
+        };
+
+        Ok(Material {
+            bind_group: bind_group.build(&self.context, &shader.material_layout, label),
+            shader,
+        })
     }
 
     pub fn context(&self) -> &WGPUContext {
@@ -1,9 +1,63 @@
+use crate::rendering::pipeline::PipelineDescriptor;
+use crate::rendering::preprocessor::ShaderDefines;
+use crate::rendering::reflection::ShaderReflection;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
 use wgpu::{BindGroupLayout, RenderPipeline, ShaderModule};
 
-#[derive(Clone, Eq, PartialEq, Debug)]
-pub struct Shader {
+// The parts of a shader that are replaced when it's hot reloaded. Every clone of a `Shader` shares
+// one, so materials pick up a reload without being rebuilt.
+#[derive(Debug)]
+pub(crate) struct ShaderProgram {
     pub(crate) module: ShaderModule,
     pub(crate) pipeline: RenderPipeline,
+    pub(crate) reflection: ShaderReflection,
+    pub(crate) files: Vec<String>, // every file the source was assembled from
+}
+
+#[derive(Clone, Debug)]
+pub struct Shader {
+    pub(crate) path: Option<String>, // None for shaders not loaded from a file
+    pub(crate) defines: ShaderDefines,
+    pub(crate) program: Arc<RwLock<ShaderProgram>>,
+    pub(crate) descriptor: PipelineDescriptor,
     pub(crate) global_layout: BindGroupLayout,
     pub(crate) material_layout: BindGroupLayout,
 }
+
+impl Shader {
+    pub(crate) fn new(
+        path: Option<&str>,
+        defines: &ShaderDefines,
+        program: ShaderProgram,
+        descriptor: &PipelineDescriptor,
+        global_layout: &BindGroupLayout,
+        material_layout: &BindGroupLayout,
+    ) -> Self {
+        Self {
+            path: path.map(str::to_owned),
+            defines: defines.clone(),
+            program: Arc::new(RwLock::new(program)),
+            descriptor: descriptor.clone(),
+            global_layout: global_layout.clone(),
+            material_layout: material_layout.clone(),
+        }
+    }
+
+    pub(crate) fn program(&self) -> RwLockReadGuard<'_, ShaderProgram> {
+        self.program.read().unwrap()
+    }
+
+    pub(crate) fn pipeline(&self) -> RenderPipeline {
+        self.program().pipeline.clone()
+    }
+}
+
+impl PartialEq for Shader {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.program, &other.program)
+            && self.global_layout == other.global_layout
+            && self.material_layout == other.material_layout
+    }
+}
+
+impl Eq for Shader {}
@@ -0,0 +1,177 @@
+use crate::rendering::buffer::Buffer;
+use crate::rendering::global_bindings::GlobalBindings;
+use crate::rendering::instance::InstanceData;
+use crate::rendering::mesh::Mesh;
+use crate::rendering::pipeline::PipelineDescriptor;
+use crate::rendering::render_object::{PassType, RenderObject};
+use crate::rendering::renderer::Renderer;
+use crate::rendering::shader::Shader;
+use crate::rendering::utils::bind_group_builder::BindGroupBuilder;
+use crate::rendering::utils::bind_group_layout_builder::BindGroupLayoutBuilder;
+use crate::rendering::wgpu_context::CreateShaderError;
+use crate::world::block::BlockId;
+use crate::world::mesher::cube_mesh;
+use crate::world::svo::Svo;
+use bytemuck::{Pod, Zeroable};
+use glam::{Mat4, Vec3};
+use wgpu::{BufferUsages, Face, ShaderStages};
+
+pub const SVO_SHADER: &str = "/res/shaders/svo_raymarch.wgsl";
+const PALETTE_SIZE: usize = 16;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
+struct SvoParams {
+    origin: [f32; 3],
+    size: u32,
+    eye: [f32; 3],
+    _padding: u32,
+    palette: [[f32; 4]; PALETTE_SIZE],
+}
+
+// Colours the raymarcher shades blocks with, since it doesn't sample their textures.
+fn default_palette() -> [[f32; 4]; PALETTE_SIZE] {
+    let mut palette = [[0.8, 0.3, 0.8, 1.0]; PALETTE_SIZE];
+    for (block, colour) in [
+        (BlockId::STONE, [0.5, 0.5, 0.52, 1.0]),
+        (BlockId::DIRT, [0.45, 0.32, 0.2, 1.0]),
+        (BlockId::GRASS, [0.3, 0.6, 0.25, 1.0]),
+        (BlockId::SAND, [0.85, 0.8, 0.55, 1.0]),
+        (BlockId::GRAVEL, [0.55, 0.52, 0.5, 1.0]),
+    ] {
+        palette[block.0 as usize] = colour;
+    }
+
+    palette
+}
+
+// A sparse voxel octree on the GPU, drawn by raymarching it in the fragment shader instead of
+// meshing its voxels. Meant for regions too far away for meshes to be worth their cost, see
+// `WorldRenderer::far_field`. Rays take a step per leaf they cross, so large detailed volumes are
+// slow to draw; a few chunks per edge is what it's meant for.
+pub struct SvoVolume {
+    params: SvoParams,
+    params_buffer: Buffer<SvoParams>,
+    object: RenderObject,
+}
+
+impl SvoVolume {
+    // What the raymarch shader's material has to bind, checked against the shader on creation.
+    pub fn layout() -> BindGroupLayoutBuilder {
+        BindGroupLayoutBuilder::new()
+            .with_uniform_buffer(0, ShaderStages::FRAGMENT)
+            .with_storage_buffer(1, ShaderStages::FRAGMENT)
+    }
+
+    // Shared by every volume.
+    pub fn create_shader(
+        renderer: &Renderer,
+        global_bindings: &GlobalBindings,
+    ) -> Result<Shader, CreateShaderError> {
+        // Back faces, so the box is still drawn with the camera inside it.
+        let shader = renderer.create_shader_with_pipeline(
+            SVO_SHADER,
+            global_bindings,
+            &PipelineDescriptor {
+                cull_mode: Some(Face::Front),
+                ..PipelineDescriptor::default()
+            },
+        )?;
+        shader
+            .program()
+            .reflection
+            .check_layout(1, &Self::layout().entries)?;
+
+        Ok(shader)
+    }
+
+    // `shader` has to come from `create_shader`.
+    pub fn new(renderer: &Renderer, shader: &Shader, svo: &Svo) -> Result<Self, CreateShaderError> {
+        let context = renderer.context();
+        let origin = svo.origin().as_vec3();
+        let params = SvoParams {
+            origin: origin.into(),
+            size: svo.size(),
+            eye: renderer.camera.eye.into(),
+            _padding: 0,
+            palette: default_palette(),
+        };
+        let params_buffer = Buffer::new_uniform(context, Some(&[params]));
+        let nodes = Buffer::new(context, Some(svo.nodes()), BufferUsages::STORAGE);
+
+        let material = renderer.create_material(
+            shader,
+            BindGroupBuilder::new()
+                .with_uniform_buffer(0, params_buffer.buffer())
+                .with_storage_buffer(1, nodes.buffer()),
+            Some("SVO Material"),
+        )?;
+
+        let cube = cube_mesh();
+        let mesh = Mesh::new(context, &cube.vertices, &cube.indices);
+        let instance = [InstanceData {
+            model: Mat4::from_translation(origin)
+                * Mat4::from_scale(Vec3::splat(svo.size() as f32)),
+        }];
+        let instances = Buffer::new_instance(context, Some(&instance));
+
+        Ok(Self {
+            params,
+            params_buffer,
+            object: RenderObject {
+                bounds: mesh.instance_bounds(&instance),
+                mesh,
+                material,
+                pass: PassType::Opaque,
+                instances: instances.buffer().clone(),
+                instances_len: 1,
+            },
+        })
+    }
+
+    pub fn render(&mut self, renderer: &mut Renderer) {
+        self.params.eye = renderer.camera.eye.into();
+        self.params_buffer
+            .upload(renderer.context(), &[self.params]);
+
+        renderer.push_object(&self.object);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assets::source::AssetSource;
+    use crate::rendering::preprocessor::{ShaderDefines, preprocess};
+    use crate::rendering::reflection::{ShaderReflection, wgsl_struct};
+    use std::mem::offset_of;
+
+    #[test]
+    fn raymarch_shader_matches_the_volume_bindings() {
+        let src = preprocess(
+            SVO_SHADER,
+            &ShaderDefines::new(),
+            &AssetSource::new().with_directory(env!("CARGO_MANIFEST_DIR")),
+        )
+        .unwrap();
+
+        let reflection = ShaderReflection::from_wgsl(&src.source).unwrap();
+        reflection
+            .check_layout(1, &SvoVolume::layout().entries)
+            .unwrap();
+
+        let module = naga::front::wgsl::parse_str(&src.source).unwrap();
+        let (span, offsets) = wgsl_struct(&module, "SvoParams");
+        assert_eq!(span as usize, size_of::<SvoParams>());
+        assert_eq!(
+            offsets,
+            [
+                offset_of!(SvoParams, origin),
+                offset_of!(SvoParams, size),
+                offset_of!(SvoParams, eye),
+                offset_of!(SvoParams, palette),
+            ]
+            .map(|offset| offset as u32)
+        );
+    }
+}
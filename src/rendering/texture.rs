@@ -1,7 +1,213 @@
-use wgpu::{Extent3d, TextureView};
+use crate::assets::server::Asset;
+use crate::assets::source::AssetSource;
+use crate::rendering::atlas::MISSING_TILE;
+use crate::rendering::compressed::CompressedImage;
+use crate::rendering::mipmap::mip_chain;
+use crate::rendering::wgpu_context::{CreateTextureError, WGPUContext};
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use wgpu::{Extent3d, TextureFormat, TextureView};
+
+pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+const PLACEHOLDER_SIZE: u32 = 8;
+const PLACEHOLDER_COLORS: [Rgba<u8>; 2] = [Rgba([255, 0, 255, 255]), Rgba([0, 0, 0, 255])];
 
 pub struct Texture {
+    #[allow(dead_code)]
     pub size: Extent3d,
+    #[allow(dead_code)]
     pub(crate) data: wgpu::Texture,
     pub view: TextureView,
 }
+
+pub enum TextureData {
+    Mips(Vec<RgbaImage>),
+    Ktx2(Vec<u8>),
+}
+
+// KTX2 files keep their own mip levels and format, and are parsed once the device's features are
+// known. Other images get their mip levels generated while decoding, so that happens in the
+// background too.
+impl Asset for Texture {
+    type Decoded = TextureData;
+    type Error = CreateTextureError;
+
+    fn decode(source: &AssetSource, path: &str) -> Result<TextureData, CreateTextureError> {
+        let bytes = source.read(path)?;
+        if path.ends_with(".ktx2") {
+            return Ok(TextureData::Ktx2(bytes));
+        }
+
+        let image = image::load_from_memory(&bytes)?.to_rgba8();
+
+        Ok(TextureData::Mips(mip_chain(image)))
+    }
+
+    fn create(
+        context: &WGPUContext,
+        path: &str,
+        data: TextureData,
+    ) -> Result<Self, CreateTextureError> {
+        match data {
+            TextureData::Mips(levels) => Ok(context.create_texture_from_mips(path, &levels)),
+            TextureData::Ktx2(bytes) => {
+                let image = CompressedImage::from_ktx2(&bytes, context.device.features())?;
+
+                context.create_compressed_texture(path, &image)
+            }
+        }
+    }
+
+    fn placeholder(context: &WGPUContext) -> Self {
+        let image = checkerboard(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE);
+
+        context.create_texture_from_image("Placeholder Texture", image)
+    }
+}
+
+// All layers of a texture array have to be the same size, which is returned.
+pub(crate) fn check_layer_sizes<'a>(
+    layers: impl IntoIterator<Item = &'a RgbaImage>,
+) -> Result<(u32, u32), CreateTextureError> {
+    let mut layers = layers.into_iter().peekable();
+    let expected = layers
+        .peek()
+        .ok_or(CreateTextureError::NoLayers)?
+        .dimensions();
+
+    for (layer, image) in layers.enumerate() {
+        if image.dimensions() != expected {
+            return Err(CreateTextureError::LayerSizeMismatch {
+                layer: layer as u32,
+                expected,
+                found: image.dimensions(),
+            });
+        }
+    }
+
+    Ok(expected)
+}
+
+// Named textures of the same size, one per layer of a `D2Array` texture. Unlike atlas tiles,
+// layers can't bleed into each other, so they can be filtered and mipmapped like separate
+// textures.
+pub struct TextureArray {
+    pub texture: Texture,
+    layers: HashMap<String, u32>,
+}
+
+impl TextureArray {
+    pub fn get(&self, name: &str) -> Option<u32> {
+        self.layers.get(name).copied()
+    }
+
+    // Unknown names get the missing layer.
+    pub fn layer(&self, name: &str) -> u32 {
+        self.get(name)
+            .or_else(|| self.get(MISSING_TILE))
+            .unwrap_or(0)
+    }
+}
+
+// Loaded from a directory of PNGs, each a layer named after its file without the extension. A
+// checkerboard layer is added for `MISSING_TILE`.
+impl Asset for TextureArray {
+    type Decoded = (Vec<Vec<RgbaImage>>, HashMap<String, u32>); // a mip chain per layer
+    type Error = CreateTextureError;
+
+    fn decode(source: &AssetSource, path: &str) -> Result<Self::Decoded, CreateTextureError> {
+        let mut images = vec![];
+        let mut layers = HashMap::new();
+        for file in source.list(path)? {
+            let Some(name) = file
+                .rsplit_once('/')
+                .and_then(|(_, file)| file.strip_suffix(".png"))
+            else {
+                continue;
+            };
+
+            layers.insert(name.to_owned(), images.len() as u32);
+            images.push(image::load_from_memory(&source.read(&file)?)?.to_rgba8());
+        }
+
+        // Checked here already so the missing layer can be sized to match.
+        let (width, height) = check_layer_sizes(&images)?;
+        if !layers.contains_key(MISSING_TILE) {
+            layers.insert(MISSING_TILE.to_owned(), images.len() as u32);
+            images.push(checkerboard(width, height));
+        }
+
+        Ok((images.into_iter().map(mip_chain).collect(), layers))
+    }
+
+    fn create(
+        context: &WGPUContext,
+        path: &str,
+        (images, layers): Self::Decoded,
+    ) -> Result<Self, CreateTextureError> {
+        Ok(Self {
+            texture: context.create_texture_array(path, &images)?,
+            layers,
+        })
+    }
+
+    fn placeholder(context: &WGPUContext) -> Self {
+        let image = checkerboard(PLACEHOLDER_SIZE, PLACEHOLDER_SIZE);
+
+        Self {
+            texture: context
+                .create_texture_array("Placeholder Texture Array", &[mip_chain(image)])
+                .unwrap(),
+            layers: HashMap::new(),
+        }
+    }
+}
+
+// Magenta and black, hard to mistake for a real texture.
+fn checkerboard(width: u32, height: u32) -> RgbaImage {
+    let cell = (width.min(height) / 4).max(1);
+    RgbaImage::from_fn(width, height, |x, y| {
+        PLACEHOLDER_COLORS[((x / cell + y / cell) % 2) as usize]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_must_have_the_same_size() {
+        let layers = [
+            RgbaImage::new(16, 16),
+            RgbaImage::new(16, 16),
+            RgbaImage::new(16, 8),
+        ];
+
+        assert_eq!(check_layer_sizes(&layers[..2]).unwrap(), (16, 16));
+        assert!(matches!(
+            check_layer_sizes(&layers),
+            Err(CreateTextureError::LayerSizeMismatch {
+                layer: 2,
+                expected: (16, 16),
+                found: (16, 8),
+            })
+        ));
+        assert!(matches!(
+            check_layer_sizes(&[] as &[RgbaImage]),
+            Err(CreateTextureError::NoLayers)
+        ));
+    }
+
+    #[test]
+    fn block_textures_load_as_layers() {
+        let source = AssetSource::new().with_directory(env!("CARGO_MANIFEST_DIR"));
+        let (images, layers) = TextureArray::decode(&source, "/res/textures/blocks").unwrap();
+
+        assert_eq!(images.len(), layers.len());
+        assert!(layers.contains_key("stone") && layers.contains_key(MISSING_TILE));
+        let missing = &images[layers[MISSING_TILE] as usize];
+        assert_eq!(missing[0].dimensions(), images[0][0].dimensions());
+        assert_eq!(missing.len(), images[0].len());
+    }
+}
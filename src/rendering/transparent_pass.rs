@@ -0,0 +1,62 @@
+use crate::rendering::render_graph::{DEPTH, FrameData, GraphPass, PassResources, SCENE};
+use wgpu::{
+    CommandEncoder, IndexFormat, LoadOp, Operations, RenderPassColorAttachment,
+    RenderPassDepthStencilAttachment, RenderPassDescriptor, StoreOp,
+};
+
+// Draws blended geometry (fluids, glass) on top of the main pass output. Objects are expected to
+// arrive sorted back to front.
+pub struct TransparentRenderPass;
+
+impl GraphPass for TransparentRenderPass {
+    fn record(
+        &mut self,
+        encoder: &mut CommandEncoder,
+        resources: &PassResources,
+        frame: &FrameData,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+            label: Some("Transparent Render Pass"),
+            color_attachments: &[Some(RenderPassColorAttachment {
+                view: resources.view(SCENE),
+                depth_slice: None,
+                resolve_target: None,
+                ops: Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+                view: resources.view(DEPTH),
+                depth_ops: Some(Operations {
+                    load: LoadOp::Load,
+                    store: StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        for &object in frame.transparent {
+            let material = &object.material;
+            let shader = &material.shader;
+            let mesh = &object.mesh;
+
+            render_pass.set_pipeline(&shader.pipeline());
+
+            render_pass.set_bind_group(0, frame.global_bind_group, &[]);
+            render_pass.set_bind_group(1, &material.bind_group, &[]);
+
+            render_pass.set_vertex_buffer(0, mesh.vertices.buffer().slice(..));
+            render_pass.set_vertex_buffer(1, object.instances.slice(..));
+            render_pass.set_index_buffer(mesh.indices.buffer().slice(..), IndexFormat::Uint32);
+
+            render_pass.draw_indexed(
+                mesh.start_index..mesh.num_indices,
+                0,
+                0..object.instances_len,
+            );
+        }
+    }
+}
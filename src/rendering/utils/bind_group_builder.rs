@@ -1,43 +1,197 @@
+use crate::rendering::reflection::BoundResource;
 use crate::rendering::wgpu_context::WGPUContext;
+use std::num::NonZeroU32;
 use wgpu::{
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, Buffer,
-    Label, Sampler, TextureView,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindingResource, BindingType,
+    Buffer, BufferBindingType, Label, Sampler, SamplerBindingType, StorageTextureAccess,
+    TextureFormat, TextureSampleType, TextureView, TextureViewDimension,
 };
 
+// Each `with_*` method records the binding type it stands for, which `resources` reports so the
+// bind group can be checked against the shader before wgpu sees it.
 pub struct BindGroupBuilder<'a> {
     entries: Vec<BindGroupEntry<'a>>,
+    resources: Vec<BoundResource>,
 }
 
 impl<'a> BindGroupBuilder<'a> {
     pub fn new() -> Self {
-        Self { entries: vec![] }
+        Self {
+            entries: vec![],
+            resources: vec![],
+        }
     }
 
-    pub fn with_texture2d(mut self, view: &'a TextureView) -> Self {
-        self.entries.push(BindGroupEntry {
-            binding: self.entries.len() as u32,
-            resource: BindingResource::TextureView(view),
+    fn with_resource(
+        mut self,
+        binding: u32,
+        resource: BindingResource<'a>,
+        ty: BindingType,
+        count: Option<usize>,
+    ) -> Self {
+        debug_assert!(
+            self.entries.iter().all(|entry| entry.binding != binding),
+            "binding {binding} is already used"
+        );
+        self.entries.push(BindGroupEntry { binding, resource });
+        self.resources.push(BoundResource {
+            binding,
+            ty,
+            count: count.and_then(|count| NonZeroU32::new(count as u32)),
         });
 
         self
     }
 
-    pub fn with_sampler(mut self, sampler: &'a Sampler) -> Self {
-        self.entries.push(BindGroupEntry {
-            binding: self.entries.len() as u32,
-            resource: BindingResource::Sampler(sampler),
-        });
+    fn with_texture(
+        self,
+        binding: u32,
+        view: &'a TextureView,
+        sample_type: TextureSampleType,
+        view_dimension: TextureViewDimension,
+    ) -> Self {
+        self.with_resource(
+            binding,
+            BindingResource::TextureView(view),
+            texture(sample_type, view_dimension),
+            None,
+        )
+    }
 
-        self
+    pub fn with_texture2d(self, binding: u32, view: &'a TextureView) -> Self {
+        self.with_texture(binding, view, FLOAT, TextureViewDimension::D2)
     }
 
-    pub fn with_buffer(mut self, buffer: &'a Buffer) -> Self {
-        self.entries.push(BindGroupEntry {
-            binding: self.entries.len() as u32,
-            resource: BindingResource::Buffer(buffer.as_entire_buffer_binding()),
-        });
+    // The view has to be created with `TextureViewDimension::D2Array`, like `Texture`s with
+    // layers are.
+    pub fn with_texture2d_array(self, binding: u32, view: &'a TextureView) -> Self {
+        self.with_texture(binding, view, FLOAT, TextureViewDimension::D2Array)
+    }
 
-        self
+    // The view has to be created with `TextureViewDimension::Cube`.
+    #[allow(dead_code)]
+    pub fn with_texture_cube(self, binding: u32, view: &'a TextureView) -> Self {
+        self.with_texture(binding, view, FLOAT, TextureViewDimension::Cube)
+    }
+
+    #[allow(dead_code)]
+    pub fn with_texture3d(self, binding: u32, view: &'a TextureView) -> Self {
+        self.with_texture(binding, view, FLOAT, TextureViewDimension::D3)
+    }
+
+    // A view of the depth aspect of a depth texture.
+    #[allow(dead_code)]
+    pub fn with_depth_texture(self, binding: u32, view: &'a TextureView) -> Self {
+        self.with_texture(
+            binding,
+            view,
+            TextureSampleType::Depth,
+            TextureViewDimension::D2,
+        )
+    }
+
+    // The texture has to be created with `TextureUsages::STORAGE_BINDING`.
+    #[allow(dead_code)]
+    pub fn with_storage_texture(
+        self,
+        binding: u32,
+        view: &'a TextureView,
+        format: TextureFormat,
+        access: StorageTextureAccess,
+        view_dimension: TextureViewDimension,
+    ) -> Self {
+        self.with_resource(
+            binding,
+            BindingResource::TextureView(view),
+            BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension,
+            },
+            None,
+        )
+    }
+
+    // For a `binding_array` of 2D textures. Arrays without a size in the shader take any number
+    // of views, others exactly as many as they declare.
+    #[allow(dead_code)]
+    pub fn with_texture_array(self, binding: u32, views: &'a [&'a TextureView]) -> Self {
+        self.with_resource(
+            binding,
+            BindingResource::TextureViewArray(views),
+            texture(FLOAT, TextureViewDimension::D2),
+            Some(views.len()),
+        )
+    }
+
+    pub fn with_sampler(self, binding: u32, sampler: &'a Sampler) -> Self {
+        self.with_resource(
+            binding,
+            BindingResource::Sampler(sampler),
+            BindingType::Sampler(SamplerBindingType::Filtering),
+            None,
+        )
+    }
+
+    // For samplers created with `SamplerBuilder::with_compare`.
+    #[allow(dead_code)]
+    pub fn with_comparison_sampler(self, binding: u32, sampler: &'a Sampler) -> Self {
+        self.with_resource(
+            binding,
+            BindingResource::Sampler(sampler),
+            BindingType::Sampler(SamplerBindingType::Comparison),
+            None,
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn with_sampler_array(self, binding: u32, samplers: &'a [&'a Sampler]) -> Self {
+        self.with_resource(
+            binding,
+            BindingResource::SamplerArray(samplers),
+            BindingType::Sampler(SamplerBindingType::Filtering),
+            Some(samplers.len()),
+        )
+    }
+
+    fn with_buffer(self, binding: u32, buffer: &'a Buffer, ty: BufferBindingType) -> Self {
+        self.with_resource(
+            binding,
+            BindingResource::Buffer(buffer.as_entire_buffer_binding()),
+            BindingType::Buffer {
+                ty,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            None,
+        )
+    }
+
+    pub fn with_uniform_buffer(self, binding: u32, buffer: &'a Buffer) -> Self {
+        self.with_buffer(binding, buffer, BufferBindingType::Uniform)
+    }
+
+    // For a `var<storage, read>` buffer.
+    pub fn with_storage_buffer(self, binding: u32, buffer: &'a Buffer) -> Self {
+        self.with_buffer(
+            binding,
+            buffer,
+            BufferBindingType::Storage { read_only: true },
+        )
+    }
+
+    // For a `var<storage, read_write>` buffer.
+    pub fn with_read_write_storage_buffer(self, binding: u32, buffer: &'a Buffer) -> Self {
+        self.with_buffer(
+            binding,
+            buffer,
+            BufferBindingType::Storage { read_only: false },
+        )
+    }
+
+    // The binding, type and array count of every resource added so far.
+    pub fn resources(&self) -> &[BoundResource] {
+        &self.resources
     }
 
     pub fn build(self, context: &WGPUContext, layout: &BindGroupLayout, label: Label) -> BindGroup {
@@ -48,3 +202,13 @@ impl<'a> BindGroupBuilder<'a> {
         })
     }
 }
+
+const FLOAT: TextureSampleType = TextureSampleType::Float { filterable: true };
+
+fn texture(sample_type: TextureSampleType, view_dimension: TextureViewDimension) -> BindingType {
+    BindingType::Texture {
+        sample_type,
+        view_dimension,
+        multisampled: false,
+    }
+}
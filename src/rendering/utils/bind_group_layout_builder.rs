@@ -1,10 +1,12 @@
 use crate::rendering::wgpu_context::WGPUContext;
+use std::num::NonZeroU32;
 use wgpu::{
     BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingType,
-    BufferBindingType, Label, SamplerBindingType, ShaderStages, TextureSampleType,
-    TextureViewDimension,
+    BufferBindingType, Label, SamplerBindingType, ShaderStages, StorageTextureAccess,
+    TextureFormat, TextureSampleType, TextureViewDimension,
 };
 
+// Every entry is added at an explicit binding index, matching the `@binding` in the shader.
 pub struct BindGroupLayoutBuilder {
     pub(crate) entries: Vec<BindGroupLayoutEntry>,
 }
@@ -14,43 +16,179 @@ impl BindGroupLayoutBuilder {
         Self { entries: vec![] }
     }
 
-    pub fn with_texture2d(mut self, visibility: ShaderStages) -> Self {
+    fn with_entry(mut self, binding: u32, visibility: ShaderStages, ty: BindingType) -> Self {
+        debug_assert!(
+            self.entries.iter().all(|entry| entry.binding != binding),
+            "binding {binding} is already used"
+        );
         self.entries.push(BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
+            binding,
             visibility,
-            ty: BindingType::Texture {
-                sample_type: TextureSampleType::Float { filterable: true },
-                view_dimension: TextureViewDimension::D2,
-                multisampled: false,
-            },
+            ty,
             count: None,
         });
 
         self
     }
 
-    pub fn with_sampler(mut self, visibility: ShaderStages) -> Self {
-        self.entries.push(BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
+    fn with_texture(
+        self,
+        binding: u32,
+        visibility: ShaderStages,
+        sample_type: TextureSampleType,
+        view_dimension: TextureViewDimension,
+    ) -> Self {
+        self.with_entry(
+            binding,
             visibility,
-            ty: BindingType::Sampler(SamplerBindingType::Filtering),
-            count: None,
-        });
+            BindingType::Texture {
+                sample_type,
+                view_dimension,
+                multisampled: false,
+            },
+        )
+    }
 
-        self
+    #[allow(dead_code)]
+    pub fn with_texture2d(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_texture(
+            binding,
+            visibility,
+            TextureSampleType::Float { filterable: true },
+            TextureViewDimension::D2,
+        )
     }
 
-    pub fn with_buffer(mut self, visibility: ShaderStages, buffer_type: BufferBindingType) -> Self {
-        self.entries.push(BindGroupLayoutEntry {
-            binding: self.entries.len() as u32,
+    #[allow(dead_code)]
+    pub fn with_texture2d_array(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_texture(
+            binding,
+            visibility,
+            TextureSampleType::Float { filterable: true },
+            TextureViewDimension::D2Array,
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn with_texture_cube(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_texture(
+            binding,
+            visibility,
+            TextureSampleType::Float { filterable: true },
+            TextureViewDimension::Cube,
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn with_texture3d(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_texture(
+            binding,
+            visibility,
+            TextureSampleType::Float { filterable: true },
+            TextureViewDimension::D3,
+        )
+    }
+
+    // A `texture_depth_2d`, e.g. a shadow map sampled with a comparison sampler.
+    #[allow(dead_code)]
+    pub fn with_depth_texture(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_texture(
+            binding,
+            visibility,
+            TextureSampleType::Depth,
+            TextureViewDimension::D2,
+        )
+    }
+
+    // `access` is `WriteOnly` or `ReadWrite`; read-write needs a format the adapter supports it
+    // for, like `R32Float`.
+    #[allow(dead_code)]
+    pub fn with_storage_texture(
+        self,
+        binding: u32,
+        visibility: ShaderStages,
+        format: TextureFormat,
+        access: StorageTextureAccess,
+        view_dimension: TextureViewDimension,
+    ) -> Self {
+        self.with_entry(
+            binding,
+            visibility,
+            BindingType::StorageTexture {
+                access,
+                format,
+                view_dimension,
+            },
+        )
+    }
+
+    pub fn with_sampler(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_entry(
+            binding,
+            visibility,
+            BindingType::Sampler(SamplerBindingType::Filtering),
+        )
+    }
+
+    // A `sampler_comparison`, for sampling depth textures with `textureSampleCompare`.
+    #[allow(dead_code)]
+    pub fn with_comparison_sampler(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_entry(
+            binding,
+            visibility,
+            BindingType::Sampler(SamplerBindingType::Comparison),
+        )
+    }
+
+    pub fn with_buffer(
+        self,
+        binding: u32,
+        visibility: ShaderStages,
+        buffer_type: BufferBindingType,
+    ) -> Self {
+        self.with_entry(
+            binding,
             visibility,
-            ty: BindingType::Buffer {
+            BindingType::Buffer {
                 ty: buffer_type,
                 has_dynamic_offset: false,
                 min_binding_size: None,
             },
-            count: None,
-        });
+        )
+    }
+
+    pub fn with_uniform_buffer(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_buffer(binding, visibility, BufferBindingType::Uniform)
+    }
+
+    // A `var<storage, read>` buffer, e.g. data too large for a uniform.
+    pub fn with_storage_buffer(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_buffer(
+            binding,
+            visibility,
+            BufferBindingType::Storage { read_only: true },
+        )
+    }
+
+    // A `var<storage, read_write>` buffer.
+    #[allow(dead_code)]
+    pub fn with_read_write_storage_buffer(self, binding: u32, visibility: ShaderStages) -> Self {
+        self.with_buffer(
+            binding,
+            visibility,
+            BufferBindingType::Storage { read_only: false },
+        )
+    }
+
+    // Turns the entry added last into a `binding_array` of `count` elements. Needs the
+    // `TEXTURE_BINDING_ARRAY` feature for textures and samplers.
+    #[allow(dead_code)]
+    pub fn with_array_count(mut self, count: NonZeroU32) -> Self {
+        let entry = self
+            .entries
+            .last_mut()
+            .expect("binding arrays need an entry to apply to");
+        entry.count = Some(count);
 
         self
     }
@@ -64,3 +202,76 @@ impl BindGroupLayoutBuilder {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::reflection::ShaderReflection;
+
+    #[test]
+    fn entries_match_what_shaders_declare() {
+        let reflection = ShaderReflection::from_wgsl(
+            "
+            @group(1) @binding(0) var<storage, read> nodes: array<u32>;
+            @group(1) @binding(1) var<storage, read_write> counts: array<atomic<u32>>;
+            @group(1) @binding(2) var output: texture_storage_2d<rgba8unorm, write>;
+            @group(1) @binding(3) var accumulated: texture_storage_2d<r32float, read_write>;
+            @group(1) @binding(4) var shadow_map: texture_depth_2d;
+            @group(1) @binding(5) var shadow_sampler: sampler_comparison;
+            @group(1) @binding(6) var sky: texture_cube<f32>;
+            @group(1) @binding(7) var volume: texture_3d<f32>;
+            @group(1) @binding(8) var layers: binding_array<texture_2d<f32>, 4>;
+            @group(1) @binding(10) var linear: sampler;
+
+            @compute @workgroup_size(1)
+            fn main() {
+                let node = nodes[0];
+                atomicAdd(&counts[0], 1u);
+                textureStore(output, vec2<i32>(0), vec4<f32>(1.0));
+                let sum = textureLoad(accumulated, vec2<i32>(0));
+                textureStore(accumulated, vec2<i32>(0), sum);
+                let shadow = textureSampleCompareLevel(shadow_map, shadow_sampler, vec2<f32>(0.5), 0.5);
+                let sky_colour = textureSampleLevel(sky, linear, vec3<f32>(1.0), 0.0);
+                let density = textureSampleLevel(volume, linear, vec3<f32>(0.5), 0.0);
+                let layer = textureSampleLevel(layers[2], linear, vec2<f32>(0.5), 0.0);
+            }
+            ",
+        )
+        .unwrap();
+
+        let compute = ShaderStages::COMPUTE;
+        let builder = BindGroupLayoutBuilder::new()
+            .with_sampler(10, compute)
+            .with_storage_buffer(0, compute)
+            .with_read_write_storage_buffer(1, compute)
+            .with_storage_texture(
+                2,
+                compute,
+                TextureFormat::Rgba8Unorm,
+                StorageTextureAccess::WriteOnly,
+                TextureViewDimension::D2,
+            )
+            .with_storage_texture(
+                3,
+                compute,
+                TextureFormat::R32Float,
+                StorageTextureAccess::ReadWrite,
+                TextureViewDimension::D2,
+            )
+            .with_depth_texture(4, compute)
+            .with_comparison_sampler(5, compute)
+            .with_texture_cube(6, compute)
+            .with_texture3d(7, compute)
+            .with_texture2d(8, compute)
+            .with_array_count(NonZeroU32::new(4).unwrap());
+        assert_eq!(reflection.check_layout(1, &builder.entries), Ok(()));
+
+        // Entries at the wrong index, or of the wrong kind, are caught.
+        let shifted = BindGroupLayoutBuilder::new()
+            .with_storage_buffer(1, compute)
+            .with_read_write_storage_buffer(0, compute);
+        assert!(reflection.check_layout(1, &shifted.entries).is_err());
+        let filtering = BindGroupLayoutBuilder::new().with_sampler(5, compute);
+        assert!(reflection.check_layout(1, &filtering.entries).is_err());
+    }
+}
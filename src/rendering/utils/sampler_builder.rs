@@ -1,5 +1,5 @@
 use crate::rendering::wgpu_context::WGPUContext;
-use wgpu::{AddressMode, FilterMode, Label, Sampler, SamplerDescriptor};
+use wgpu::{AddressMode, CompareFunction, FilterMode, Label, Sampler, SamplerDescriptor};
 
 pub struct SamplerBuilder<'a> {
     desc: SamplerDescriptor<'a>,
@@ -28,6 +28,15 @@ impl<'a> SamplerBuilder<'a> {
         self
     }
 
+    // Makes a comparison sampler, e.g. for shadow maps, which compares depth samples against a
+    // reference instead of returning them.
+    #[allow(dead_code)]
+    pub fn with_compare(mut self, compare: CompareFunction) -> Self {
+        self.desc.compare = Some(compare);
+
+        self
+    }
+
     pub fn build(mut self, context: &WGPUContext, label: Label<'a>) -> Sampler {
         self.desc.label = label;
         context.device.create_sampler(&self.desc)
@@ -6,11 +6,13 @@ use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexStepMode, v
 pub struct Vertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub layer: u32, // in texture arrays, ignored by shaders sampling a 2D texture
 }
 
 impl Vertex {
     pub(crate) fn desc() -> VertexBufferLayout<'static> {
-        const ATTRIBS: [VertexAttribute; 2] = vertex_attr_array![0 => Float32x3, 1 => Float32x2];
+        const ATTRIBS: [VertexAttribute; 3] =
+            vertex_attr_array![0 => Float32x3, 1 => Float32x2, 2 => Uint32];
 
         VertexBufferLayout {
             array_stride: size_of::<Vertex>() as BufferAddress,
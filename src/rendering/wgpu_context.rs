@@ -1,28 +1,36 @@
-use crate::cubes::CubeData;
-use crate::rendering::shader::Shader;
-use crate::rendering::texture::Texture;
-use crate::rendering::vertex::Vertex;
-use bytemuck::checked::cast_slice;
-use bytemuck::{Pod, Zeroable};
-use image::{ImageError, ImageReader};
+use crate::assets::source::{AssetError, AssetSource};
+use crate::rendering::compressed::{
+    CompressedImage, TEXTURE_COMPRESSION_FEATURES, level_byte_size,
+};
+use crate::rendering::compute::ComputeShader;
+use crate::rendering::global_bindings::GlobalBindings;
+use crate::rendering::mipmap::mip_chain;
+use crate::rendering::pipeline::{PipelineCache, PipelineDescriptor};
+use crate::rendering::preprocessor::{
+    PreprocessError, PreprocessedShader, ShaderDefines, preprocess,
+};
+use crate::rendering::reflection::{BindingMismatchError, ReflectionError, ShaderReflection};
+use crate::rendering::shader::{Shader, ShaderProgram};
+use crate::rendering::texture::{Texture, check_layer_sizes};
+use image::{ImageError, RgbaImage};
+use log::info;
 use std::fmt::Debug;
 use std::sync::Arc;
-use std::{fs, io};
 use thiserror::Error;
 use wgpu::MemoryHints::Performance;
 use wgpu::PowerPreference::HighPerformance;
 use wgpu::PresentMode::{Fifo, Mailbox};
-use wgpu::util::{BufferInitDescriptor, DeviceExt};
 use wgpu::{
-    Adapter, Backends, BindGroupLayout, BlendState, Buffer, BufferUsages, ColorTargetState,
-    ColorWrites, CreateSurfaceError, Device, DeviceDescriptor, Extent3d, Face, Features,
-    FragmentState, FrontFace, Instance, InstanceDescriptor, Limits, MultisampleState, Origin3d,
-    PipelineCompilationOptions, PipelineLayoutDescriptor, PolygonMode, PrimitiveState,
-    PrimitiveTopology, Queue, RenderPipeline, RenderPipelineDescriptor, RequestAdapterError,
-    RequestAdapterOptions, RequestDeviceError, ShaderModule, ShaderModuleDescriptor, ShaderSource,
-    Surface, SurfaceConfiguration, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect,
+    Adapter, Backends, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
+    ColorTargetState, ComputePipelineDescriptor, CreateSurfaceError, DepthBiasState,
+    DepthStencilState, Device, DeviceDescriptor, ErrorFilter, Extent3d, Features, FragmentState,
+    IndexFormat, Instance, InstanceDescriptor, Limits, MultisampleState, Origin3d,
+    PipelineCompilationOptions, PipelineLayoutDescriptor, PolygonMode, PrimitiveState, Queue,
+    RenderPipeline, RenderPipelineDescriptor, RequestAdapterError, RequestAdapterOptions,
+    RequestDeviceError, ShaderModule, ShaderModuleDescriptor, ShaderSource, StencilState, Surface,
+    SurfaceConfiguration, TexelCopyBufferLayout, TexelCopyTextureInfo, TextureAspect,
     TextureDescriptor, TextureDimension, TextureFormat, TextureUsages, TextureViewDescriptor,
-    Trace, VertexState,
+    TextureViewDimension, Trace, VertexState,
 };
 use winit::window::Window;
 
@@ -38,28 +46,71 @@ pub enum CreateWGPUContextError {
 
 #[derive(Error, Debug)]
 pub enum CreateShaderError {
-    #[error("Failed to read shader file due to {0:?}.")]
-    IoError(#[from] io::Error),
+    #[error("Failed to preprocess shader: {0}")]
+    Preprocess(#[from] PreprocessError),
+    #[error("{file}:{line}:{column}: {message}")]
+    Compile {
+        file: String,
+        line: u32,
+        column: u32,
+        message: String,
+    },
+    #[error("Failed to reflect shader: {0}")]
+    Reflection(#[from] ReflectionError),
+    #[error("Shader doesn't match its bindings: {0}")]
+    BindingMismatch(#[from] BindingMismatchError),
 }
 
+// The file errors are reported in for shaders that aren't associated with one.
+const EMBEDDED_SHADER: &str = "<embedded shader>";
+
 #[derive(Error, Debug)]
 pub enum CreateTextureError {
-    #[error("Failed to read texture file due to {0:?}.")]
-    IoError(#[from] io::Error),
+    #[error("Failed to read texture: {0}")]
+    Asset(#[from] AssetError),
     #[error("Failed to decode texture due to {0:?}.")]
     DecodeError(#[from] ImageError),
+    #[error("Texture arrays need at least one layer.")]
+    NoLayers,
+    #[error("Texture array layer {layer} is {found:?}, but the first layer is {expected:?}.")]
+    LayerSizeMismatch {
+        layer: u32,
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    #[error("Failed to parse KTX2 container: {0}")]
+    Ktx2(#[from] ktx2::ParseError),
+    #[error("KTX2 format {0} isn't supported.")]
+    UnsupportedFormat(String),
+    #[error("KTX2 supercompression {0} isn't supported.")]
+    UnsupportedSupercompression(String),
+    #[error("Only single 2D KTX2 textures are supported, found {0}.")]
+    UnsupportedLayout(String),
+    #[error("KTX2 mip level {level} should be {expected} bytes, but is {found}.")]
+    LevelSizeMismatch {
+        level: u32,
+        expected: usize,
+        found: usize,
+    },
+    #[error("The device doesn't support {0:?} and it can't be decompressed on the CPU.")]
+    NoFallback(TextureFormat),
 }
 
 pub struct WGPUContext {
     pub(crate) device: Device,
     pub(crate) queue: Queue,
     pub(crate) config: SurfaceConfiguration,
-    pub(crate) surface: Surface<'static>,
-    pub(crate) is_surface_configured: bool, // MacOS/Metal support
+    pub(crate) surface: Option<Surface<'static>>, // None for headless contexts
+    pub(crate) is_surface_configured: bool,       // MacOS/Metal support
+    pub(crate) pipelines: PipelineCache,
+    pub(crate) assets: AssetSource,
 }
 
 impl WGPUContext {
-    pub async fn new(window: Arc<Window>) -> Result<Self, CreateWGPUContextError> {
+    pub async fn new(
+        window: Arc<Window>,
+        assets: AssetSource,
+    ) -> Result<Self, CreateWGPUContextError> {
         let instance = Instance::new(&InstanceDescriptor {
             backends: Backends::PRIMARY,
             ..Default::default()
@@ -75,26 +126,85 @@ impl WGPUContext {
             .await?;
 
         let surface_config = Self::setup_surface_config(&adapter, &surface, window.clone());
+        let (device, queue) = Self::request_device(&adapter).await?;
 
-        let (device, queue) = adapter
-            .request_device(&DeviceDescriptor {
-                label: None,
-                required_features: Features::empty(),
-                required_limits: Limits::default(),
-                memory_hints: Performance,
-                trace: Trace::Off,
+        Ok(Self {
+            device,
+            queue,
+            config: surface_config,
+            surface: Some(surface),
+            is_surface_configured: false,
+            pipelines: PipelineCache::default(),
+            assets,
+        })
+    }
+
+    // A context without a window, on the software adapter, e.g. for running compute shaders in
+    // tests. `config` describes a `width` x `height` target nothing is presented to.
+    #[cfg(test)]
+    pub async fn headless(
+        assets: AssetSource,
+        width: u32,
+        height: u32,
+    ) -> Result<Self, CreateWGPUContextError> {
+        let instance = Instance::new(&InstanceDescriptor {
+            backends: Backends::all(),
+            ..Default::default()
+        });
+        let adapter = instance
+            .request_adapter(&RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::None,
+                force_fallback_adapter: true,
+                compatible_surface: None,
             })
             .await?;
+        let (device, queue) = Self::request_device(&adapter).await?;
 
         Ok(Self {
             device,
             queue,
-            config: surface_config,
-            surface,
+            config: SurfaceConfiguration {
+                usage: TextureUsages::RENDER_ATTACHMENT,
+                format: TextureFormat::Rgba8UnormSrgb,
+                width,
+                height,
+                present_mode: Fifo,
+                desired_maximum_frame_latency: 2,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+            },
+            surface: None,
             is_surface_configured: false,
+            pipelines: PipelineCache::default(),
+            assets,
         })
     }
 
+    async fn request_device(adapter: &Adapter) -> Result<(Device, Queue), RequestDeviceError> {
+        adapter
+            .request_device(&DeviceDescriptor {
+                label: None,
+                // Optional features, used when the adapter has them.
+                required_features: adapter.features()
+                    & (TEXTURE_COMPRESSION_FEATURES
+                        | Features::MULTI_DRAW_INDIRECT
+                        | Features::TEXTURE_BINDING_ARRAY),
+                // Binding arrays are limited to 0 elements unless asked for.
+                required_limits: Limits {
+                    max_binding_array_elements_per_shader_stage: adapter
+                        .limits()
+                        .max_binding_array_elements_per_shader_stage,
+                    max_binding_array_sampler_elements_per_shader_stage: adapter
+                        .limits()
+                        .max_binding_array_sampler_elements_per_shader_stage,
+                    ..Limits::default()
+                },
+                memory_hints: Performance,
+                trace: Trace::Off,
+            })
+            .await
+    }
+
     fn setup_surface_config(
         adapter: &Adapter,
         surface: &Surface,
@@ -130,12 +240,14 @@ impl WGPUContext {
         }
     }
 
-    pub(crate) fn create_texture(&self, path: &str) -> Result<Texture, CreateTextureError> {
-        let image = ImageReader::open(env!("CARGO_MANIFEST_DIR").to_owned() + path)?.decode()?;
-        let image_rgba = image.to_rgba8();
+    // Generates the full mip chain.
+    pub(crate) fn create_texture_from_image(&self, label: &str, image_rgba: RgbaImage) -> Texture {
+        self.create_texture_from_mips(label, &mip_chain(image_rgba))
+    }
 
-        use image::GenericImageView;
-        let (width, height) = image.dimensions();
+    // `levels` starts with the full size image, each following one half the size of the last.
+    pub(crate) fn create_texture_from_mips(&self, label: &str, levels: &[RgbaImage]) -> Texture {
+        let (width, height) = levels[0].dimensions();
 
         let size = Extent3d {
             width,
@@ -144,9 +256,9 @@ impl WGPUContext {
         };
 
         let texture = self.device.create_texture(&TextureDescriptor {
-            label: Some(path),
+            label: Some(label),
             size,
-            mip_level_count: 1,
+            mip_level_count: levels.len() as u32,
             sample_count: 1,
             dimension: TextureDimension::D2,
             format: TextureFormat::Rgba8UnormSrgb,
@@ -154,24 +266,138 @@ impl WGPUContext {
             view_formats: &[],
         });
 
-        self.queue.write_texture(
-            TexelCopyTextureInfo {
-                texture: &texture,
-                mip_level: 0,
-                origin: Origin3d::ZERO,
-                aspect: TextureAspect::All,
-            },
-            &image_rgba,
-            TexelCopyBufferLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * width),
-                rows_per_image: Some(height),
-            },
+        self.write_mip_levels(&texture, 0, levels);
+
+        let view = texture.create_view(&TextureViewDescriptor::default());
+
+        Texture {
             size,
+            data: texture,
+            view,
+        }
+    }
+
+    // Uploads the levels as they are if the device supports the format, otherwise decompresses
+    // them first.
+    pub(crate) fn create_compressed_texture(
+        &self,
+        label: &str,
+        image: &CompressedImage,
+    ) -> Result<Texture, CreateTextureError> {
+        if image.is_supported(self.device.features()) {
+            return Ok(self.create_texture_with_format(
+                label,
+                image.format,
+                (image.width, image.height),
+                &image.levels,
+            ));
+        }
+
+        info!(
+            "Decompressing {label} on the CPU, since the device doesn't support {:?}",
+            image.format
         );
+        let (format, levels) = image.decompress()?;
+        let levels: Vec<Vec<u8>> = levels.into_iter().map(RgbaImage::into_raw).collect();
+
+        Ok(self.create_texture_with_format(label, format, (image.width, image.height), &levels))
+    }
+
+    // Tightly packed levels in any format, including block compressed ones.
+    pub(crate) fn create_texture_with_format(
+        &self,
+        label: &str,
+        format: TextureFormat,
+        (width, height): (u32, u32),
+        levels: &[Vec<u8>],
+    ) -> Texture {
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: levels.len() as u32,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let (_, block_height) = format.block_dimensions();
+        for (mip_level, data) in levels.iter().enumerate() {
+            let mip_level = mip_level as u32;
+            let physical = size
+                .mip_level_size(mip_level, TextureDimension::D2)
+                .physical_size(format);
+            let rows = physical.height / block_height;
+            let bytes_per_row = level_byte_size(format, width, height, mip_level) as u32 / rows;
+
+            self.queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture: &texture,
+                    mip_level,
+                    origin: Origin3d::ZERO,
+                    aspect: TextureAspect::All,
+                },
+                data,
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: Some(rows),
+                },
+                physical,
+            );
+        }
 
         let view = texture.create_view(&TextureViewDescriptor::default());
 
+        Texture {
+            size,
+            data: texture,
+            view,
+        }
+    }
+
+    // A `D2Array` texture with one layer per mip chain, see `mip_chain`. All layers have to be the
+    // same size.
+    pub(crate) fn create_texture_array(
+        &self,
+        label: &str,
+        layers: &[Vec<RgbaImage>],
+    ) -> Result<Texture, CreateTextureError> {
+        let (width, height) = check_layer_sizes(layers.iter().map(|levels| &levels[0]))?;
+        let size = Extent3d {
+            width,
+            height,
+            depth_or_array_layers: layers.len() as u32,
+        };
+
+        let texture = self.device.create_texture(&TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: layers[0].len() as u32,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: TextureFormat::Rgba8UnormSrgb,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (layer, levels) in layers.iter().enumerate() {
+            self.write_mip_levels(&texture, layer as u32, levels);
+        }
+
+        // Without the explicit dimension a single layer would be viewed as a plain 2D texture.
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+
         Ok(Texture {
             size,
             data: texture,
@@ -179,71 +405,417 @@ impl WGPUContext {
         })
     }
 
+    // Uploads `levels`, starting at mip level 0, into one layer of `texture`.
+    fn write_mip_levels(&self, texture: &wgpu::Texture, layer: u32, levels: &[RgbaImage]) {
+        for (mip_level, image_rgba) in levels.iter().enumerate() {
+            let (width, height) = image_rgba.dimensions();
+            self.queue.write_texture(
+                TexelCopyTextureInfo {
+                    texture,
+                    mip_level: mip_level as u32,
+                    origin: Origin3d {
+                        x: 0,
+                        y: 0,
+                        z: layer,
+                    },
+                    aspect: TextureAspect::All,
+                },
+                image_rgba,
+                TexelCopyBufferLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * width),
+                    rows_per_image: Some(height),
+                },
+                Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+            );
+        }
+    }
+
+    // The material layout (@group(1)) is derived from the shader itself. @group(0) has to match
+    // the global bindings and the vertex inputs have to match the descriptor's vertex layouts.
     pub(crate) fn create_shader(
         &self,
         path: &str,
-        global_layout: &BindGroupLayout,
-        material_layout: &BindGroupLayout,
+        global_bindings: &GlobalBindings,
+        descriptor: &PipelineDescriptor,
+        defines: &ShaderDefines,
     ) -> Result<Shader, CreateShaderError> {
-        let src = fs::read_to_string(env!("OUT_DIR").to_owned() + path)?;
-        let shader = self.device.create_shader_module(ShaderModuleDescriptor {
-            label: Some(path),
-            source: ShaderSource::Wgsl(src.into()),
-        });
+        let mut shader =
+            self.create_shader_from(&self.assets, path, global_bindings, descriptor, defines)?;
+        shader.path = Some(path.to_owned());
 
-        let pipeline = self.create_render_pipeline(&shader, [global_layout, material_layout]);
+        Ok(shader)
+    }
+
+    // Like `create_shader`, but reads the shader and its includes from `assets`. The result isn't
+    // associated with a file, so it won't be hot reloaded.
+    pub(crate) fn create_shader_from(
+        &self,
+        assets: &AssetSource,
+        path: &str,
+        global_bindings: &GlobalBindings,
+        descriptor: &PipelineDescriptor,
+        defines: &ShaderDefines,
+    ) -> Result<Shader, CreateShaderError> {
+        let src = preprocess(path, defines, assets)?;
+        let (module, reflection) = self.compile_shader(path, &src)?;
+        check_render_groups(&reflection)?;
+        reflection.check_layout(0, global_bindings.layout_entries())?;
+        reflection.check_vertex_layouts(&descriptor.vertex_entry, &descriptor.vertex_layouts)?;
+
+        let material_layout = self
+            .device
+            .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: Some(path),
+                entries: reflection.group(1),
+            });
+
+        // Vertex layouts and groups are checked above, but not e.g. the fragment outputs against
+        // the targets.
+        let global_layout = global_bindings.bind_group_layout();
+        let pipeline = self.create_validated_render_pipeline(
+            path,
+            &module,
+            &[global_layout, &material_layout],
+            descriptor,
+        )?;
+
+        Ok(Shader::new(
+            None,
+            defines,
+            ShaderProgram {
+                module,
+                pipeline,
+                reflection,
+                files: src.files,
+            },
+            descriptor,
+            global_layout,
+            &material_layout,
+        ))
+    }
+
+    // Unlike render shaders, compute shaders have no global group and may use any groups the
+    // device supports. Groups in between declared ones get empty layouts.
+    pub(crate) fn create_compute_shader_from(
+        &self,
+        assets: &AssetSource,
+        path: &str,
+        entry_point: &str,
+        defines: &ShaderDefines,
+    ) -> Result<ComputeShader, CreateShaderError> {
+        let src = preprocess(path, defines, assets)?;
+        let (module, reflection) = self.compile_shader(path, &src)?;
+        let workgroup_size = reflection.workgroup_size(entry_point)?;
+
+        let max = self.device.limits().max_bind_groups;
+        let group_count = reflection.groups().last().map_or(0, |group| group + 1);
+        if group_count > max {
+            return Err(BindingMismatchError::TooManyGroups {
+                group: group_count - 1,
+                max,
+            }
+            .into());
+        }
+
+        let layouts: Vec<BindGroupLayout> = (0..group_count)
+            .map(|group| {
+                self.device
+                    .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                        label: Some(path),
+                        entries: reflection.group(group),
+                    })
+            })
+            .collect();
+        let pipeline_layout = self
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some(path),
+                bind_group_layouts: &layouts.iter().collect::<Vec<_>>(),
+                push_constant_ranges: &[],
+            });
+        let pipeline = self.validated(path, || {
+            self.device
+                .create_compute_pipeline(&ComputePipelineDescriptor {
+                    label: Some(path),
+                    layout: Some(&pipeline_layout),
+                    module: &module,
+                    entry_point: Some(entry_point),
+                    compilation_options: Default::default(),
+                    cache: None,
+                })
+        })?;
 
-        Ok(Shader {
-            module: shader,
+        Ok(ComputeShader {
             pipeline,
-            global_layout: global_layout.clone(),
-            material_layout: material_layout.clone(),
+            layouts,
+            reflection,
+            workgroup_size,
         })
     }
 
+    // Validates the source before anything is created on the device, so broken shaders come back
+    // as errors instead of panics.
+    pub(crate) fn compile_shader(
+        &self,
+        name: &str,
+        src: &PreprocessedShader,
+    ) -> Result<(ShaderModule, ShaderReflection), CreateShaderError> {
+        let reflection = ShaderReflection::from_wgsl(&src.source).map_err(|err| match err {
+            // Errors refer to the preprocessed source, so they're mapped back to where the line
+            // was written.
+            ReflectionError::Compile {
+                line,
+                column,
+                message,
+            } => match src.origin(line) {
+                Some(origin) => CreateShaderError::Compile {
+                    file: origin.file.clone(),
+                    line: origin.line,
+                    column,
+                    message,
+                },
+                None => CreateShaderError::Compile {
+                    file: name.to_owned(),
+                    line,
+                    column,
+                    message,
+                },
+            },
+            err => err.into(),
+        })?;
+        // naga above may accept things this device doesn't support.
+        let module = self.validated(name, || {
+            self.device.create_shader_module(ShaderModuleDescriptor {
+                label: Some(name),
+                source: ShaderSource::Wgsl(src.source.as_str().into()),
+            })
+        })?;
+
+        Ok((module, reflection))
+    }
+
+    // Runs `create` in validation and internal error scopes, so what wgpu would otherwise report
+    // through its uncaptured error handler, which panics, is returned as an error in `file` instead.
+    // Internal errors are what the backends report when they fail to compile the translated shader.
+    fn validated<T>(&self, file: &str, create: impl FnOnce() -> T) -> Result<T, CreateShaderError> {
+        self.device.push_error_scope(ErrorFilter::Internal);
+        self.device.push_error_scope(ErrorFilter::Validation);
+        let result = create();
+        let validation = pollster::block_on(self.device.pop_error_scope());
+        let internal = pollster::block_on(self.device.pop_error_scope());
+        match validation.or(internal) {
+            Some(err) => Err(CreateShaderError::Compile {
+                file: file.to_owned(),
+                line: 0,
+                column: 0,
+                message: err.to_string(),
+            }),
+            None => Ok(result),
+        }
+    }
+
+    // Recompiles `shader` from its files and swaps the result in for every
+    // material using it. The new source can't change the bindings the old one declared, since
+    // existing bind groups were built against them. On any error the shader is left untouched.
+    #[cfg(debug_assertions)]
+    pub(crate) fn reload_shader(&self, shader: &Shader) -> Result<(), CreateShaderError> {
+        let Some(path) = shader.path.as_deref() else {
+            return Ok(());
+        };
+        let src = preprocess(path, &shader.defines, &self.assets)?;
+        let (module, reflection) = self.compile_shader(path, &src)?;
+        check_render_groups(&reflection)?;
+
+        {
+            let old = shader.program();
+            reflection.check_layout(0, old.reflection.group(0))?;
+            reflection.check_layout(1, old.reflection.group(1))?;
+        }
+        let descriptor = &shader.descriptor;
+        reflection.check_vertex_layouts(&descriptor.vertex_entry, &descriptor.vertex_layouts)?;
+
+        let pipeline = self.create_validated_render_pipeline(
+            path,
+            &module,
+            &[&shader.global_layout, &shader.material_layout],
+            descriptor,
+        )?;
+
+        let old = std::mem::replace(
+            &mut *shader.program.write().unwrap(),
+            ShaderProgram {
+                module,
+                pipeline,
+                reflection,
+                files: src.files,
+            },
+        );
+        // Other variants of the old module keep their own pipelines, but nothing will ask the
+        // cache for them again.
+        self.pipelines.remove_module(&old.module);
+
+        Ok(())
+    }
+
+    // The same shader module and layouts drawn with a different pipeline configuration.
+    pub(crate) fn create_shader_variant(
+        &self,
+        shader: &Shader,
+        descriptor: &PipelineDescriptor,
+    ) -> Result<Shader, CreateShaderError> {
+        shader
+            .program()
+            .reflection
+            .check_vertex_layouts(&descriptor.vertex_entry, &descriptor.vertex_layouts)?;
+
+        self.rebuild_shader(shader, descriptor, &shader.material_layout)
+    }
+
+    // The same shader with @group(1) laid out as `entries`, for materials that size arrays the
+    // shader leaves unsized, see `ShaderReflection::sized_group`.
+    pub(crate) fn create_sized_material_shader(
+        &self,
+        shader: &Shader,
+        entries: &[BindGroupLayoutEntry],
+    ) -> Result<Shader, CreateShaderError> {
+        let material_layout = self
+            .device
+            .create_bind_group_layout(&BindGroupLayoutDescriptor {
+                label: shader.path.as_deref(),
+                entries,
+            });
+
+        self.rebuild_shader(shader, &shader.descriptor, &material_layout)
+    }
+
+    fn rebuild_shader(
+        &self,
+        shader: &Shader,
+        descriptor: &PipelineDescriptor,
+        material_layout: &BindGroupLayout,
+    ) -> Result<Shader, CreateShaderError> {
+        let program = shader.program();
+        let pipeline = self.create_validated_render_pipeline(
+            shader.path.as_deref().unwrap_or(EMBEDDED_SHADER),
+            &program.module,
+            &[&shader.global_layout, material_layout],
+            descriptor,
+        )?;
+
+        Ok(Shader::new(
+            shader.path.as_deref(),
+            &shader.defines,
+            ShaderProgram {
+                module: program.module.clone(),
+                pipeline,
+                reflection: program.reflection.clone(),
+                files: program.files.clone(),
+            },
+            descriptor,
+            &shader.global_layout,
+            material_layout,
+        ))
+    }
+
+    // `create_render_pipeline`, with errors returned as coming from `file`. A pipeline that
+    // failed to create isn't kept in the cache.
+    fn create_validated_render_pipeline(
+        &self,
+        file: &str,
+        module: &ShaderModule,
+        layouts: &[&BindGroupLayout],
+        descriptor: &PipelineDescriptor,
+    ) -> Result<RenderPipeline, CreateShaderError> {
+        self.validated(file, || {
+            self.create_render_pipeline(module, layouts, descriptor)
+        })
+        .inspect_err(|_| self.pipelines.remove(module, layouts, descriptor))
+    }
+
+    // Returns the cached pipeline if one was already created for this exact configuration.
     pub(crate) fn create_render_pipeline(
         &self,
         shader: &ShaderModule,
-        layouts: [&BindGroupLayout; 2],
+        layouts: &[&BindGroupLayout],
+        descriptor: &PipelineDescriptor,
+    ) -> RenderPipeline {
+        self.pipelines
+            .get_or_create(shader, layouts, descriptor, || {
+                self.build_render_pipeline(shader, layouts, descriptor)
+            })
+    }
+
+    fn build_render_pipeline(
+        &self,
+        shader: &ShaderModule,
+        layouts: &[&BindGroupLayout],
+        descriptor: &PipelineDescriptor,
     ) -> RenderPipeline {
         let render_pipeline_layout =
             self.device
                 .create_pipeline_layout(&PipelineLayoutDescriptor {
                     label: None,
-                    bind_group_layouts: &layouts,
+                    bind_group_layouts: layouts,
                     push_constant_ranges: &[],
                 });
 
+        let targets: Vec<Option<ColorTargetState>> = descriptor
+            .targets
+            .iter()
+            .map(|target| {
+                Some(ColorTargetState {
+                    format: target.format.unwrap_or(self.config.format),
+                    blend: target.blend,
+                    write_mask: target.write_mask,
+                })
+            })
+            .collect();
+
         self.device
             .create_render_pipeline(&RenderPipelineDescriptor {
                 label: None,
                 layout: Some(&render_pipeline_layout),
                 vertex: VertexState {
                     module: shader,
-                    entry_point: Some("vs_main"),
-                    buffers: &[Vertex::desc(), CubeData::desc()],
+                    entry_point: Some(&descriptor.vertex_entry),
+                    buffers: &descriptor.vertex_layouts,
                     compilation_options: PipelineCompilationOptions::default(),
                 },
-                fragment: Some(FragmentState {
-                    module: shader,
-                    entry_point: Some("fs_main"),
-                    targets: &[Some(ColorTargetState {
-                        format: self.config.format,
-                        blend: Some(BlendState::REPLACE),
-                        write_mask: ColorWrites::ALL,
-                    })],
-                    compilation_options: PipelineCompilationOptions::default(),
-                }),
+                fragment: descriptor
+                    .fragment_entry
+                    .as_deref()
+                    .map(|entry_point| FragmentState {
+                        module: shader,
+                        entry_point: Some(entry_point),
+                        targets: &targets,
+                        compilation_options: PipelineCompilationOptions::default(),
+                    }),
                 primitive: PrimitiveState {
-                    topology: PrimitiveTopology::TriangleList,
-                    strip_index_format: None,
-                    front_face: FrontFace::Ccw,
-                    cull_mode: Some(Face::Back),
+                    topology: descriptor.topology,
+                    // Strips restart at the max index, which depends on the format. Meshes are
+                    // always drawn with u32 indices.
+                    strip_index_format: descriptor
+                        .topology
+                        .is_strip()
+                        .then_some(IndexFormat::Uint32),
+                    front_face: descriptor.front_face,
+                    cull_mode: descriptor.cull_mode,
                     unclipped_depth: false,
                     polygon_mode: PolygonMode::Fill,
                     conservative: false,
                 },
-                depth_stencil: None,
+                depth_stencil: descriptor.depth.map(|depth| DepthStencilState {
+                    format: depth.format,
+                    depth_write_enabled: depth.write,
+                    depth_compare: depth.compare,
+                    stencil: StencilState::default(),
+                    bias: DepthBiasState::default(),
+                }),
                 multisample: MultisampleState {
                     count: 1,
                     mask: !0,
@@ -254,3 +826,260 @@ impl WGPUContext {
             })
     }
 }
+
+#[cfg(test)]
+const SKIP_GPU_TESTS_VAR: &str = "VOXEL_SKIP_GPU_TESTS";
+
+#[cfg(test)]
+impl WGPUContext {
+    // A small headless context. GPU tests have to run, so a missing software adapter fails them,
+    // unless `SKIP_GPU_TESTS_VAR` is set, in which case this is None and they pass without running.
+    pub(crate) fn for_tests() -> Option<Self> {
+        match pollster::block_on(Self::headless(AssetSource::new(), 64, 64)) {
+            Ok(context) => Some(context),
+            Err(_) if std::env::var_os(SKIP_GPU_TESTS_VAR).is_some() => None,
+            Err(err) => panic!(
+                "No adapter to run GPU tests on ({err}). Install a software renderer like \
+                 llvmpipe, or set {SKIP_GPU_TESTS_VAR} to skip them."
+            ),
+        }
+    }
+
+    // Waits for the GPU and copies out the contents of `buffer`, which needs `COPY_SRC`.
+    pub(crate) fn read_buffer(&self, buffer: &wgpu::Buffer) -> Vec<u8> {
+        let staging = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback"),
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(buffer, 0, &staging, 0, buffer.size());
+        self.queue.submit([encoder.finish()]);
+
+        staging
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        self.device.poll(wgpu::PollType::Wait).unwrap();
+        let bytes = staging.slice(..).get_mapped_range().to_vec();
+        staging.unmap();
+
+        bytes
+    }
+}
+
+// Render shaders only have the global (0) and material (1) groups.
+fn check_render_groups(reflection: &ShaderReflection) -> Result<(), BindingMismatchError> {
+    match reflection.groups().find(|&group| group > 1) {
+        Some(group) => Err(BindingMismatchError::UnsupportedGroup(group)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::camera::Camera;
+    use crate::rendering::global_bindings::GlobalBufferContext;
+    use crate::rendering::pipeline::DepthState;
+    use crate::rendering::preprocessor::BUILTIN_SHADERS;
+    use glam::Vec3;
+
+    // Fragment shaders sample through `sampled` so the global samplers are only visible to the
+    // fragment stage, like in the layout.
+    const VERTEX: &str = r#"
+        #include "include/globals.wgsl"
+        #include "include/mesh.wgsl"
+
+        @group(1) @binding(0) var smooth_texture: texture_2d<f32>;
+        @group(1) @binding(1) var pixel_texture: texture_2d<f32>;
+
+        fn sampled() -> vec4<f32> {
+            let uv = vec2<f32>(0.5);
+            return textureSample(smooth_texture, trilinear_sampler, uv)
+                + textureSample(pixel_texture, point_sampler, uv);
+        }
+
+        @vertex
+        fn vs_main(vert: VertexInput, instance: InstanceInput) -> @builtin(position) vec4<f32> {
+            let world_position = instance_model(instance) * vec4<f32>(vert.position, 1.0);
+            return global_context.camera.view_proj * world_position;
+        }
+    "#;
+
+    // Creates a shader from `VERTEX` followed by `fragment`.
+    fn create_shader(
+        context: &WGPUContext,
+        fragment: &str,
+        descriptor: &PipelineDescriptor,
+    ) -> Result<Shader, CreateShaderError> {
+        let camera = Camera {
+            eye: Vec3::Z,
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect: 1.0,
+            fov: 1.0,
+            near_clip: 0.1,
+            far_clip: 100.0,
+        };
+        let global_bindings = GlobalBindings::new(context, GlobalBufferContext::new(&camera));
+        let src: &'static str = format!("{VERTEX}{fragment}").leak();
+        let assets = AssetSource::new()
+            .with_embedded(BUILTIN_SHADERS)
+            .with_embedded(vec![("/res/shaders/test.wgsl", src.as_bytes())].leak());
+
+        context.create_shader_from(
+            &assets,
+            "/res/shaders/test.wgsl",
+            &global_bindings,
+            descriptor,
+            &ShaderDefines::new(),
+        )
+    }
+
+    #[test]
+    fn pipeline_errors_are_returned() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let fragment = "@fragment fn fs_main() -> @location(0) vec4<f32> { return sampled(); }";
+        create_shader(&context, fragment, &PipelineDescriptor::new()).unwrap();
+
+        // Valid WGSL, but integers can't be written to the surface's float format.
+        let fragment =
+            "@fragment fn fs_main() -> @location(0) vec4<u32> { return vec4<u32>(sampled()); }";
+        let result = create_shader(&context, fragment, &PipelineDescriptor::new());
+        assert!(
+            matches!(&result, Err(CreateShaderError::Compile { file, .. }) if file == "/res/shaders/test.wgsl"),
+            "{:?}",
+            result.err()
+        );
+        // The failed pipeline isn't cached.
+        assert_eq!(context.pipelines.len(), 1);
+    }
+
+    #[test]
+    fn invalid_variants_are_returned() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let fragment = "@fragment fn fs_main() -> @location(0) vec4<f32> { return sampled(); }";
+        let shader = create_shader(&context, fragment, &PipelineDescriptor::new()).unwrap();
+        let pipelines = context.pipelines.len();
+
+        // A color format can't be used for depth.
+        let descriptor = PipelineDescriptor::new().with_depth(Some(DepthState {
+            format: TextureFormat::Rgba8Unorm,
+            ..Default::default()
+        }));
+        let result = context.create_shader_variant(&shader, &descriptor);
+        assert!(
+            matches!(&result, Err(CreateShaderError::Compile { .. })),
+            "{:?}",
+            result.err()
+        );
+
+        let descriptor = PipelineDescriptor {
+            fragment_entry: Some("missing".to_owned()),
+            ..PipelineDescriptor::new()
+        };
+        let result = context.create_shader_variant(&shader, &descriptor);
+        assert!(
+            matches!(&result, Err(CreateShaderError::Compile { .. })),
+            "{:?}",
+            result.err()
+        );
+        assert_eq!(context.pipelines.len(), pipelines);
+    }
+
+    // Draws a strip with 4 indices of `format`, returning the validation error if there is one.
+    fn draw_strip(
+        context: &WGPUContext,
+        pipeline: &RenderPipeline,
+        format: IndexFormat,
+    ) -> Option<wgpu::Error> {
+        let target = context.device.create_texture(&TextureDescriptor {
+            label: None,
+            size: Extent3d {
+                width: 4,
+                height: 4,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            format: context.config.format,
+            usage: TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        let view = target.create_view(&TextureViewDescriptor::default());
+        let indices = context.device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: 16,
+            usage: wgpu::BufferUsages::INDEX,
+            mapped_at_creation: false,
+        });
+
+        context.device.push_error_scope(ErrorFilter::Validation);
+        let mut encoder = context.device.create_command_encoder(&Default::default());
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: None,
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view: &view,
+                    depth_slice: None,
+                    resolve_target: None,
+                    ops: wgpu::Operations::default(),
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+            pass.set_pipeline(pipeline);
+            pass.set_index_buffer(indices.slice(..), format);
+            pass.draw_indexed(0..4, 0, 0..1);
+        }
+        context.queue.submit([encoder.finish()]);
+        pollster::block_on(context.device.pop_error_scope())
+    }
+
+    #[test]
+    fn strips_are_drawn_with_u32_indices() {
+        let Some(context) = WGPUContext::for_tests() else {
+            return;
+        };
+        let module = context.device.create_shader_module(ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(
+                r#"
+                    @vertex
+                    fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+                        return vec4<f32>(f32(index & 1u), f32(index >> 1u), 0.0, 1.0);
+                    }
+
+                    @fragment
+                    fn fs_main() -> @location(0) vec4<f32> {
+                        return vec4<f32>(1.0);
+                    }
+                    "#
+                .into(),
+            ),
+        });
+        let descriptor = PipelineDescriptor {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            ..PipelineDescriptor::new()
+        }
+        .with_vertex_layouts(vec![])
+        .with_depth(None);
+        let pipeline = context.create_render_pipeline(&module, &[], &descriptor);
+
+        assert!(draw_strip(&context, &pipeline, IndexFormat::Uint32).is_none());
+        assert!(draw_strip(&context, &pipeline, IndexFormat::Uint16).is_some());
+
+        // Another configuration of the same module is cached separately, until the module goes.
+        context.create_render_pipeline(&module, &[], &descriptor.clone().with_cull_mode(None));
+        assert_eq!(context.pipelines.len(), 2);
+        context.pipelines.remove_module(&module);
+        assert_eq!(context.pipelines.len(), 0);
+    }
+}
@@ -0,0 +1,50 @@
+use crate::world::World;
+use crate::world::block::BlockId;
+use glam::IVec3;
+use rand::Rng;
+
+// Grass dies when covered and otherwise spreads to nearby uncovered dirt.
+pub fn grass_random_tick(world: &mut World, pos: IVec3) {
+    if world.is_solid(pos + IVec3::Y) {
+        world.set_block(pos, BlockId::DIRT);
+        return;
+    }
+
+    let offset = IVec3::new(
+        world.rng().random_range(-1..=1),
+        world.rng().random_range(-3..=1),
+        world.rng().random_range(-1..=1),
+    );
+    let target = pos + offset;
+    if world.block(target) == BlockId::DIRT && !world.is_solid(target + IVec3::Y) {
+        world.set_block(target, BlockId::GRASS);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::Chunk;
+
+    #[test]
+    fn grass_spreads_to_uncovered_dirt_and_dies_when_covered() {
+        let mut world = World::new();
+        world.random_tick_speed = 64;
+        world.insert_chunk(IVec3::ZERO, Chunk::new());
+        for x in 0..16 {
+            for z in 0..16 {
+                world.set_block(IVec3::new(x, 0, z), BlockId::DIRT);
+            }
+        }
+        world.set_block(IVec3::new(8, 0, 8), BlockId::GRASS);
+        world.set_block(IVec3::new(0, 0, 0), BlockId::GRASS);
+        world.set_block(IVec3::new(0, 1, 0), BlockId::STONE);
+
+        for _ in 0..2000 {
+            world.tick();
+        }
+
+        assert_eq!(world.block(IVec3::new(9, 0, 8)), BlockId::GRASS);
+        assert_eq!(world.block(IVec3::new(0, 0, 0)), BlockId::DIRT);
+    }
+}
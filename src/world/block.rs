@@ -0,0 +1,150 @@
+use crate::world::World;
+use crate::world::behaviour;
+use glam::IVec3;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u16);
+
+impl BlockId {
+    pub const AIR: Self = Self(0);
+    pub const STONE: Self = Self(1);
+    pub const DIRT: Self = Self(2);
+    pub const GRASS: Self = Self(3);
+    pub const SAND: Self = Self(4);
+    pub const GRAVEL: Self = Self(5);
+}
+
+pub type BlockTickFn = fn(&mut World, IVec3);
+pub type NeighbourChangedFn = fn(world: &mut World, pos: IVec3, changed: IVec3);
+
+// Atlas tile names of a block's faces, see `AtlasBuilder`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BlockTextures {
+    pub top: &'static str,
+    pub side: &'static str,
+    pub bottom: &'static str,
+}
+
+impl BlockTextures {
+    pub const fn all(name: &'static str) -> Self {
+        Self {
+            top: name,
+            side: name,
+            bottom: name,
+        }
+    }
+
+    pub fn face(&self, normal: IVec3) -> &'static str {
+        match normal.y {
+            1 => self.top,
+            -1 => self.bottom,
+            _ => self.side,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BlockProperties {
+    pub textures: BlockTextures,
+    pub solid: bool,       // occludes neighbouring faces and blocks fluids
+    pub replaceable: bool, // fluids and placed blocks may overwrite it
+    pub gravity: bool,     // falls when the block below is not solid
+
+    pub on_scheduled_tick: Option<BlockTickFn>,
+    pub on_random_tick: Option<BlockTickFn>,
+    pub on_neighbour_changed: Option<NeighbourChangedFn>,
+}
+
+impl BlockProperties {
+    // Textured with the atlas tile of the same name on every face.
+    pub const fn solid(name: &'static str) -> Self {
+        Self {
+            textures: BlockTextures::all(name),
+            solid: true,
+            replaceable: false,
+            gravity: false,
+            on_scheduled_tick: None,
+            on_random_tick: None,
+            on_neighbour_changed: None,
+        }
+    }
+
+    pub const fn falling(name: &'static str) -> Self {
+        Self {
+            gravity: true,
+            ..Self::solid(name)
+        }
+    }
+}
+
+pub struct BlockRegistry {
+    blocks: Vec<BlockProperties>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        let mut registry = Self { blocks: vec![] };
+
+        let builtin = [
+            (
+                BlockId::AIR,
+                BlockProperties {
+                    solid: false,
+                    replaceable: true,
+                    ..BlockProperties::solid("air")
+                },
+            ),
+            (BlockId::STONE, BlockProperties::solid("stone")),
+            (BlockId::DIRT, BlockProperties::solid("dirt")),
+            (
+                BlockId::GRASS,
+                BlockProperties {
+                    textures: BlockTextures {
+                        top: "grass_top",
+                        side: "grass_side",
+                        bottom: "dirt",
+                    },
+                    on_random_tick: Some(behaviour::grass_random_tick),
+                    ..BlockProperties::solid("grass")
+                },
+            ),
+            (BlockId::SAND, BlockProperties::falling("sand")),
+            (BlockId::GRAVEL, BlockProperties::falling("gravel")),
+        ];
+
+        for (id, properties) in builtin {
+            let registered = registry.register(properties);
+            debug_assert_eq!(registered, id);
+        }
+
+        registry
+    }
+
+    pub fn register(&mut self, properties: BlockProperties) -> BlockId {
+        let id = BlockId(self.blocks.len() as u16);
+        self.blocks.push(properties);
+
+        id
+    }
+
+    pub fn get(&self, id: BlockId) -> &BlockProperties {
+        &self.blocks[id.0 as usize]
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (BlockId, &BlockProperties)> {
+        self.blocks
+            .iter()
+            .enumerate()
+            .map(|(id, properties)| (BlockId(id as u16), properties))
+    }
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
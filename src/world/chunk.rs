@@ -0,0 +1,79 @@
+use crate::world::block::BlockId;
+use crate::world::fluid::FluidState;
+use crate::world::tick::TickQueue;
+use glam::IVec3;
+
+pub const CHUNK_SIZE: i32 = 16;
+pub const CHUNK_VOLUME: usize = (CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE) as usize;
+
+// A cubic section of the world. Blocks and fluids are stored in separate layers so a voxel can be
+// both empty and flooded.
+#[derive(Clone)]
+pub struct Chunk {
+    blocks: Box<[BlockId]>,
+    fluids: Box<[FluidState]>,
+    ticks: TickQueue,
+}
+
+impl Chunk {
+    pub fn new() -> Self {
+        Self::filled(BlockId::AIR)
+    }
+
+    pub fn filled(block: BlockId) -> Self {
+        Self {
+            blocks: vec![block; CHUNK_VOLUME].into_boxed_slice(),
+            fluids: vec![FluidState::EMPTY; CHUNK_VOLUME].into_boxed_slice(),
+            ticks: TickQueue::new(),
+        }
+    }
+
+    pub fn index(local: IVec3) -> usize {
+        debug_assert!(
+            local.cmpge(IVec3::ZERO).all() && local.cmplt(IVec3::splat(CHUNK_SIZE)).all()
+        );
+        (local.x + local.z * CHUNK_SIZE + local.y * CHUNK_SIZE * CHUNK_SIZE) as usize
+    }
+
+    pub fn block(&self, local: IVec3) -> BlockId {
+        self.blocks[Self::index(local)]
+    }
+
+    pub fn set_block(&mut self, local: IVec3, block: BlockId) {
+        self.blocks[Self::index(local)] = block;
+    }
+
+    pub fn fluid(&self, local: IVec3) -> FluidState {
+        self.fluids[Self::index(local)]
+    }
+
+    pub fn set_fluid(&mut self, local: IVec3, fluid: FluidState) {
+        self.fluids[Self::index(local)] = fluid;
+    }
+
+    pub fn ticks(&self) -> &TickQueue {
+        &self.ticks
+    }
+
+    pub fn ticks_mut(&mut self) -> &mut TickQueue {
+        &mut self.ticks
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn chunk_pos(world_pos: IVec3) -> IVec3 {
+    world_pos.div_euclid(IVec3::splat(CHUNK_SIZE))
+}
+
+pub fn local_pos(world_pos: IVec3) -> IVec3 {
+    world_pos.rem_euclid(IVec3::splat(CHUNK_SIZE))
+}
+
+pub fn chunk_origin(chunk_pos: IVec3) -> IVec3 {
+    chunk_pos * CHUNK_SIZE
+}
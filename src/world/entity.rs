@@ -0,0 +1,329 @@
+use crate::world::block::BlockId;
+use crate::world::chunk::chunk_pos;
+use glam::{IVec3, Quat, Vec3};
+use std::collections::HashMap;
+
+// Handle to an entity. The generation makes handles to despawned entities go stale instead of
+// silently pointing at whatever reuses the slot.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum EntityKind {
+    FallingBlock(BlockId),
+    ItemDrop(BlockId),
+    Mob,
+    Projectile,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub position: Vec3, // bottom center of the entity
+    pub previous_position: Vec3,
+    pub rotation: Quat,
+}
+
+impl Transform {
+    pub fn new(position: Vec3) -> Self {
+        Self {
+            position,
+            previous_position: position,
+            rotation: Quat::IDENTITY,
+        }
+    }
+
+    // Position to render at, blended between the last two ticks.
+    pub fn interpolated_position(&self, alpha: f32) -> Vec3 {
+        self.previous_position.lerp(self.position, alpha)
+    }
+}
+
+// Per-tick physics parameters, in blocks per tick.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Physics {
+    pub velocity: Vec3,
+    pub gravity: f32,
+    pub drag: f32,
+    pub on_ground: bool,
+    pub collided: bool, // hit a block on any axis during the last tick
+}
+
+impl Physics {
+    pub fn new(gravity: f32, drag: f32) -> Self {
+        Self {
+            velocity: Vec3::ZERO,
+            gravity,
+            drag,
+            on_ground: false,
+            collided: false,
+        }
+    }
+}
+
+// Which registered mesh and material an entity is drawn with. Entities sharing both are batched
+// into a single instanced draw.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MeshKey(pub u32);
+
+impl MeshKey {
+    pub const CUBE: Self = Self(0);
+    // Keys from here on are cubes textured like a block, created when first drawn.
+    const FIRST_BLOCK: u32 = 1 << 16;
+
+    pub fn block(block: BlockId) -> Self {
+        Self(Self::FIRST_BLOCK + block.0 as u32)
+    }
+
+    pub fn as_block(self) -> Option<BlockId> {
+        let id = self.0.checked_sub(Self::FIRST_BLOCK)?;
+        u16::try_from(id).ok().map(BlockId)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MaterialKey(pub u32);
+
+impl MaterialKey {
+    pub const DEFAULT: Self = Self(0);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RenderComponent {
+    pub mesh: MeshKey,
+    pub material: MaterialKey,
+    pub scale: Vec3,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub transform: Transform,
+    pub physics: Physics,
+    pub size: Vec3, // collision box extents
+    pub render: Option<RenderComponent>,
+    pub age: u64, // ticks since spawning
+}
+
+impl Entity {
+    const GRAVITY: f32 = 0.04;
+    const DRAG: f32 = 0.98;
+
+    // Builds an entity of `kind` with that kind's default components.
+    pub fn new(kind: EntityKind, position: Vec3) -> Self {
+        let mesh = match kind {
+            EntityKind::FallingBlock(block) | EntityKind::ItemDrop(block) => MeshKey::block(block),
+            EntityKind::Mob | EntityKind::Projectile => MeshKey::CUBE,
+        };
+        let (size, physics, render_scale) = match kind {
+            EntityKind::FallingBlock(_) => (
+                Vec3::splat(0.98),
+                Physics::new(Self::GRAVITY, Self::DRAG),
+                Vec3::ONE,
+            ),
+            EntityKind::ItemDrop(_) => (
+                Vec3::splat(0.25),
+                Physics::new(Self::GRAVITY, Self::DRAG),
+                Vec3::splat(0.25),
+            ),
+            EntityKind::Mob => (
+                Vec3::new(0.6, 1.8, 0.6),
+                Physics::new(Self::GRAVITY * 2.0, Self::DRAG),
+                Vec3::new(0.6, 1.8, 0.6),
+            ),
+            EntityKind::Projectile => (
+                Vec3::splat(0.25),
+                Physics::new(Self::GRAVITY * 0.5, 0.99),
+                Vec3::splat(0.25),
+            ),
+        };
+
+        Self {
+            kind,
+            transform: Transform::new(position),
+            physics,
+            size,
+            render: Some(RenderComponent {
+                mesh,
+                material: MaterialKey::DEFAULT,
+                scale: render_scale,
+            }),
+            age: 0,
+        }
+    }
+
+    pub fn falling_block(block: BlockId, cell: IVec3) -> Self {
+        Self::new(
+            EntityKind::FallingBlock(block),
+            cell.as_vec3() + Vec3::new(0.5, 0.0, 0.5),
+        )
+    }
+
+    pub fn chunk(&self) -> IVec3 {
+        chunk_pos(self.transform.position.floor().as_ivec3())
+    }
+}
+
+struct Slot {
+    generation: u32,
+    entity: Option<Entity>,
+    chunk: IVec3, // chunk the entity is indexed under
+}
+
+// Arena of all live entities, plus an index of which entities are in which chunk.
+#[derive(Default)]
+pub struct Entities {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    by_chunk: HashMap<IVec3, Vec<EntityId>>,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self, entity: Entity) -> EntityId {
+        let chunk = entity.chunk();
+        let id = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.entity = Some(entity);
+                slot.chunk = chunk;
+                EntityId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    entity: Some(entity),
+                    chunk,
+                });
+                EntityId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            }
+        };
+
+        self.by_chunk.entry(chunk).or_default().push(id);
+
+        id
+    }
+
+    pub fn despawn(&mut self, id: EntityId) -> Option<Entity> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+
+        let entity = slot.entity.take()?;
+        slot.generation += 1;
+        self.free.push(id.index);
+
+        if let Some(ids) = self.by_chunk.get_mut(&slot.chunk) {
+            ids.retain(|&other| other != id);
+        }
+
+        Some(entity)
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<&Entity> {
+        let slot = self.slots.get(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+
+        slot.entity.as_ref()
+    }
+
+    // Moving an entity through this reference doesn't update the chunk index until the next
+    // `reindex`.
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut Entity> {
+        let slot = self.slots.get_mut(id.index as usize)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+
+        slot.entity.as_mut()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &Entity)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.entity.as_ref().map(|entity| {
+                (
+                    EntityId {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    entity,
+                )
+            })
+        })
+    }
+
+    pub fn ids(&self) -> Vec<EntityId> {
+        self.iter().map(|(id, _)| id).collect()
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn in_chunk(&self, chunk: IVec3) -> &[EntityId] {
+        self.by_chunk.get(&chunk).map_or(&[], |ids| ids.as_slice())
+    }
+
+    // Rebuilds the chunk index after entities have moved.
+    pub fn reindex(&mut self) {
+        self.by_chunk.clear();
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            if let Some(entity) = &slot.entity {
+                slot.chunk = entity.chunk();
+                self.by_chunk.entry(slot.chunk).or_default().push(EntityId {
+                    index: index as u32,
+                    generation: slot.generation,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale_ids_do_not_reach_reused_slots() {
+        let mut entities = Entities::new();
+        let first = entities.spawn(Entity::new(EntityKind::Mob, Vec3::ZERO));
+        entities.despawn(first);
+
+        let second = entities.spawn(Entity::new(EntityKind::Projectile, Vec3::ZERO));
+        assert!(entities.get(first).is_none());
+        assert_eq!(entities.get(second).unwrap().kind, EntityKind::Projectile);
+        assert_eq!(entities.len(), 1);
+    }
+
+    #[test]
+    fn entities_are_indexed_by_chunk() {
+        let mut entities = Entities::new();
+        let id = entities.spawn(Entity::new(EntityKind::Mob, Vec3::new(20.0, 1.0, 1.0)));
+        assert_eq!(entities.in_chunk(IVec3::new(1, 0, 0)), &[id]);
+
+        entities.get_mut(id).unwrap().transform.position = Vec3::new(-1.0, 1.0, 1.0);
+        entities.reindex();
+        assert!(entities.in_chunk(IVec3::new(1, 0, 0)).is_empty());
+        assert_eq!(entities.in_chunk(IVec3::new(-1, 0, 0)), &[id]);
+    }
+}
@@ -0,0 +1,100 @@
+use crate::world::World;
+use crate::world::block::BlockId;
+use crate::world::entity::Entity;
+use glam::IVec3;
+
+// Ticks a gravity-affected block waits after being disturbed before it starts to fall.
+pub const FALL_DELAY: u64 = 2;
+
+pub(crate) fn is_unsupported(world: &World, pos: IVec3) -> bool {
+    let below = pos - IVec3::Y;
+    world.is_loaded(below) && !world.is_solid(below)
+}
+
+// Turns the gravity block at `pos` into a falling block entity if nothing holds it up.
+pub(crate) fn try_start_falling(world: &mut World, pos: IVec3) {
+    if !is_unsupported(world, pos) {
+        return;
+    }
+
+    let block = world.block(pos);
+    world.set_block(pos, BlockId::AIR);
+    world.spawn_entity(Entity::falling_block(block, pos));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::{BlockProperties, BlockRegistry};
+    use crate::world::entity::EntityKind;
+    use crate::world::test_worlds::floor_world;
+
+    fn run(world: &mut World, ticks: u32) {
+        for _ in 0..ticks {
+            world.tick();
+        }
+    }
+
+    fn count(world: &World, matches: impl Fn(EntityKind) -> bool) -> usize {
+        world
+            .entities()
+            .iter()
+            .filter(|(_, entity)| matches(entity.kind))
+            .count()
+    }
+
+    #[test]
+    fn unsupported_sand_falls_and_lands_as_a_block() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_block(IVec3::new(4, 10, 4), BlockId::SAND);
+
+        run(&mut world, FALL_DELAY as u32);
+        assert_eq!(world.block(IVec3::new(4, 10, 4)), BlockId::AIR);
+        assert_eq!(
+            count(&world, |kind| kind
+                == EntityKind::FallingBlock(BlockId::SAND)),
+            1
+        );
+
+        run(&mut world, 100);
+        assert!(world.entities().is_empty());
+        assert_eq!(world.block(IVec3::new(4, 1, 4)), BlockId::SAND);
+    }
+
+    #[test]
+    fn removing_support_makes_a_stack_fall() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_block(IVec3::new(4, 1, 4), BlockId::STONE);
+        world.set_block(IVec3::new(4, 2, 4), BlockId::GRAVEL);
+        world.set_block(IVec3::new(4, 3, 4), BlockId::SAND);
+        run(&mut world, 10);
+        assert!(world.entities().is_empty());
+
+        world.set_block(IVec3::new(4, 1, 4), BlockId::AIR);
+        run(&mut world, 100);
+
+        assert_eq!(world.block(IVec3::new(4, 1, 4)), BlockId::GRAVEL);
+        assert_eq!(world.block(IVec3::new(4, 2, 4)), BlockId::SAND);
+        assert_eq!(world.block(IVec3::new(4, 3, 4)), BlockId::AIR);
+    }
+
+    #[test]
+    fn landing_in_a_non_replaceable_block_drops_an_item() {
+        let mut registry = BlockRegistry::new();
+        let torch = registry.register(BlockProperties {
+            solid: false,
+            ..BlockProperties::solid("torch")
+        });
+
+        let mut world = floor_world(registry);
+        world.set_block(IVec3::new(4, 1, 4), torch);
+        world.set_block(IVec3::new(4, 6, 4), BlockId::SAND);
+        run(&mut world, 100);
+
+        assert_eq!(world.block(IVec3::new(4, 1, 4)), torch);
+        assert_eq!(
+            count(&world, |kind| kind == EntityKind::ItemDrop(BlockId::SAND)),
+            1
+        );
+    }
+}
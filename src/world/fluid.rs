@@ -0,0 +1,299 @@
+use crate::world::World;
+use glam::IVec3;
+
+pub const HORIZONTAL_DIRECTIONS: [IVec3; 4] = [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z];
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Fluid {
+    #[default]
+    Empty,
+    Water,
+    Lava,
+}
+
+impl Fluid {
+    pub const ALL: [Fluid; 2] = [Fluid::Water, Fluid::Lava];
+
+    // The atlas tile it's drawn with.
+    pub fn texture(self) -> &'static str {
+        match self {
+            Fluid::Empty => "",
+            Fluid::Water => "water",
+            Fluid::Lava => "lava",
+        }
+    }
+
+    // Ticks between a fluid voxel being disturbed and it reacting.
+    pub fn tick_delay(self) -> u64 {
+        match self {
+            Fluid::Empty => 0,
+            Fluid::Water => 5,
+            Fluid::Lava => 30,
+        }
+    }
+
+    // How many levels are lost per horizontally spread block.
+    pub fn level_drop(self) -> u8 {
+        match self {
+            Fluid::Empty => FluidState::MAX_LEVEL,
+            Fluid::Water => 1,
+            Fluid::Lava => 2,
+        }
+    }
+
+    // Whether two adjacent sources turn a flowing block between them into a new source.
+    pub fn forms_sources(self) -> bool {
+        matches!(self, Fluid::Water)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FluidState {
+    pub fluid: Fluid,
+    pub level: u8,
+    pub falling: bool,
+}
+
+impl FluidState {
+    pub const MAX_LEVEL: u8 = 8;
+
+    pub const EMPTY: Self = Self {
+        fluid: Fluid::Empty,
+        level: 0,
+        falling: false,
+    };
+
+    pub fn source(fluid: Fluid) -> Self {
+        Self {
+            fluid,
+            level: Self::MAX_LEVEL,
+            falling: false,
+        }
+    }
+
+    pub fn flowing(fluid: Fluid, level: u8) -> Self {
+        if level == 0 {
+            return Self::EMPTY;
+        }
+
+        Self {
+            fluid,
+            level: level.min(Self::MAX_LEVEL - 1),
+            falling: false,
+        }
+    }
+
+    pub fn falling(fluid: Fluid) -> Self {
+        Self {
+            fluid,
+            level: Self::MAX_LEVEL,
+            falling: true,
+        }
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.fluid == Fluid::Empty
+    }
+
+    pub fn is_source(self) -> bool {
+        !self.is_empty() && self.level == Self::MAX_LEVEL && !self.falling
+    }
+
+    // Surface height inside the voxel, in blocks.
+    pub fn height(self) -> f32 {
+        self.level as f32 / (Self::MAX_LEVEL + 1) as f32
+    }
+}
+
+// Runs one scheduled update of the fluid at `pos`. Neighbours that change are rescheduled by
+// `World::set_fluid`, so the flow keeps going until it settles.
+pub(crate) fn update_fluid(world: &mut World, pos: IVec3) {
+    let mut state = world.fluid(pos);
+    if state.is_empty() {
+        return;
+    }
+
+    if !state.is_source() {
+        let new_state = flowing_state(world, pos, state.fluid);
+        if new_state != state {
+            world.set_fluid(pos, new_state);
+        }
+        if new_state.is_empty() {
+            return;
+        }
+
+        state = new_state;
+    }
+
+    spread(world, pos, state);
+}
+
+// The state a non-source voxel should have given its neighbours.
+fn flowing_state(world: &World, pos: IVec3, fluid: Fluid) -> FluidState {
+    if world.fluid(pos + IVec3::Y).fluid == fluid {
+        return FluidState::falling(fluid);
+    }
+
+    let mut sources = 0;
+    let mut max_level = 0;
+    for direction in HORIZONTAL_DIRECTIONS {
+        let neighbour = world.fluid(pos + direction);
+        if neighbour.fluid != fluid {
+            continue;
+        }
+
+        if neighbour.is_source() {
+            sources += 1;
+        }
+        max_level = max_level.max(neighbour.level);
+    }
+
+    if fluid.forms_sources() && sources >= 2 {
+        let below = pos - IVec3::Y;
+        if world.is_solid(below) || world.fluid(below) == FluidState::source(fluid) {
+            return FluidState::source(fluid);
+        }
+    }
+
+    FluidState::flowing(fluid, max_level.saturating_sub(fluid.level_drop()))
+}
+
+fn spread(world: &mut World, pos: IVec3, state: FluidState) {
+    let fluid = state.fluid;
+
+    let below = pos - IVec3::Y;
+    if can_flow_into(world, below, fluid) {
+        if world.fluid(below) != FluidState::falling(fluid) {
+            world.set_fluid(below, FluidState::falling(fluid));
+        }
+
+        return;
+    }
+
+    let level = state.level.saturating_sub(fluid.level_drop());
+    if level == 0 {
+        return;
+    }
+
+    for direction in HORIZONTAL_DIRECTIONS {
+        let neighbour_pos = pos + direction;
+        if !can_flow_into(world, neighbour_pos, fluid) {
+            continue;
+        }
+
+        let neighbour = world.fluid(neighbour_pos);
+        if neighbour.is_empty() || (!neighbour.falling && neighbour.level < level) {
+            world.set_fluid(neighbour_pos, FluidState::flowing(fluid, level));
+        }
+    }
+}
+
+fn can_flow_into(world: &World, pos: IVec3, fluid: Fluid) -> bool {
+    if !world.is_replaceable(pos) {
+        return false;
+    }
+
+    let existing = world.fluid(pos);
+    existing.is_empty() || (existing.fluid == fluid && !existing.is_source())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::BlockRegistry;
+    use crate::world::test_worlds::floor_world;
+
+    fn settle(world: &mut World) {
+        for _ in 0..500 {
+            world.tick();
+        }
+    }
+
+    #[test]
+    fn water_spreads_until_it_runs_out_of_levels() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_fluid(IVec3::new(0, 1, 8), FluidState::source(Fluid::Water));
+        settle(&mut world);
+
+        assert_eq!(
+            world.fluid(IVec3::new(1, 1, 8)),
+            FluidState::flowing(Fluid::Water, 7)
+        );
+        assert_eq!(
+            world.fluid(IVec3::new(7, 1, 8)),
+            FluidState::flowing(Fluid::Water, 1)
+        );
+        assert!(world.fluid(IVec3::new(8, 1, 8)).is_empty());
+    }
+
+    #[test]
+    fn lava_spreads_slower_and_shorter_than_water() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_fluid(IVec3::new(0, 1, 8), FluidState::source(Fluid::Lava));
+
+        for _ in 0..Fluid::Water.tick_delay() * 2 {
+            world.tick();
+        }
+        assert!(world.fluid(IVec3::new(1, 1, 8)).is_empty());
+
+        settle(&mut world);
+        assert_eq!(
+            world.fluid(IVec3::new(3, 1, 8)),
+            FluidState::flowing(Fluid::Lava, 2)
+        );
+        assert!(world.fluid(IVec3::new(4, 1, 8)).is_empty());
+    }
+
+    #[test]
+    fn fluid_falls_down_before_spreading() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_fluid(IVec3::new(8, 5, 8), FluidState::source(Fluid::Water));
+        settle(&mut world);
+
+        for y in 1..5 {
+            assert_eq!(
+                world.fluid(IVec3::new(8, y, 8)),
+                FluidState::falling(Fluid::Water)
+            );
+        }
+        assert!(world.fluid(IVec3::new(9, 5, 8)).is_empty());
+        assert_eq!(
+            world.fluid(IVec3::new(9, 1, 8)),
+            FluidState::flowing(Fluid::Water, 7)
+        );
+    }
+
+    #[test]
+    fn two_water_sources_form_a_new_source() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_fluid(IVec3::new(4, 1, 8), FluidState::source(Fluid::Water));
+        world.set_fluid(IVec3::new(6, 1, 8), FluidState::source(Fluid::Water));
+        settle(&mut world);
+
+        assert!(world.fluid(IVec3::new(5, 1, 8)).is_source());
+    }
+
+    #[test]
+    fn lava_does_not_form_sources() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_fluid(IVec3::new(4, 1, 8), FluidState::source(Fluid::Lava));
+        world.set_fluid(IVec3::new(6, 1, 8), FluidState::source(Fluid::Lava));
+        settle(&mut world);
+
+        assert!(!world.fluid(IVec3::new(5, 1, 8)).is_source());
+    }
+
+    #[test]
+    fn flow_recedes_when_its_source_is_removed() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.set_fluid(IVec3::new(8, 1, 8), FluidState::source(Fluid::Water));
+        settle(&mut world);
+
+        world.set_fluid(IVec3::new(8, 1, 8), FluidState::EMPTY);
+        settle(&mut world);
+
+        for x in 0..16 {
+            assert!(world.fluid(IVec3::new(x, 1, 8)).is_empty());
+        }
+    }
+}
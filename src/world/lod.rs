@@ -0,0 +1,255 @@
+use crate::world::World;
+use crate::world::block::BlockId;
+use crate::world::chunk::{CHUNK_SIZE, chunk_origin};
+use crate::world::fluid::Fluid;
+use crate::world::mesher::{BlockUvs, ChunkMeshes, FACES, FaceTexture, MeshData};
+use glam::{IVec3, Vec3};
+
+// Voxels per cell edge at each level of detail. Level 0 is the regular mesh.
+pub const LOD_SCALES: [i32; 4] = [1, 2, 4, 8];
+
+// What a cell of downsampled voxels is drawn as.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LodCell {
+    Empty,
+    Solid(BlockId),
+    Fluid(Fluid),
+}
+
+// Distances in chunks from the camera to the center of a chunk at which coarser levels take over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LodSettings {
+    pub thresholds: [f32; 3], // for levels 1 to 3, ascending
+    // How far past a threshold a chunk has to move before switching, so chunks near one don't
+    // flip between levels with every step of the camera.
+    pub hysteresis: f32,
+}
+
+impl LodSettings {
+    // The level a chunk `distance` chunks away should be meshed at, given the one it has now.
+    pub fn select(&self, current: Option<usize>, distance: f32) -> usize {
+        let target = self
+            .thresholds
+            .iter()
+            .filter(|&&threshold| distance >= threshold)
+            .count();
+        let Some(current) = current else {
+            return target;
+        };
+
+        let lower = match current {
+            0 => f32::NEG_INFINITY,
+            level => self.thresholds[level - 1] - self.hysteresis,
+        };
+        let upper = self
+            .thresholds
+            .get(current)
+            .map_or(f32::INFINITY, |threshold| threshold + self.hysteresis);
+        if (lower..upper).contains(&distance) {
+            current
+        } else {
+            target
+        }
+    }
+}
+
+impl Default for LodSettings {
+    fn default() -> Self {
+        Self {
+            thresholds: [4.0, 8.0, 16.0],
+            hysteresis: 0.5,
+        }
+    }
+}
+
+// Distance from `camera` to the center of the chunk at `pos`, in chunks.
+pub fn chunk_distance(camera: Vec3, pos: IVec3) -> f32 {
+    let center = chunk_origin(pos).as_vec3() + Vec3::splat(CHUNK_SIZE as f32 * 0.5);
+    center.distance(camera) / CHUNK_SIZE as f32
+}
+
+// The `scale`³ voxels from `origin` as one cell. Any solid voxel makes the cell solid, so coarse
+// meshes cover everything the voxels do and finer neighbours never show gaps into them. The cell
+// takes the highest solid block, which is what's seen of it from above.
+pub fn downsample(world: &World, origin: IVec3, scale: i32) -> LodCell {
+    let mut fluid = None;
+    for y in (0..scale).rev() {
+        for z in 0..scale {
+            for x in 0..scale {
+                let pos = origin + IVec3::new(x, y, z);
+                if world.is_solid(pos) {
+                    return LodCell::Solid(world.block(pos));
+                }
+
+                let state = world.fluid(pos);
+                if fluid.is_none() && !state.is_empty() {
+                    fluid = Some(state.fluid);
+                }
+            }
+        }
+    }
+
+    fluid.map_or(LodCell::Empty, LodCell::Fluid)
+}
+
+// Meshes a chunk with cells of `scale`³ voxels, in chunk-local coordinates like `mesh_chunk`.
+// Solid cells on the chunk's border always get their outward faces, as a skirt hiding cracks
+// against neighbours meshed at another level.
+pub fn mesh_lod(world: &World, chunk: IVec3, scale: i32, uvs: &BlockUvs) -> ChunkMeshes {
+    let origin = chunk_origin(chunk);
+    let size = CHUNK_SIZE / scale;
+    let index = |cell: IVec3| (cell.x + cell.z * size + cell.y * size * size) as usize;
+    let inside =
+        |cell: IVec3| cell.cmpge(IVec3::ZERO).all() && cell.cmplt(IVec3::splat(size)).all();
+
+    let mut cells = vec![LodCell::Empty; (size * size * size) as usize];
+    for y in 0..size {
+        for z in 0..size {
+            for x in 0..size {
+                let cell = IVec3::new(x, y, z);
+                cells[index(cell)] = downsample(world, origin + cell * scale, scale);
+            }
+        }
+    }
+
+    let mut meshes = ChunkMeshes::default();
+    for y in 0..size {
+        for z in 0..size {
+            for x in 0..size {
+                let cell = IVec3::new(x, y, z);
+                let local = (cell * scale).as_vec3();
+
+                match cells[index(cell)] {
+                    LodCell::Empty => {}
+                    LodCell::Solid(block) => {
+                        for (face, (normal, corners)) in FACES.iter().enumerate() {
+                            let neighbour = cell + *normal;
+                            if inside(neighbour)
+                                && matches!(cells[index(neighbour)], LodCell::Solid(_))
+                            {
+                                continue;
+                            }
+                            push_scaled_face(
+                                &mut meshes.opaque,
+                                local,
+                                corners,
+                                scale,
+                                uvs.block(block, face),
+                            );
+                        }
+                    }
+                    LodCell::Fluid(fluid) => {
+                        for (normal, corners) in &FACES {
+                            let neighbour = cell + *normal;
+                            let neighbour = if inside(neighbour) {
+                                cells[index(neighbour)]
+                            } else {
+                                downsample(world, origin + neighbour * scale, scale)
+                            };
+                            if neighbour == LodCell::Empty {
+                                push_scaled_face(
+                                    &mut meshes.fluid,
+                                    local,
+                                    corners,
+                                    scale,
+                                    uvs.fluid(fluid),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    meshes
+}
+
+fn push_scaled_face(
+    mesh: &mut MeshData,
+    origin: Vec3,
+    corners: &[Vec3; 4],
+    scale: i32,
+    texture: FaceTexture,
+) {
+    let corners = corners.map(|corner| corner * scale as f32);
+    mesh.push_face(origin, &corners, texture);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::chunk::Chunk;
+    use crate::world::fluid::FluidState;
+    use crate::world::mesher::mesh_chunk;
+
+    #[test]
+    fn cells_are_solid_if_any_voxel_is() {
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::new());
+        world.set_block(IVec3::new(0, 0, 0), BlockId::STONE);
+        world.set_block(IVec3::new(1, 1, 0), BlockId::GRASS);
+        world.set_fluid(IVec3::new(2, 0, 0), FluidState::source(Fluid::Water));
+
+        assert_eq!(
+            downsample(&world, IVec3::ZERO, 2),
+            LodCell::Solid(BlockId::GRASS)
+        );
+        assert_eq!(
+            downsample(&world, IVec3::new(2, 0, 0), 2),
+            LodCell::Fluid(Fluid::Water)
+        );
+        assert_eq!(downsample(&world, IVec3::new(4, 0, 0), 2), LodCell::Empty);
+        // Coarser cells take the highest solid voxel too.
+        assert_eq!(
+            downsample(&world, IVec3::ZERO, 8),
+            LodCell::Solid(BlockId::GRASS)
+        );
+    }
+
+    #[test]
+    fn coarse_chunks_have_skirts_on_their_borders() {
+        let mut world = World::new();
+        for direction in [IVec3::ZERO, IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y] {
+            world.insert_chunk(direction, Chunk::filled(BlockId::STONE));
+        }
+
+        // Buried in stone on most sides, the full mesh only has the faces towards air.
+        let full = mesh_chunk(&world, IVec3::ZERO, &BlockUvs::default());
+        assert_eq!(full.opaque.indices.len() / 6, 2 * 16 * 16);
+
+        // Each coarse level has every border face, but only those.
+        for scale in [2, 4, 8] {
+            let cells = CHUNK_SIZE / scale;
+            let lod = mesh_lod(&world, IVec3::ZERO, scale, &BlockUvs::default());
+            assert_eq!(lod.opaque.indices.len() / 6, (6 * cells * cells) as usize);
+            assert!(lod.fluid.is_empty());
+
+            let max = lod
+                .opaque
+                .vertices
+                .iter()
+                .map(|vertex| Vec3::from(vertex.position).max_element())
+                .fold(f32::MIN, f32::max);
+            assert_eq!(max, CHUNK_SIZE as f32);
+        }
+    }
+
+    #[test]
+    fn levels_switch_with_hysteresis() {
+        let settings = LodSettings::default();
+        assert_eq!(settings.select(None, 2.0), 0);
+        assert_eq!(settings.select(None, 5.0), 1);
+        assert_eq!(settings.select(None, 30.0), 3);
+
+        // Just past a threshold, chunks keep the level they have.
+        assert_eq!(settings.select(Some(0), 4.2), 0);
+        assert_eq!(settings.select(Some(1), 3.8), 1);
+        assert_eq!(settings.select(Some(0), 4.6), 1);
+        assert_eq!(settings.select(Some(1), 3.4), 0);
+
+        // Far enough away, levels are skipped.
+        assert_eq!(settings.select(Some(0), 20.0), 3);
+        assert_eq!(settings.select(Some(3), 1.0), 0);
+    }
+}
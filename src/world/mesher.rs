@@ -0,0 +1,383 @@
+use crate::rendering::atlas::{AtlasUvs, UvRect};
+use crate::rendering::texture::TextureArray;
+use crate::rendering::vertex::Vertex;
+use crate::world::World;
+use crate::world::block::{BlockId, BlockRegistry};
+use crate::world::chunk::{CHUNK_SIZE, chunk_origin};
+use crate::world::fluid::{Fluid, FluidState};
+use glam::{IVec3, Vec3};
+use std::collections::HashMap;
+
+// Corners of each unit-cube face, counter-clockwise when viewed from outside.
+pub(super) const FACES: [(IVec3, [Vec3; 4]); 6] = [
+    (
+        IVec3::X,
+        [
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(1.0, 0.0, 1.0),
+        ],
+    ),
+    (
+        IVec3::NEG_X,
+        [
+            Vec3::new(0.0, 0.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.0),
+        ],
+    ),
+    (
+        IVec3::Y,
+        [
+            Vec3::new(0.0, 1.0, 1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ],
+    ),
+    (
+        IVec3::NEG_Y,
+        [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ],
+    ),
+    (
+        IVec3::Z,
+        [
+            Vec3::new(1.0, 0.0, 1.0),
+            Vec3::new(1.0, 1.0, 1.0),
+            Vec3::new(0.0, 1.0, 1.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        ],
+    ),
+    (
+        IVec3::NEG_Z,
+        [
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+        ],
+    ),
+];
+
+const FACE_TEX_COORDS: [[f32; 2]; 4] = [[0.0, 1.0], [0.0, 0.0], [1.0, 0.0], [1.0, 1.0]];
+const FACE_INDICES: [u32; 6] = [0, 1, 2, 0, 2, 3];
+
+// Where a face's texture is: a region of an atlas, or a layer of a texture array.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FaceTexture {
+    pub uv: UvRect,
+    pub layer: u32,
+}
+
+impl FaceTexture {
+    pub const FULL: Self = Self {
+        uv: UvRect::FULL,
+        layer: 0,
+    };
+}
+
+// The texture of every block face and fluid, resolved once so meshing doesn't look up names.
+// Anything without an entry, like everything in the default, covers the whole texture.
+#[derive(Clone, Debug, Default)]
+pub struct BlockUvs {
+    blocks: Vec<[FaceTexture; 6]>, // in the order of `FACES`
+    fluids: HashMap<Fluid, FaceTexture>,
+}
+
+impl BlockUvs {
+    pub fn new(registry: &BlockRegistry, atlas: &AtlasUvs) -> Self {
+        Self::resolve(registry, |name| FaceTexture {
+            uv: atlas.uv(name),
+            layer: 0,
+        })
+    }
+
+    pub fn from_layers(registry: &BlockRegistry, array: &TextureArray) -> Self {
+        Self::resolve(registry, |name| FaceTexture {
+            uv: UvRect::FULL,
+            layer: array.layer(name),
+        })
+    }
+
+    fn resolve(registry: &BlockRegistry, texture: impl Fn(&str) -> FaceTexture) -> Self {
+        let blocks = registry
+            .iter()
+            .map(|(_, properties)| {
+                FACES.map(|(normal, _)| texture(properties.textures.face(normal)))
+            })
+            .collect();
+        let fluids = Fluid::ALL
+            .into_iter()
+            .map(|fluid| (fluid, texture(fluid.texture())))
+            .collect();
+
+        Self { blocks, fluids }
+    }
+
+    pub(super) fn block(&self, block: BlockId, face: usize) -> FaceTexture {
+        self.blocks
+            .get(block.0 as usize)
+            .map_or(FaceTexture::FULL, |faces| faces[face])
+    }
+
+    pub(super) fn fluid(&self, fluid: Fluid) -> FaceTexture {
+        self.fluids
+            .get(&fluid)
+            .copied()
+            .unwrap_or(FaceTexture::FULL)
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub(super) fn push_face(&mut self, origin: Vec3, corners: &[Vec3; 4], texture: FaceTexture) {
+        let base = self.vertices.len() as u32;
+        for (corner, tex_coords) in corners.iter().zip(FACE_TEX_COORDS) {
+            self.vertices.push(Vertex {
+                position: (origin + *corner).to_array(),
+                tex_coords: texture.uv.map(tex_coords),
+                layer: texture.layer,
+            });
+        }
+
+        self.indices
+            .extend(FACE_INDICES.iter().map(|index| base + index));
+    }
+}
+
+// A unit cube with its minimum corner at the origin, each face covering the whole texture.
+pub fn cube_mesh() -> MeshData {
+    textured_cube(|_| FaceTexture::FULL)
+}
+
+// A unit cube textured like `block`, for blocks drawn outside of chunk meshes.
+pub fn block_mesh(block: BlockId, uvs: &BlockUvs) -> MeshData {
+    textured_cube(|face| uvs.block(block, face))
+}
+
+fn textured_cube(texture: impl Fn(usize) -> FaceTexture) -> MeshData {
+    let mut mesh = MeshData::default();
+    for (face, (_, corners)) in FACES.iter().enumerate() {
+        mesh.push_face(Vec3::ZERO, corners, texture(face));
+    }
+
+    mesh
+}
+
+// Meshes of a single chunk, in chunk-local coordinates.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChunkMeshes {
+    pub opaque: MeshData,
+    pub fluid: MeshData,
+}
+
+pub fn mesh_chunk(world: &World, chunk: IVec3, uvs: &BlockUvs) -> ChunkMeshes {
+    let mut meshes = ChunkMeshes::default();
+    let origin = chunk_origin(chunk);
+
+    for y in 0..CHUNK_SIZE {
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                let local = IVec3::new(x, y, z);
+                let pos = origin + local;
+
+                if world.is_solid(pos) {
+                    mesh_solid(world, pos, local.as_vec3(), uvs, &mut meshes.opaque);
+                }
+
+                let fluid = world.fluid(pos);
+                if !fluid.is_empty() {
+                    let texture = uvs.fluid(fluid.fluid);
+                    mesh_fluid(
+                        world,
+                        pos,
+                        fluid,
+                        local.as_vec3(),
+                        texture,
+                        &mut meshes.fluid,
+                    );
+                }
+            }
+        }
+    }
+
+    meshes
+}
+
+fn mesh_solid(world: &World, pos: IVec3, local: Vec3, uvs: &BlockUvs, mesh: &mut MeshData) {
+    let block = world.block(pos);
+    for (face, (normal, corners)) in FACES.iter().enumerate() {
+        if !world.is_solid(pos + *normal) {
+            mesh.push_face(local, corners, uvs.block(block, face));
+        }
+    }
+}
+
+// Fluids are drawn as a box whose top sits at the fluid's level. A fluid with more of itself on
+// top fills the whole voxel so falling columns have no gaps.
+fn mesh_fluid(
+    world: &World,
+    pos: IVec3,
+    fluid: FluidState,
+    local: Vec3,
+    texture: FaceTexture,
+    mesh: &mut MeshData,
+) {
+    let same_above = world.fluid(pos + IVec3::Y).fluid == fluid.fluid;
+    let height = if same_above { 1.0 } else { fluid.height() };
+
+    for (normal, corners) in &FACES {
+        let neighbour_pos = pos + *normal;
+        let neighbour = world.fluid(neighbour_pos);
+
+        let hidden = if *normal == IVec3::Y {
+            same_above
+        } else {
+            world.is_solid(neighbour_pos)
+                || (neighbour.fluid == fluid.fluid
+                    && neighbour_height(world, neighbour_pos, neighbour) >= height)
+        };
+        if hidden {
+            continue;
+        }
+
+        let corners = corners.map(|corner| Vec3::new(corner.x, corner.y * height, corner.z));
+        mesh.push_face(local, &corners, texture);
+    }
+}
+
+fn neighbour_height(world: &World, pos: IVec3, fluid: FluidState) -> f32 {
+    if world.fluid(pos + IVec3::Y).fluid == fluid.fluid {
+        1.0
+    } else {
+        fluid.height()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rendering::atlas::{AtlasBuilder, MISSING_TILE};
+    use crate::world::chunk::Chunk;
+    use crate::world::entity::Entity;
+    use glam::Vec2;
+    use image::RgbaImage;
+
+    #[test]
+    fn faces_wind_counter_clockwise_from_outside() {
+        for (normal, corners) in &FACES {
+            let winding = (corners[1] - corners[0]).cross(corners[2] - corners[0]);
+            assert_eq!(winding.normalize(), normal.as_vec3());
+        }
+    }
+
+    #[test]
+    fn adjacent_blocks_hide_shared_faces() {
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::new());
+        world.set_block(IVec3::new(1, 1, 1), BlockId::STONE);
+        world.set_block(IVec3::new(2, 1, 1), BlockId::STONE);
+
+        let meshes = mesh_chunk(&world, IVec3::ZERO, &BlockUvs::default());
+        assert_eq!(meshes.opaque.indices.len(), 10 * FACE_INDICES.len());
+        assert!(meshes.fluid.is_empty());
+    }
+
+    #[test]
+    fn meshes_index_past_u16_vertices() {
+        let mut mesh = MeshData::default();
+        let faces = (u16::MAX as usize + 1) / 4 + 1;
+        for face in 0..faces {
+            let origin = Vec3::new(face as f32, 0.0, 0.0);
+            mesh.push_face(origin, &FACES[0].1, FaceTexture::FULL);
+        }
+
+        assert!(mesh.vertices.len() > u16::MAX as usize + 1);
+        for (face, indices) in mesh.indices.chunks(FACE_INDICES.len()).enumerate() {
+            assert!(indices.iter().all(|&index| index as usize / 4 == face));
+        }
+    }
+
+    #[test]
+    fn faces_use_their_atlas_tiles() {
+        let atlas = AtlasBuilder::new()
+            .with_tile("grass_top", RgbaImage::new(4, 4))
+            .with_tile("grass_side", RgbaImage::new(4, 4))
+            .build()
+            .unwrap();
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::new());
+        world.set_block(IVec3::new(1, 1, 1), BlockId::GRASS);
+        let uvs = BlockUvs::new(world.registry(), &atlas.uvs);
+
+        let meshes = mesh_chunk(&world, IVec3::ZERO, &uvs);
+        let top = atlas.uvs.uv("grass_top");
+        let on_top_tile = meshes.opaque.vertices.iter().filter(|vertex| {
+            let uv = Vec2::from(vertex.tex_coords);
+            uv.cmpge(top.min).all() && uv.cmple(top.max).all()
+        });
+        assert!(on_top_tile.clone().all(|vertex| vertex.position[1] == 2.0));
+        assert_eq!(on_top_tile.count(), 4);
+
+        // The bottom is dirt, which this atlas doesn't have.
+        assert_eq!(uvs.block(BlockId::GRASS, 3).uv, atlas.uvs.uv(MISSING_TILE));
+        assert_eq!(uvs.block(BlockId::GRASS, 0).uv, atlas.uvs.uv("grass_side"));
+    }
+
+    #[test]
+    fn falling_blocks_are_drawn_with_their_block_texture() {
+        let atlas = AtlasBuilder::new()
+            .with_tile("sand", RgbaImage::new(4, 4))
+            .with_tile("stone", RgbaImage::new(4, 4))
+            .build()
+            .unwrap();
+        let uvs = BlockUvs::new(&BlockRegistry::new(), &atlas.uvs);
+
+        let entity = Entity::falling_block(BlockId::SAND, IVec3::ZERO);
+        let block = entity.render.unwrap().mesh.as_block();
+        assert_eq!(block, Some(BlockId::SAND));
+
+        let sand = atlas.uvs.uv("sand");
+        let mesh = block_mesh(block.unwrap(), &uvs);
+        assert_eq!(mesh.vertices.len(), 24);
+        assert!(mesh.vertices.iter().all(|vertex| {
+            let uv = Vec2::from(vertex.tex_coords);
+            uv.cmpge(sand.min).all() && uv.cmple(sand.max).all()
+        }));
+    }
+
+    #[test]
+    fn fluid_surface_sits_at_its_level() {
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::new());
+        world.set_block(IVec3::new(1, 0, 1), BlockId::STONE);
+        world.set_fluid(IVec3::new(1, 1, 1), FluidState::flowing(Fluid::Water, 4));
+
+        let meshes = mesh_chunk(&world, IVec3::ZERO, &BlockUvs::default());
+        let top = meshes
+            .fluid
+            .vertices
+            .iter()
+            .map(|vertex| vertex.position[1])
+            .fold(f32::MIN, f32::max);
+
+        assert_eq!(top, 1.0 + FluidState::flowing(Fluid::Water, 4).height());
+        assert_eq!(meshes.fluid.indices.len(), 5 * FACE_INDICES.len());
+    }
+}
@@ -0,0 +1,428 @@
+pub mod behaviour;
+pub mod block;
+pub mod chunk;
+pub mod entity;
+pub mod falling_block;
+pub mod fluid;
+pub mod lod;
+pub mod mesher;
+pub mod physics;
+pub mod storage;
+pub mod svo;
+pub mod tick;
+pub mod visibility;
+
+use crate::world::block::{BlockId, BlockRegistry};
+use crate::world::chunk::{CHUNK_SIZE, Chunk, chunk_origin, chunk_pos, local_pos};
+use crate::world::entity::{Entities, Entity, EntityId, EntityKind};
+use crate::world::falling_block::{FALL_DELAY, try_start_falling};
+use crate::world::fluid::{Fluid, FluidState, update_fluid};
+use crate::world::storage::{LoadChunkError, load_chunk, save_chunk};
+use crate::world::tick::{TickKind, TickPriority};
+use glam::{IVec3, Vec3};
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+pub const NEIGHBOUR_DIRECTIONS: [IVec3; 6] = [
+    IVec3::X,
+    IVec3::NEG_X,
+    IVec3::Y,
+    IVec3::NEG_Y,
+    IVec3::Z,
+    IVec3::NEG_Z,
+];
+
+// The voxel world: a sparse set of loaded chunks plus the simulation state that spans them.
+// Everything here is CPU-only so it can be ticked headlessly.
+pub struct World {
+    chunks: HashMap<IVec3, Chunk>,
+    registry: BlockRegistry,
+    time: u64,
+
+    rng: SmallRng,
+    // Voxels picked per chunk per tick for random updates.
+    pub random_tick_speed: u32,
+
+    entities: Entities,
+
+    dirty_chunks: HashSet<IVec3>,
+}
+
+impl World {
+    pub const DEFAULT_RANDOM_TICK_SPEED: u32 = 3;
+    pub const ITEM_DROP_LIFETIME: u64 = 6000;
+
+    pub fn new() -> Self {
+        Self::with_registry(BlockRegistry::new())
+    }
+
+    pub fn with_registry(registry: BlockRegistry) -> Self {
+        Self {
+            chunks: HashMap::new(),
+            registry,
+            time: 0,
+            rng: SmallRng::seed_from_u64(0),
+            random_tick_speed: Self::DEFAULT_RANDOM_TICK_SPEED,
+            entities: Entities::new(),
+            dirty_chunks: HashSet::new(),
+        }
+    }
+
+    pub fn insert_chunk(&mut self, pos: IVec3, chunk: Chunk) {
+        self.chunks.insert(pos, chunk);
+        self.dirty_chunks.insert(pos);
+    }
+
+    // Unloads a chunk along with the entities inside it.
+    #[allow(dead_code)]
+    pub fn remove_chunk(&mut self, pos: IVec3) -> Option<Chunk> {
+        for id in self.entities.in_chunk(pos).to_vec() {
+            self.entities.despawn(id);
+        }
+
+        self.chunks.remove(&pos)
+    }
+
+    pub fn chunk(&self, pos: IVec3) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    // Serializes a loaded chunk together with its pending ticks and the entities inside it.
+    #[allow(dead_code)]
+    pub fn save_chunk(&self, pos: IVec3) -> Option<Vec<u8>> {
+        let entities: Vec<Entity> = self
+            .entities
+            .in_chunk(pos)
+            .iter()
+            .filter_map(|&id| self.entities.get(id).copied())
+            .collect();
+
+        self.chunks
+            .get(&pos)
+            .map(|chunk| save_chunk(chunk, &entities, self.time))
+    }
+
+    // Replaces the chunk and its entities if it's already loaded.
+    #[allow(dead_code)]
+    pub fn load_chunk(&mut self, pos: IVec3, data: &[u8]) -> Result<(), LoadChunkError> {
+        let (chunk, entities) = load_chunk(data, self.time, &self.registry)?;
+        self.remove_chunk(pos);
+        self.insert_chunk(pos, chunk);
+        for entity in entities {
+            self.entities.spawn(entity);
+        }
+
+        Ok(())
+    }
+
+    pub fn registry(&self) -> &BlockRegistry {
+        &self.registry
+    }
+
+    pub fn rng(&mut self) -> &mut SmallRng {
+        &mut self.rng
+    }
+
+    pub fn entities(&self) -> &Entities {
+        &self.entities
+    }
+
+    pub fn spawn_entity(&mut self, entity: Entity) -> EntityId {
+        self.entities.spawn(entity)
+    }
+
+    // Unloaded voxels read as air.
+    pub fn block(&self, pos: IVec3) -> BlockId {
+        self.chunks
+            .get(&chunk_pos(pos))
+            .map_or(BlockId::AIR, |chunk| chunk.block(local_pos(pos)))
+    }
+
+    // Places a block and notifies its neighbours. Returns false if the position is not loaded.
+    pub fn set_block(&mut self, pos: IVec3, block: BlockId) -> bool {
+        let properties = self.registry.get(block);
+        let (replaceable, gravity) = (properties.replaceable, properties.gravity);
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos(pos)) else {
+            return false;
+        };
+
+        let local = local_pos(pos);
+        if chunk.block(local) == block {
+            return true;
+        }
+
+        chunk.set_block(local, block);
+        if !replaceable {
+            chunk.set_fluid(local, FluidState::EMPTY);
+        }
+
+        self.mark_dirty(pos);
+        self.schedule_fluid_tick(pos);
+        if gravity {
+            self.schedule_block_tick(pos, FALL_DELAY);
+        }
+        self.notify_neighbours(pos);
+
+        true
+    }
+
+    pub fn fluid(&self, pos: IVec3) -> FluidState {
+        self.chunks
+            .get(&chunk_pos(pos))
+            .map_or(FluidState::EMPTY, |chunk| chunk.fluid(local_pos(pos)))
+    }
+
+    // Returns false if the position is not loaded.
+    pub fn set_fluid(&mut self, pos: IVec3, fluid: FluidState) -> bool {
+        let Some(chunk) = self.chunks.get_mut(&chunk_pos(pos)) else {
+            return false;
+        };
+
+        chunk.set_fluid(local_pos(pos), fluid);
+        self.mark_dirty(pos);
+        self.schedule_fluid_tick(pos);
+        self.schedule_fluid_neighbours(pos);
+
+        true
+    }
+
+    pub fn is_loaded(&self, pos: IVec3) -> bool {
+        self.chunks.contains_key(&chunk_pos(pos))
+    }
+
+    pub fn is_solid(&self, pos: IVec3) -> bool {
+        self.registry.get(self.block(pos)).solid
+    }
+
+    // Unloaded voxels are never replaceable so nothing leaks out of the loaded area.
+    pub fn is_replaceable(&self, pos: IVec3) -> bool {
+        self.is_loaded(pos) && self.registry.get(self.block(pos)).replaceable
+    }
+
+    // Advances the world by one fixed tick: due scheduled ticks, random ticks, then entities.
+    pub fn tick(&mut self) {
+        self.time += 1;
+        self.run_scheduled_ticks();
+        self.run_random_ticks();
+        self.run_entities();
+    }
+
+    // Chunks are visited in a fixed order so a seeded world always evolves the same way.
+    fn sorted_chunk_positions(&self) -> Vec<IVec3> {
+        let mut positions: Vec<IVec3> = self.chunks.keys().copied().collect();
+        positions.sort_by_key(|pos| pos.to_array());
+
+        positions
+    }
+
+    fn run_scheduled_ticks(&mut self) {
+        let mut due = vec![];
+        for chunk in self.sorted_chunk_positions() {
+            let origin = chunk_origin(chunk);
+            let ticks = self.chunks.get_mut(&chunk).unwrap().ticks_mut();
+            due.extend(
+                ticks
+                    .drain_due(self.time)
+                    .into_iter()
+                    .map(|tick| (origin + tick.local, tick)),
+            );
+        }
+        due.sort_by_key(|(_, tick)| (tick.due, tick.priority));
+
+        for (pos, tick) in due {
+            match tick.kind {
+                // A tick scheduled for a block that has since been replaced is stale.
+                TickKind::Block(block) if self.block(pos) == block => {
+                    let properties = self.registry.get(block);
+                    let (gravity, on_tick) = (properties.gravity, properties.on_scheduled_tick);
+
+                    if gravity {
+                        try_start_falling(self, pos);
+                    }
+                    if let Some(on_tick) = on_tick
+                        && self.block(pos) == block
+                    {
+                        on_tick(self, pos);
+                    }
+                }
+                TickKind::Fluid(fluid) if self.fluid(pos).fluid == fluid => {
+                    update_fluid(self, pos);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn run_random_ticks(&mut self) {
+        for chunk in self.sorted_chunk_positions() {
+            let origin = chunk_origin(chunk);
+            for _ in 0..self.random_tick_speed {
+                let local = IVec3::new(
+                    self.rng.random_range(0..CHUNK_SIZE),
+                    self.rng.random_range(0..CHUNK_SIZE),
+                    self.rng.random_range(0..CHUNK_SIZE),
+                );
+                let pos = origin + local;
+
+                if let Some(on_tick) = self.registry.get(self.block(pos)).on_random_tick {
+                    on_tick(self, pos);
+                }
+            }
+        }
+    }
+
+    // Entities in unloaded chunks are frozen until their chunk comes back.
+    fn run_entities(&mut self) {
+        for id in self.entities.ids() {
+            let Some(mut entity) = self.entities.get(id).copied() else {
+                continue;
+            };
+            if !self.chunks.contains_key(&entity.chunk()) {
+                continue;
+            }
+
+            physics::step(self, &mut entity);
+            entity.age += 1;
+            *self.entities.get_mut(id).unwrap() = entity;
+
+            match entity.kind {
+                EntityKind::FallingBlock(block) if entity.physics.on_ground => {
+                    self.entities.despawn(id);
+                    self.land_falling_block(block, entity.transform.position);
+                }
+                EntityKind::ItemDrop(_) if entity.age >= Self::ITEM_DROP_LIFETIME => {
+                    self.entities.despawn(id);
+                }
+                EntityKind::Projectile if entity.physics.collided => {
+                    self.entities.despawn(id);
+                }
+                _ => {}
+            }
+        }
+
+        self.entities.reindex();
+    }
+
+    // Lands as a block if the cell can take it, otherwise breaks into an item drop.
+    fn land_falling_block(&mut self, block: BlockId, position: Vec3) {
+        let cell = IVec3::new(
+            position.x.floor() as i32,
+            position.y.round() as i32,
+            position.z.floor() as i32,
+        );
+
+        if self.is_replaceable(cell) {
+            self.set_block(cell, block);
+        } else {
+            self.entities.spawn(Entity::new(
+                EntityKind::ItemDrop(block),
+                cell.as_vec3() + Vec3::new(0.5, 0.0, 0.5),
+            ));
+        }
+    }
+
+    // Schedules a tick for `kind` at `pos`, `delay` ticks from now (at least one). A voxel has at
+    // most one pending tick per kind; rescheduling it before it runs is a no-op.
+    pub fn schedule_tick(
+        &mut self,
+        pos: IVec3,
+        kind: TickKind,
+        delay: u64,
+        priority: TickPriority,
+    ) {
+        let due = self.time + delay.max(1);
+        if let Some(chunk) = self.chunks.get_mut(&chunk_pos(pos)) {
+            chunk
+                .ticks_mut()
+                .schedule(local_pos(pos), kind, due, priority);
+        }
+    }
+
+    // Schedules a tick for whatever block currently sits at `pos`.
+    pub fn schedule_block_tick(&mut self, pos: IVec3, delay: u64) {
+        let block = self.block(pos);
+        self.schedule_tick(pos, TickKind::Block(block), delay, TickPriority::NORMAL);
+    }
+
+    pub fn schedule_fluid_tick(&mut self, pos: IVec3) {
+        let fluid = self.fluid(pos).fluid;
+        if fluid == Fluid::Empty {
+            return;
+        }
+
+        self.schedule_tick(
+            pos,
+            TickKind::Fluid(fluid),
+            fluid.tick_delay(),
+            TickPriority::NORMAL,
+        );
+    }
+
+    fn schedule_fluid_neighbours(&mut self, pos: IVec3) {
+        for direction in NEIGHBOUR_DIRECTIONS {
+            self.schedule_fluid_tick(pos + direction);
+        }
+    }
+
+    // Tells every neighbour of `pos` that it changed; fluids next to it react on their next tick.
+    fn notify_neighbours(&mut self, pos: IVec3) {
+        for direction in NEIGHBOUR_DIRECTIONS {
+            let neighbour = pos + direction;
+            self.schedule_fluid_tick(neighbour);
+
+            let properties = self.registry.get(self.block(neighbour));
+            let (gravity, on_changed) = (properties.gravity, properties.on_neighbour_changed);
+            if gravity {
+                self.schedule_block_tick(neighbour, FALL_DELAY);
+            }
+            if let Some(on_changed) = on_changed {
+                on_changed(self, neighbour, pos);
+            }
+        }
+    }
+
+    // Marks the chunk holding `pos` for remeshing, plus any neighbour whose faces border it.
+    fn mark_dirty(&mut self, pos: IVec3) {
+        let chunk = chunk_pos(pos);
+        self.dirty_chunks.insert(chunk);
+
+        for direction in NEIGHBOUR_DIRECTIONS {
+            let neighbour = chunk_pos(pos + direction);
+            if neighbour != chunk && self.chunks.contains_key(&neighbour) {
+                self.dirty_chunks.insert(neighbour);
+            }
+        }
+    }
+
+    pub fn take_dirty_chunks(&mut self) -> Vec<IVec3> {
+        self.dirty_chunks
+            .drain()
+            .filter(|pos| self.chunks.contains_key(pos))
+            .collect()
+    }
+}
+
+impl Default for World {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_worlds {
+    use super::*;
+
+    // A single chunk with a stone floor at y = 0.
+    pub(crate) fn floor_world(registry: BlockRegistry) -> World {
+        let mut world = World::with_registry(registry);
+        world.insert_chunk(IVec3::ZERO, Chunk::new());
+        for x in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                world.set_block(IVec3::new(x, 0, z), BlockId::STONE);
+            }
+        }
+
+        world
+    }
+}
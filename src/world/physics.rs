@@ -0,0 +1,113 @@
+use crate::aabb::Aabb;
+use crate::world::World;
+use crate::world::entity::Entity;
+use glam::{IVec3, Vec3};
+
+// Axes are resolved vertical first so entities land before sliding along walls.
+const AXIS_ORDER: [usize; 3] = [1, 0, 2];
+const GROUND_FRICTION: f32 = 0.6;
+
+// Voxels an entity cannot pass through. Unloaded space counts as solid so nothing falls out of
+// the loaded area.
+fn is_obstacle(world: &World, pos: IVec3) -> bool {
+    world.is_solid(pos) || !world.is_loaded(pos)
+}
+
+fn obstacles(world: &World, region: &Aabb) -> Vec<Aabb> {
+    let min = region.min.floor().as_ivec3();
+    let max = region.max.ceil().as_ivec3();
+
+    let mut boxes = vec![];
+    for y in min.y..max.y {
+        for z in min.z..max.z {
+            for x in min.x..max.x {
+                let pos = IVec3::new(x, y, z);
+                if is_obstacle(world, pos) {
+                    boxes.push(Aabb::new(pos.as_vec3(), pos.as_vec3() + Vec3::ONE));
+                }
+            }
+        }
+    }
+
+    boxes
+}
+
+// Applies gravity and moves the entity by its velocity for one tick, stopping at solid voxels.
+pub(crate) fn step(world: &World, entity: &mut Entity) {
+    entity.transform.previous_position = entity.transform.position;
+
+    let physics = &mut entity.physics;
+    physics.velocity.y -= physics.gravity;
+    let motion = physics.velocity;
+
+    let mut bounds = Aabb::from_bottom_center(entity.transform.position, entity.size);
+    let obstacles = obstacles(world, &bounds.expand_towards(motion));
+
+    let mut moved = Vec3::ZERO;
+    for axis in AXIS_ORDER {
+        let clipped = obstacles.iter().fold(motion[axis], |motion, obstacle| {
+            bounds.clip_motion(obstacle, axis, motion)
+        });
+
+        let mut offset = Vec3::ZERO;
+        offset[axis] = clipped;
+        bounds = bounds.translate(offset);
+        moved[axis] = clipped;
+    }
+
+    entity.transform.position += moved;
+
+    physics.on_ground = motion.y < 0.0 && moved.y > motion.y;
+    physics.collided = moved != motion;
+    for axis in 0..3 {
+        if moved[axis] != motion[axis] {
+            physics.velocity[axis] = 0.0;
+        }
+    }
+
+    physics.velocity *= physics.drag;
+    if physics.on_ground {
+        physics.velocity.x *= GROUND_FRICTION;
+        physics.velocity.z *= GROUND_FRICTION;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::{BlockId, BlockRegistry};
+    use crate::world::entity::EntityKind;
+    use crate::world::test_worlds::floor_world;
+
+    #[test]
+    fn entities_come_to_rest_on_the_ground() {
+        let world = floor_world(BlockRegistry::new());
+        let mut entity = Entity::new(EntityKind::Mob, Vec3::new(8.0, 6.0, 8.0));
+
+        for _ in 0..100 {
+            step(&world, &mut entity);
+        }
+
+        assert_eq!(entity.transform.position.y, 1.0);
+        assert!(entity.physics.on_ground);
+        assert_eq!(entity.physics.velocity.y, 0.0);
+    }
+
+    #[test]
+    fn walls_stop_horizontal_motion() {
+        let mut world = floor_world(BlockRegistry::new());
+        for y in 1..4 {
+            world.set_block(IVec3::new(10, y, 8), BlockId::STONE);
+        }
+
+        let mut entity = Entity::new(EntityKind::Projectile, Vec3::new(8.5, 2.0, 8.5));
+        entity.physics.velocity = Vec3::new(0.5, 0.0, 0.0);
+        for _ in 0..10 {
+            step(&world, &mut entity);
+        }
+
+        let bounds = Aabb::from_bottom_center(entity.transform.position, entity.size);
+        assert!(bounds.max.x <= 10.0);
+        assert!(entity.transform.position.x > 9.0);
+    }
+}
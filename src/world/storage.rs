@@ -0,0 +1,451 @@
+use crate::world::block::{BlockId, BlockRegistry};
+use crate::world::chunk::{CHUNK_SIZE, CHUNK_VOLUME, Chunk};
+use crate::world::entity::{Entity, EntityKind};
+use crate::world::fluid::{Fluid, FluidState};
+use crate::world::tick::{TickKind, TickPriority};
+use glam::{IVec3, Vec3};
+use thiserror::Error;
+
+const MAGIC: &[u8; 4] = b"VXCH";
+const VERSION: u16 = 2;
+// Version 1 predates entities; it is still readable and loads without any.
+const MIN_VERSION: u16 = 1;
+
+const FALLING_BIT: u8 = 0x80;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum LoadChunkError {
+    #[error("Chunk data ended unexpectedly.")]
+    Truncated,
+    #[error("Chunk data does not start with the chunk header.")]
+    InvalidMagic,
+    #[error("Unsupported chunk format version {0}.")]
+    UnsupportedVersion(u16),
+    #[error("Unknown block id {0}.")]
+    InvalidBlock(u16),
+    #[error("Unknown fluid id {0}.")]
+    InvalidFluid(u16),
+    #[error("Fluid level {0} is above the maximum.")]
+    InvalidFluidLevel(u8),
+    #[error("Scheduled tick at {0} is outside the chunk.")]
+    InvalidTickPosition(IVec3),
+    #[error("Unknown scheduled tick kind {0}.")]
+    InvalidTickKind(u8),
+    #[error("Unknown entity kind {0}.")]
+    InvalidEntityKind(u8),
+}
+
+// Chunks are stored as little-endian binary: header, block ids, fluid states, pending ticks and
+// entities. Tick due times are saved relative to `time` so they survive the world clock being
+// reset. Entities only store their kind and motion; other components come from the kind.
+pub fn save_chunk(chunk: &Chunk, entities: &[Entity], time: u64) -> Vec<u8> {
+    let mut writer = ChunkWriter::default();
+    writer.bytes(MAGIC);
+    writer.u16(VERSION);
+
+    for index in 0..CHUNK_VOLUME {
+        let local = index_to_local(index);
+        writer.u16(chunk.block(local).0);
+    }
+
+    for index in 0..CHUNK_VOLUME {
+        let fluid = chunk.fluid(index_to_local(index));
+        writer.u8(fluid_id(fluid.fluid));
+        writer.u8(fluid.level | if fluid.falling { FALLING_BIT } else { 0 });
+    }
+
+    let ticks = chunk.ticks().iter_sorted();
+    writer.u32(ticks.len() as u32);
+    for tick in ticks {
+        writer.u8(tick.local.x as u8);
+        writer.u8(tick.local.y as u8);
+        writer.u8(tick.local.z as u8);
+        writer.u64(tick.due.saturating_sub(time));
+        writer.u8(tick.priority.0 as u8);
+        match tick.kind {
+            TickKind::Block(block) => {
+                writer.u8(0);
+                writer.u16(block.0);
+            }
+            TickKind::Fluid(fluid) => {
+                writer.u8(1);
+                writer.u16(fluid_id(fluid) as u16);
+            }
+        }
+    }
+
+    writer.u32(entities.len() as u32);
+    for entity in entities {
+        match entity.kind {
+            EntityKind::FallingBlock(block) => {
+                writer.u8(0);
+                writer.u16(block.0);
+            }
+            EntityKind::ItemDrop(block) => {
+                writer.u8(1);
+                writer.u16(block.0);
+            }
+            EntityKind::Mob => {
+                writer.u8(2);
+                writer.u16(0);
+            }
+            EntityKind::Projectile => {
+                writer.u8(3);
+                writer.u16(0);
+            }
+        }
+        writer.vec3(entity.transform.position);
+        writer.vec3(entity.physics.velocity);
+        writer.u64(entity.age);
+    }
+
+    writer.data
+}
+
+// Block ids are checked against `registry`, so a corrupt save or one from a build with more
+// blocks is rejected rather than panicking later.
+pub fn load_chunk(
+    data: &[u8],
+    time: u64,
+    registry: &BlockRegistry,
+) -> Result<(Chunk, Vec<Entity>), LoadChunkError> {
+    let mut reader = ChunkReader {
+        data,
+        cursor: 0,
+        blocks: registry.len(),
+    };
+    if reader.bytes(MAGIC.len())? != MAGIC {
+        return Err(LoadChunkError::InvalidMagic);
+    }
+
+    let version = reader.u16()?;
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(LoadChunkError::UnsupportedVersion(version));
+    }
+
+    let mut chunk = Chunk::new();
+    for index in 0..CHUNK_VOLUME {
+        chunk.set_block(index_to_local(index), reader.block()?);
+    }
+
+    for index in 0..CHUNK_VOLUME {
+        let fluid = fluid_from_id(reader.u8()? as u16)?;
+        let packed = reader.u8()?;
+        let level = packed & !FALLING_BIT;
+        if level > FluidState::MAX_LEVEL {
+            return Err(LoadChunkError::InvalidFluidLevel(level));
+        }
+        chunk.set_fluid(
+            index_to_local(index),
+            FluidState {
+                fluid,
+                level,
+                falling: packed & FALLING_BIT != 0,
+            },
+        );
+    }
+
+    let tick_count = reader.u32()?;
+    for _ in 0..tick_count {
+        let local = IVec3::new(
+            reader.u8()? as i32,
+            reader.u8()? as i32,
+            reader.u8()? as i32,
+        );
+        if local.max_element() >= CHUNK_SIZE {
+            return Err(LoadChunkError::InvalidTickPosition(local));
+        }
+        let delay = reader.u64()?;
+        let priority = TickPriority(reader.u8()? as i8);
+        let kind = match reader.u8()? {
+            0 => TickKind::Block(reader.block()?),
+            1 => TickKind::Fluid(fluid_from_id(reader.u16()?)?),
+            other => return Err(LoadChunkError::InvalidTickKind(other)),
+        };
+
+        chunk
+            .ticks_mut()
+            .schedule(local, kind, time.saturating_add(delay), priority);
+    }
+
+    let mut entities = vec![];
+    if version >= 2 {
+        let entity_count = reader.u32()?;
+        for _ in 0..entity_count {
+            let kind = match reader.u8()? {
+                0 => EntityKind::FallingBlock(reader.block()?),
+                1 => EntityKind::ItemDrop(reader.block()?),
+                2 => {
+                    reader.u16()?;
+                    EntityKind::Mob
+                }
+                3 => {
+                    reader.u16()?;
+                    EntityKind::Projectile
+                }
+                other => return Err(LoadChunkError::InvalidEntityKind(other)),
+            };
+
+            let mut entity = Entity::new(kind, reader.vec3()?);
+            entity.physics.velocity = reader.vec3()?;
+            entity.age = reader.u64()?;
+            entities.push(entity);
+        }
+    }
+
+    Ok((chunk, entities))
+}
+
+fn index_to_local(index: usize) -> IVec3 {
+    let index = index as i32;
+    IVec3::new(
+        index % CHUNK_SIZE,
+        index / (CHUNK_SIZE * CHUNK_SIZE),
+        (index / CHUNK_SIZE) % CHUNK_SIZE,
+    )
+}
+
+fn fluid_id(fluid: Fluid) -> u8 {
+    match fluid {
+        Fluid::Empty => 0,
+        Fluid::Water => 1,
+        Fluid::Lava => 2,
+    }
+}
+
+fn fluid_from_id(id: u16) -> Result<Fluid, LoadChunkError> {
+    match id {
+        0 => Ok(Fluid::Empty),
+        1 => Ok(Fluid::Water),
+        2 => Ok(Fluid::Lava),
+        other => Err(LoadChunkError::InvalidFluid(other)),
+    }
+}
+
+#[derive(Default)]
+struct ChunkWriter {
+    data: Vec<u8>,
+}
+
+impl ChunkWriter {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn vec3(&mut self, value: Vec3) {
+        for component in value.to_array() {
+            self.bytes(&component.to_le_bytes());
+        }
+    }
+}
+
+struct ChunkReader<'a> {
+    data: &'a [u8],
+    cursor: usize,
+    blocks: usize, // registered block count
+}
+
+impl<'a> ChunkReader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], LoadChunkError> {
+        let bytes = self
+            .data
+            .get(self.cursor..self.cursor + len)
+            .ok_or(LoadChunkError::Truncated)?;
+        self.cursor += len;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, LoadChunkError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, LoadChunkError> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn block(&mut self) -> Result<BlockId, LoadChunkError> {
+        let id = self.u16()?;
+        if id as usize >= self.blocks {
+            return Err(LoadChunkError::InvalidBlock(id));
+        }
+
+        Ok(BlockId(id))
+    }
+
+    fn u32(&mut self) -> Result<u32, LoadChunkError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, LoadChunkError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    fn f32(&mut self) -> Result<f32, LoadChunkError> {
+        Ok(f32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, LoadChunkError> {
+        Ok(Vec3::new(self.f32()?, self.f32()?, self.f32()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::test_worlds::floor_world;
+
+    #[test]
+    fn index_to_local_matches_chunk_index() {
+        for index in [0, 1, 17, 300, CHUNK_VOLUME - 1] {
+            assert_eq!(Chunk::index(index_to_local(index)), index);
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_blocks_fluids_and_tick_delays() {
+        let registry = BlockRegistry::new();
+        let mut chunk = Chunk::new();
+        chunk.set_block(IVec3::new(1, 2, 3), BlockId::STONE);
+        chunk.set_fluid(IVec3::new(4, 5, 6), FluidState::falling(Fluid::Lava));
+        chunk.ticks_mut().schedule(
+            IVec3::new(4, 5, 6),
+            TickKind::Fluid(Fluid::Lava),
+            130,
+            TickPriority(-1),
+        );
+
+        let (loaded, _) = load_chunk(&save_chunk(&chunk, &[], 100), 1000, &registry).unwrap();
+        assert_eq!(loaded.block(IVec3::new(1, 2, 3)), BlockId::STONE);
+        assert_eq!(
+            loaded.fluid(IVec3::new(4, 5, 6)),
+            FluidState::falling(Fluid::Lava)
+        );
+
+        let ticks = loaded.ticks().iter_sorted();
+        assert_eq!(ticks.len(), 1);
+        assert_eq!(ticks[0].due, 1030);
+        assert_eq!(ticks[0].priority, TickPriority(-1));
+    }
+
+    #[test]
+    fn round_trip_keeps_entities() {
+        let mut mob = Entity::new(EntityKind::Mob, Vec3::new(1.5, 2.0, 3.5));
+        mob.physics.velocity = Vec3::new(0.1, -0.2, 0.0);
+        mob.age = 42;
+        let drop = Entity::new(EntityKind::ItemDrop(BlockId::SAND), Vec3::splat(4.0));
+
+        let (_, entities) = load_chunk(
+            &save_chunk(&Chunk::new(), &[mob, drop], 0),
+            0,
+            &BlockRegistry::new(),
+        )
+        .unwrap();
+        assert_eq!(entities, vec![mob, drop]);
+    }
+
+    #[test]
+    fn reloading_a_loaded_chunk_replaces_its_entities() {
+        let mut world = floor_world(BlockRegistry::new());
+        world.spawn_entity(Entity::new(EntityKind::Mob, Vec3::new(1.5, 1.0, 1.5)));
+        let data = world.save_chunk(IVec3::ZERO).unwrap();
+
+        world.load_chunk(IVec3::ZERO, &data).unwrap();
+        world.load_chunk(IVec3::ZERO, &data).unwrap();
+        assert_eq!(world.entities().len(), 1);
+        assert_eq!(world.entities().in_chunk(IVec3::ZERO).len(), 1);
+        assert_eq!(world.block(IVec3::ZERO), BlockId::STONE);
+    }
+
+    #[test]
+    fn version_one_chunks_load_without_entities() {
+        let mut data = save_chunk(&Chunk::new(), &[], 0);
+        data[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&1u16.to_le_bytes());
+        data.truncate(data.len() - 4);
+
+        let (_, entities) = load_chunk(&data, 0, &BlockRegistry::new()).unwrap();
+        assert!(entities.is_empty());
+    }
+
+    #[test]
+    fn truncated_data_is_rejected() {
+        let registry = BlockRegistry::new();
+        let data = save_chunk(&Chunk::new(), &[], 0);
+        assert_eq!(
+            load_chunk(&data[..data.len() - 1], 0, &registry).err(),
+            Some(LoadChunkError::Truncated)
+        );
+        assert_eq!(
+            load_chunk(b"nope", 0, &registry).err(),
+            Some(LoadChunkError::InvalidMagic)
+        );
+    }
+
+    fn patch(data: &[u8], offset: usize, bytes: &[u8]) -> Vec<u8> {
+        let mut data = data.to_vec();
+        data[offset..offset + bytes.len()].copy_from_slice(bytes);
+
+        data
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let registry = BlockRegistry::new();
+        let load = |data: Vec<u8>| load_chunk(&data, 0, &registry).err();
+        let blocks = MAGIC.len() + 2;
+        let fluids = blocks + CHUNK_VOLUME * 2;
+        // Past the fluids and the tick count: local, delay, priority, kind and id.
+        let tick = fluids + CHUNK_VOLUME * 2 + 4;
+
+        let mut chunk = Chunk::new();
+        chunk.ticks_mut().schedule(
+            IVec3::ZERO,
+            TickKind::Fluid(Fluid::Water),
+            5,
+            TickPriority(-1),
+        );
+        let data = save_chunk(&chunk, &[], 0);
+
+        let unknown = registry.len() as u16;
+        assert_eq!(
+            load(patch(&data, blocks, &unknown.to_le_bytes())),
+            Some(LoadChunkError::InvalidBlock(unknown))
+        );
+        assert_eq!(
+            load(patch(&data, fluids + 1, &[FluidState::MAX_LEVEL + 1])),
+            Some(LoadChunkError::InvalidFluidLevel(FluidState::MAX_LEVEL + 1))
+        );
+        assert_eq!(
+            load(patch(&data, tick + 1, &[CHUNK_SIZE as u8])),
+            Some(LoadChunkError::InvalidTickPosition(IVec3::new(
+                0, CHUNK_SIZE, 0
+            )))
+        );
+        // Tick fluid ids are saved as u16, and mustn't be truncated into a valid id.
+        assert_eq!(
+            load(patch(&data, tick + 13, &257u16.to_le_bytes())),
+            Some(LoadChunkError::InvalidFluid(257))
+        );
+
+        // A huge delay saturates rather than overflowing.
+        let data = patch(&data, tick + 3, &u64::MAX.to_le_bytes());
+        let (loaded, _) = load_chunk(&data, 10, &registry).unwrap();
+        assert_eq!(loaded.ticks().iter_sorted()[0].due, u64::MAX);
+    }
+}
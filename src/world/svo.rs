@@ -0,0 +1,209 @@
+use crate::world::World;
+use crate::world::chunk::{CHUNK_SIZE, chunk_origin, chunk_pos};
+use glam::IVec3;
+
+// Set on leaf nodes, whose low 16 bits are the block filling them. Other nodes are the index of
+// their 8 children, ordered x + y * 2 + z * 4.
+pub const LEAF: u32 = 1 << 31;
+
+// The solid blocks of a cube of voxels as a sparse voxel octree, in the flat form it's uploaded to
+// the GPU in: `nodes[0]` is the root, and uniform regions are single leaves however large.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Svo {
+    origin: IVec3,
+    size: u32, // voxels per edge, a power of two
+    nodes: Vec<u32>,
+}
+
+impl Svo {
+    // Covers `size`³ voxels from `origin`. Fluids and non-solid blocks are left out.
+    pub fn build(world: &World, origin: IVec3, size: u32) -> Self {
+        assert!(
+            size.is_power_of_two(),
+            "octree size {size} isn't a power of two"
+        );
+
+        let mut nodes = vec![0];
+        nodes[0] = build_node(world, origin, size as i32, &mut nodes);
+
+        Self {
+            origin,
+            size,
+            nodes,
+        }
+    }
+
+    // Covers `chunks`³ chunks from the chunk at `min`.
+    pub fn from_chunks(world: &World, min: IVec3, chunks: u32) -> Self {
+        Self::build(world, chunk_origin(min), chunks * CHUNK_SIZE as u32)
+    }
+
+    pub fn origin(&self) -> IVec3 {
+        self.origin
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn nodes(&self) -> &[u32] {
+        &self.nodes
+    }
+}
+
+// Appends the subtree of the cube at `min` and returns its node.
+fn build_node(world: &World, min: IVec3, size: i32, nodes: &mut Vec<u32>) -> u32 {
+    if size == 1 {
+        return if world.is_solid(min) {
+            LEAF | world.block(min).0 as u32
+        } else {
+            LEAF
+        };
+    }
+
+    // Whole chunks that aren't loaded are empty, without looking at every voxel.
+    if size % CHUNK_SIZE == 0 && chunk_origin(chunk_pos(min)) == min {
+        let chunks = size / CHUNK_SIZE;
+        let first = chunk_pos(min);
+        let any_loaded = (0..chunks * chunks * chunks).any(|index| {
+            let offset = IVec3::new(
+                index % chunks,
+                index / (chunks * chunks),
+                index / chunks % chunks,
+            );
+            world.chunk(first + offset).is_some()
+        });
+        if !any_loaded {
+            return LEAF;
+        }
+    }
+
+    let half = size / 2;
+    let children: [u32; 8] = std::array::from_fn(|child| {
+        let offset = IVec3::new(child as i32 & 1, child as i32 >> 1 & 1, child as i32 >> 2);
+        build_node(world, min + offset * half, half, nodes)
+    });
+    if children[0] & LEAF != 0 && children.iter().all(|&child| child == children[0]) {
+        return children[0];
+    }
+
+    let base = nodes.len() as u32;
+    nodes.extend_from_slice(&children);
+    base
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::BlockId;
+    use crate::world::chunk::Chunk;
+
+    // AIR outside of the octree.
+    fn block(svo: &Svo, pos: IVec3) -> BlockId {
+        let local = pos - svo.origin;
+        if local.cmplt(IVec3::ZERO).any() || local.cmpge(IVec3::splat(svo.size as i32)).any() {
+            return BlockId::AIR;
+        }
+
+        let mut node = svo.nodes[0];
+        let mut min = IVec3::ZERO;
+        let mut size = svo.size as i32;
+        while node & LEAF == 0 {
+            size /= 2;
+            let upper = local.cmpge(min + size);
+            let child = upper.bitmask();
+            min += IVec3::select(upper, IVec3::splat(size), IVec3::ZERO);
+            node = svo.nodes[(node + child) as usize];
+        }
+
+        BlockId(node as u16)
+    }
+
+    fn world_with_chunks(chunks: &[IVec3]) -> World {
+        let mut world = World::new();
+        for &pos in chunks {
+            world.insert_chunk(pos, Chunk::new());
+        }
+        world
+    }
+
+    #[test]
+    fn uniform_regions_are_single_leaves() {
+        let world = world_with_chunks(&[]);
+        let empty = Svo::from_chunks(&world, IVec3::ZERO, 4);
+        assert_eq!(empty.nodes(), [LEAF]);
+        assert_eq!(block(&empty, IVec3::new(5, 5, 5)), BlockId::AIR);
+
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::filled(BlockId::STONE));
+        let filled = Svo::from_chunks(&world, IVec3::ZERO, 1);
+        assert_eq!(filled.nodes(), [LEAF | BlockId::STONE.0 as u32]);
+    }
+
+    #[test]
+    fn known_shapes_have_known_trees() {
+        // One voxel needs a node at every level down to it.
+        let mut world = world_with_chunks(&[IVec3::ZERO]);
+        let voxel = IVec3::new(3, 5, 7);
+        world.set_block(voxel, BlockId::SAND);
+        let svo = Svo::from_chunks(&world, IVec3::ZERO, 1);
+        assert_eq!(svo.nodes().len(), 1 + 4 * 8);
+        assert_eq!(block(&svo, voxel), BlockId::SAND);
+        assert_eq!(block(&svo, voxel + IVec3::X), BlockId::AIR);
+
+        // The bottom half filled is the root's lower four children.
+        let mut world = world_with_chunks(&[IVec3::ZERO]);
+        for index in 0..CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE / 2 {
+            let pos = IVec3::new(
+                index % CHUNK_SIZE,
+                index / (CHUNK_SIZE * CHUNK_SIZE),
+                index / CHUNK_SIZE % CHUNK_SIZE,
+            );
+            world.set_block(pos, BlockId::STONE);
+        }
+        let svo = Svo::from_chunks(&world, IVec3::ZERO, 1);
+        let stone = LEAF | BlockId::STONE.0 as u32;
+        assert_eq!(
+            svo.nodes(),
+            [1, stone, stone, LEAF, LEAF, stone, stone, LEAF, LEAF]
+        );
+    }
+
+    #[test]
+    fn lookups_match_the_world() {
+        let chunks: Vec<IVec3> = (0..8)
+            .map(|index| IVec3::new(index & 1, index >> 1 & 1, index >> 2) - IVec3::ONE)
+            .collect();
+        let mut world = world_with_chunks(&chunks);
+
+        // A ball of stone with a layer of grass on top, around the corner all chunks share.
+        let radius = 10;
+        for x in -radius..radius {
+            for y in -radius..radius {
+                for z in -radius..radius {
+                    let pos = IVec3::new(x, y, z);
+                    if pos.length_squared() < radius * radius {
+                        let block = if y > 6 {
+                            BlockId::GRASS
+                        } else {
+                            BlockId::STONE
+                        };
+                        world.set_block(pos, block);
+                    }
+                }
+            }
+        }
+
+        let svo = Svo::from_chunks(&world, -IVec3::ONE, 2);
+        assert_eq!(svo.origin(), IVec3::splat(-CHUNK_SIZE));
+        for x in -CHUNK_SIZE..CHUNK_SIZE {
+            for y in -CHUNK_SIZE..CHUNK_SIZE {
+                for z in -CHUNK_SIZE..CHUNK_SIZE {
+                    let pos = IVec3::new(x, y, z);
+                    assert_eq!(block(&svo, pos), world.block(pos), "at {pos}");
+                }
+            }
+        }
+        assert!(svo.nodes().len() < (2 * CHUNK_SIZE).pow(3) as usize / 4);
+    }
+}
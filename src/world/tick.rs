@@ -0,0 +1,140 @@
+use crate::world::block::BlockId;
+use crate::world::fluid::Fluid;
+use glam::IVec3;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+// Lower values run first among ticks due on the same game tick.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TickPriority(pub i8);
+
+impl TickPriority {
+    pub const NORMAL: Self = Self(0);
+}
+
+// What a scheduled tick updates. A voxel can have one pending tick per kind.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TickKind {
+    Block(BlockId),
+    Fluid(Fluid),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ScheduledTick {
+    pub local: IVec3,
+    pub due: u64,
+    pub priority: TickPriority,
+    pub kind: TickKind,
+    order: u64,
+}
+
+impl ScheduledTick {
+    fn sort_key(&self) -> (u64, TickPriority, u64) {
+        (self.due, self.priority, self.order)
+    }
+}
+
+impl PartialOrd for ScheduledTick {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ScheduledTick {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+// Pending ticks of a single chunk, ordered by due time, then priority, then insertion order.
+#[derive(Clone, Debug, Default)]
+pub struct TickQueue {
+    ticks: BinaryHeap<Reverse<ScheduledTick>>,
+    pending: HashSet<(IVec3, TickKind)>,
+    next_order: u64,
+}
+
+impl TickQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns false if the voxel already has a pending tick of this kind.
+    pub fn schedule(
+        &mut self,
+        local: IVec3,
+        kind: TickKind,
+        due: u64,
+        priority: TickPriority,
+    ) -> bool {
+        if !self.pending.insert((local, kind)) {
+            return false;
+        }
+
+        self.ticks.push(Reverse(ScheduledTick {
+            local,
+            due,
+            priority,
+            kind,
+            order: self.next_order,
+        }));
+        self.next_order += 1;
+
+        true
+    }
+
+    // Removes and returns every tick due at or before `time`, in execution order.
+    pub fn drain_due(&mut self, time: u64) -> Vec<ScheduledTick> {
+        let mut due = vec![];
+        while let Some(Reverse(tick)) = self.ticks.peek() {
+            if tick.due > time {
+                break;
+            }
+
+            let Reverse(tick) = self.ticks.pop().unwrap();
+            self.pending.remove(&(tick.local, tick.kind));
+            due.push(tick);
+        }
+
+        due
+    }
+
+    // All pending ticks in execution order, for saving.
+    pub fn iter_sorted(&self) -> Vec<ScheduledTick> {
+        let mut ticks: Vec<ScheduledTick> = self.ticks.iter().map(|Reverse(tick)| *tick).collect();
+        ticks.sort();
+
+        ticks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_run_by_due_time_then_priority_then_order() {
+        let mut queue = TickQueue::new();
+        let kind = |id| TickKind::Block(BlockId(id));
+
+        queue.schedule(IVec3::ZERO, kind(1), 5, TickPriority::NORMAL);
+        queue.schedule(IVec3::ZERO, kind(2), 3, TickPriority(1));
+        queue.schedule(IVec3::ZERO, kind(3), 3, TickPriority(-1));
+        queue.schedule(IVec3::ZERO, kind(4), 3, TickPriority(-1));
+
+        let order: Vec<TickKind> = queue.drain_due(10).iter().map(|tick| tick.kind).collect();
+        assert_eq!(order, vec![kind(3), kind(4), kind(2), kind(1)]);
+    }
+
+    #[test]
+    fn duplicate_ticks_are_ignored_until_they_run() {
+        let mut queue = TickQueue::new();
+        let kind = TickKind::Fluid(Fluid::Water);
+
+        assert!(queue.schedule(IVec3::ONE, kind, 2, TickPriority::NORMAL));
+        assert!(!queue.schedule(IVec3::ONE, kind, 1, TickPriority::NORMAL));
+        assert!(queue.drain_due(1).is_empty());
+        assert_eq!(queue.drain_due(2).len(), 1);
+        assert!(queue.schedule(IVec3::ONE, kind, 3, TickPriority::NORMAL));
+    }
+}
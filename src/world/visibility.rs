@@ -0,0 +1,181 @@
+use crate::world::chunk::{CHUNK_SIZE, CHUNK_VOLUME, Chunk, chunk_pos};
+use crate::world::{NEIGHBOUR_DIRECTIONS, World};
+use glam::{IVec3, Vec3};
+use std::collections::{HashSet, VecDeque};
+
+// Which faces of a chunk see each other through non-solid voxels. Faces are indices into
+// `NEIGHBOUR_DIRECTIONS`. Looking into a chunk through one face can only reveal what's behind the
+// faces connected to it, which is what cave culling is built on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ChunkVisibility {
+    connections: u64, // bit a * 6 + b
+}
+
+impl ChunkVisibility {
+    pub const NONE: Self = Self { connections: 0 };
+    pub const ALL: Self = Self {
+        connections: (1 << 36) - 1,
+    };
+
+    // Flood fills the chunk's non-solid voxels and connects every face each region touches.
+    // Unloaded chunks are open, like the air they read as.
+    pub fn compute(world: &World, pos: IVec3) -> Self {
+        let Some(chunk) = world.chunk(pos) else {
+            return Self::ALL;
+        };
+
+        let registry = world.registry();
+        let mut visited: Vec<bool> = (0..CHUNK_VOLUME)
+            .map(|index| registry.get(chunk.block(local(index))).solid)
+            .collect();
+
+        let mut visibility = Self::NONE;
+        let mut stack = vec![];
+        for start in 0..CHUNK_VOLUME {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            stack.push(start);
+
+            let mut faces = 0u8;
+            while let Some(index) = stack.pop() {
+                let voxel = local(index);
+                for (face, direction) in NEIGHBOUR_DIRECTIONS.iter().enumerate() {
+                    let neighbour = voxel + *direction;
+                    if neighbour.cmplt(IVec3::ZERO).any()
+                        || neighbour.cmpge(IVec3::splat(CHUNK_SIZE)).any()
+                    {
+                        faces |= 1 << face;
+                        continue;
+                    }
+
+                    let neighbour = Chunk::index(neighbour);
+                    if !visited[neighbour] {
+                        visited[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+
+            for a in 0..6 {
+                for b in 0..6 {
+                    if faces & (1 << a) != 0 && faces & (1 << b) != 0 {
+                        visibility.connections |= 1 << (a * 6 + b);
+                    }
+                }
+            }
+        }
+
+        visibility
+    }
+
+    pub fn connects(&self, a: usize, b: usize) -> bool {
+        self.connections & (1 << (a * 6 + b)) != 0
+    }
+}
+
+fn local(index: usize) -> IVec3 {
+    let size = CHUNK_SIZE as usize;
+    IVec3::new(
+        (index % size) as i32,
+        (index / (size * size)) as i32,
+        (index / size % size) as i32,
+    )
+}
+
+// `NEIGHBOUR_DIRECTIONS` lists each direction next to its opposite.
+fn opposite(face: usize) -> usize {
+    face ^ 1
+}
+
+// The chunks that might be visible from `camera`, up to `max_distance` chunks away on any axis.
+// Walks outwards from the camera's chunk, leaving each chunk only through faces connected to the
+// one it was entered through, and never turning back towards the camera.
+pub fn visible_chunks(
+    camera: Vec3,
+    max_distance: i32,
+    visibility: impl Fn(IVec3) -> ChunkVisibility,
+) -> HashSet<IVec3> {
+    let start = chunk_pos(camera.floor().as_ivec3());
+
+    let mut visible = HashSet::from([start]);
+    // Each chunk with the face it was entered through and the directions taken to get there.
+    let mut queue = VecDeque::from([(start, None::<usize>, 0u8)]);
+    while let Some((pos, entered, directions)) = queue.pop_front() {
+        let connections = visibility(pos);
+        for (face, direction) in NEIGHBOUR_DIRECTIONS.iter().enumerate() {
+            if directions & (1 << opposite(face)) != 0 {
+                continue;
+            }
+            if entered.is_some_and(|entered| !connections.connects(entered, face)) {
+                continue;
+            }
+
+            let neighbour = pos + *direction;
+            if (neighbour - start).abs().max_element() > max_distance || !visible.insert(neighbour)
+            {
+                continue;
+            }
+            queue.push_back((neighbour, Some(opposite(face)), directions | 1 << face));
+        }
+    }
+
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::block::BlockId;
+    use crate::world::chunk::chunk_origin;
+
+    #[test]
+    fn faces_connect_through_air() {
+        let mut world = World::new();
+        world.insert_chunk(IVec3::ZERO, Chunk::new());
+        world.insert_chunk(IVec3::X, Chunk::filled(BlockId::STONE));
+        assert_eq!(
+            ChunkVisibility::compute(&world, IVec3::ZERO),
+            ChunkVisibility::ALL
+        );
+        assert_eq!(
+            ChunkVisibility::compute(&world, IVec3::X),
+            ChunkVisibility::NONE
+        );
+        assert_eq!(
+            ChunkVisibility::compute(&world, IVec3::Y),
+            ChunkVisibility::ALL
+        );
+
+        // A wall across the chunk at x = 8 splits it in two.
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                world.set_block(IVec3::new(8, y, z), BlockId::STONE);
+            }
+        }
+        let visibility = ChunkVisibility::compute(&world, IVec3::ZERO);
+        let [x, neg_x, y, ..] = [0, 1, 2];
+        assert!(!visibility.connects(x, neg_x));
+        assert!(visibility.connects(x, y) && visibility.connects(neg_x, y));
+    }
+
+    #[test]
+    fn walls_of_solid_chunks_hide_what_is_behind_them() {
+        let camera = chunk_origin(IVec3::ZERO).as_vec3() + Vec3::splat(8.0);
+
+        let open = visible_chunks(camera, 1, |_| ChunkVisibility::ALL);
+        assert_eq!(open.len(), 27);
+
+        // Every neighbour is solid, so only they and the camera's own chunk can be seen.
+        let enclosed = visible_chunks(camera, 3, |pos| {
+            if pos == IVec3::ZERO {
+                ChunkVisibility::ALL
+            } else {
+                ChunkVisibility::NONE
+            }
+        });
+        assert_eq!(enclosed.len(), 7);
+        assert!(enclosed.contains(&IVec3::NEG_Y) && !enclosed.contains(&IVec3::new(2, 0, 0)));
+    }
+}
@@ -0,0 +1,562 @@
+use crate::aabb::Aabb;
+use crate::rendering::buffer::Buffer;
+use crate::rendering::compute::ComputeStage;
+use crate::rendering::global_bindings::GlobalBindings;
+use crate::rendering::gpu_cull::{CullCandidate, GpuCuller};
+use crate::rendering::indirect::{IndirectBatch, IndirectDraws};
+use crate::rendering::instance::{InstanceBatcher, InstanceData};
+use crate::rendering::material::Material;
+use crate::rendering::mesh::Mesh;
+use crate::rendering::mesh_pool::{MeshPool, PoolAllocation};
+use crate::rendering::render_object::{PassType, RenderObject};
+use crate::rendering::renderer::Renderer;
+use crate::rendering::shader::Shader;
+use crate::rendering::svo_volume::SvoVolume;
+use crate::rendering::vertex::Vertex;
+use crate::world::World;
+use crate::world::chunk::{CHUNK_SIZE, chunk_origin, chunk_pos};
+use crate::world::entity::{MaterialKey, MeshKey};
+use crate::world::lod::{LOD_SCALES, LodSettings, chunk_distance, mesh_lod};
+use crate::world::mesher::{BlockUvs, MeshData, block_mesh, cube_mesh, mesh_chunk};
+use crate::world::svo::Svo;
+use crate::world::visibility::{ChunkVisibility, visible_chunks};
+use glam::{IVec3, Mat4, Vec3};
+use log::warn;
+use std::collections::{HashMap, HashSet};
+
+// Initial room in the shared buffers for opaque chunk meshes, which grow from there. Chunks that
+// would grow them past the device's limits get their own buffers instead.
+const POOL_VERTICES: u32 = 1 << 18;
+const POOL_INDICES: u32 = 3 << 17;
+
+// Chunks remeshed per update because their level of detail changed, to spread the work over
+// frames when the camera moves fast.
+const MAX_LOD_CHANGES_PER_UPDATE: usize = 32;
+
+// Chunks per edge of the regions drawn as one `SvoVolume`, aligned to multiples of it.
+const REGION_CHUNKS: i32 = 4;
+const MAX_REGION_BUILDS_PER_UPDATE: usize = 2;
+
+// Opaque chunk meshes that fit live in the `MeshPool`, in world space.
+struct PooledMesh {
+    allocation: PoolAllocation,
+    bounds: Aabb,
+}
+
+struct ChunkRenderData {
+    pooled: Option<PooledMesh>,
+    opaque: Option<RenderObject>, // only if the mesh didn't fit in the pool
+    fluid: Option<RenderObject>,
+    visibility: ChunkVisibility,
+    level: usize, // of detail, see `LodSettings`
+}
+
+// Keeps one GPU mesh per chunk in sync with the world and submits them each frame, together with
+// the entities inside them. Opaque chunks are drawn as one indirect batch from shared buffers.
+pub struct WorldRenderer {
+    chunks: HashMap<IVec3, ChunkRenderData>,
+    pool: MeshPool,
+    identity: Buffer<InstanceData>, // the one instance of pooled meshes
+    draws: IndirectDraws,
+    // None if the cull shader failed to compile.
+    culler: Option<GpuCuller>,
+    opaque_material: Material,
+    fluid_material: Material,
+    uvs: BlockUvs,
+
+    // Entities refer to these by key and are drawn with one instanced draw per pair. Block meshes
+    // are added as entities first need them.
+    meshes: HashMap<MeshKey, Mesh>,
+    materials: HashMap<MaterialKey, Material>,
+    entity_batches: InstanceBatcher<(MeshKey, MaterialKey)>,
+
+    // In chunks around the camera on any axis. Further chunks are left to the far field, or not
+    // drawn without it.
+    pub render_distance: i32,
+    // Which chunks are meshed at a lower resolution, by distance.
+    pub lod: LodSettings,
+    // Skips chunks hidden behind solid ones, see `visible_chunks`.
+    pub occlusion_culling: bool,
+    occluded: u32, // chunks skipped by occlusion culling last frame
+    // Frustum culls pooled chunks in a compute pass instead of on the CPU.
+    pub gpu_culling: bool,
+    // Raymarches regions entirely beyond `render_distance` instead of drawing their meshes. Until
+    // a region's octree is built, its meshes are drawn.
+    pub far_field: bool,
+    far_regions: HashMap<IVec3, SvoVolume>,
+    // None if the raymarch shader failed to compile.
+    svo_shader: Option<Shader>,
+}
+
+impl WorldRenderer {
+    // `uvs` has to match the texture bound by the materials.
+    pub fn new(
+        renderer: &Renderer,
+        global_bindings: &GlobalBindings,
+        opaque_material: &Material,
+        fluid_material: &Material,
+        uvs: BlockUvs,
+    ) -> Self {
+        let cube = cube_mesh();
+        let context = renderer.context();
+        let culler = GpuCuller::new(context)
+            .inspect_err(|err| warn!("GPU culling is unavailable: {}", err))
+            .ok();
+        let svo_shader = SvoVolume::create_shader(renderer, global_bindings)
+            .inspect_err(|err| warn!("Far field rendering is unavailable: {}", err))
+            .ok();
+
+        Self {
+            chunks: HashMap::new(),
+            pool: MeshPool::new(context, POOL_VERTICES, POOL_INDICES),
+            identity: Buffer::new_instance(
+                context,
+                Some(&[InstanceData {
+                    model: Mat4::IDENTITY,
+                }]),
+            ),
+            draws: IndirectDraws::new(context),
+            culler,
+            opaque_material: opaque_material.clone(),
+            fluid_material: fluid_material.clone(),
+            uvs,
+            meshes: HashMap::from([(
+                MeshKey::CUBE,
+                Mesh::new(renderer.context(), &cube.vertices, &cube.indices),
+            )]),
+            materials: HashMap::from([(MaterialKey::DEFAULT, opaque_material.clone())]),
+            entity_batches: InstanceBatcher::new(),
+            render_distance: 8,
+            lod: LodSettings::default(),
+            occlusion_culling: true,
+            occluded: 0,
+            gpu_culling: false,
+            far_field: true,
+            far_regions: HashMap::new(),
+            svo_shader,
+        }
+    }
+
+    // False if the cull shader couldn't be created, in which case `gpu_culling` does nothing.
+    pub fn supports_gpu_culling(&self) -> bool {
+        self.culler.is_some()
+    }
+
+    // False if the raymarch shader couldn't be created, in which case `far_field` does nothing.
+    pub fn supports_far_field(&self) -> bool {
+        self.svo_shader.is_some()
+    }
+
+    pub fn occluded_chunks(&self) -> u32 {
+        self.occluded
+    }
+
+    // Remeshes every chunk the world reported as changed since the last call, and chunks whose
+    // level of detail changed with the camera's distance to them, nearest first.
+    pub fn update(&mut self, renderer: &Renderer, world: &mut World) {
+        let eye = renderer.camera.eye;
+        let dirty = world.take_dirty_chunks();
+
+        let mut changed: Vec<(f32, IVec3)> = self
+            .chunks
+            .iter()
+            .filter_map(|(pos, chunk)| {
+                let distance = chunk_distance(eye, *pos);
+                let level = self.lod.select(Some(chunk.level), distance);
+                (level != chunk.level).then_some((distance, *pos))
+            })
+            .filter(|(_, pos)| !dirty.contains(pos))
+            .collect();
+        changed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        changed.truncate(MAX_LOD_CHANGES_PER_UPDATE);
+
+        for pos in dirty
+            .into_iter()
+            .chain(changed.into_iter().map(|(_, pos)| pos))
+        {
+            let current = self.chunks.get(&pos).map(|chunk| chunk.level);
+            let level = self.lod.select(current, chunk_distance(eye, pos));
+            self.remesh(renderer, world, pos, level);
+            self.far_regions.remove(&region(pos));
+        }
+
+        self.update_far_regions(renderer, world);
+    }
+
+    // Drops regions that came closer and builds the octrees of those that moved away, nearest
+    // first.
+    fn update_far_regions(&mut self, renderer: &Renderer, world: &World) {
+        let Some(shader) = self.svo_shader.as_ref().filter(|_| self.far_field) else {
+            self.far_regions.clear();
+            return;
+        };
+
+        let eye = renderer.camera.eye;
+        let camera_chunk = chunk_pos(eye.floor().as_ivec3());
+        let far: HashSet<IVec3> = self
+            .chunks
+            .keys()
+            .map(|&pos| region(pos))
+            .filter(|&region| is_far_region(region, camera_chunk, self.render_distance))
+            .collect();
+        self.far_regions.retain(|region, _| far.contains(region));
+
+        let mut missing: Vec<(f32, IVec3)> = far
+            .into_iter()
+            .filter(|region| !self.far_regions.contains_key(region))
+            .map(|region| {
+                let center = region * REGION_CHUNKS + REGION_CHUNKS / 2;
+                (chunk_distance(eye, center), region)
+            })
+            .collect();
+        missing.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        missing.truncate(MAX_REGION_BUILDS_PER_UPDATE);
+
+        for (_, region) in missing {
+            let svo = Svo::from_chunks(world, region * REGION_CHUNKS, REGION_CHUNKS as u32);
+            match SvoVolume::new(renderer, shader, &svo) {
+                Ok(volume) => {
+                    self.far_regions.insert(region, volume);
+                }
+                Err(err) => {
+                    warn!("Failed to create far field volume, disabling it: {}", err);
+                    self.svo_shader = None;
+                    self.far_regions.clear();
+                    return;
+                }
+            }
+        }
+    }
+
+    fn remesh(&mut self, renderer: &Renderer, world: &World, pos: IVec3, level: usize) {
+        let meshes = match level {
+            0 => mesh_chunk(world, pos, &self.uvs),
+            level => mesh_lod(world, pos, LOD_SCALES[level], &self.uvs),
+        };
+        if let Some(pooled) = self.chunks.remove(&pos).and_then(|chunk| chunk.pooled) {
+            self.pool.remove(pooled.allocation);
+        }
+
+        let origin = chunk_origin(pos).as_vec3();
+        let model = Mat4::from_translation(origin);
+        let instance = [InstanceData { model }];
+        let buffer = Buffer::new_instance(renderer.context(), Some(&instance));
+
+        let pooled = self.pool_mesh(renderer, &meshes.opaque, origin);
+        let opaque = if pooled.is_none() {
+            Self::create_object(
+                renderer,
+                &meshes.opaque,
+                &self.opaque_material,
+                PassType::Opaque,
+                &instance,
+                &buffer,
+            )
+        } else {
+            None
+        };
+        let fluid = Self::create_object(
+            renderer,
+            &meshes.fluid,
+            &self.fluid_material,
+            PassType::Transparent,
+            &instance,
+            &buffer,
+        );
+
+        let visibility = ChunkVisibility::compute(world, pos);
+        self.chunks.insert(
+            pos,
+            ChunkRenderData {
+                pooled,
+                opaque,
+                fluid,
+                visibility,
+                level,
+            },
+        );
+    }
+
+    // `alpha` interpolates entities between the last two world ticks.
+    pub fn render(&mut self, renderer: &mut Renderer, world: &World, alpha: f32) {
+        let eye = renderer.camera.eye;
+        let camera_chunk = chunk_pos(eye.floor().as_ivec3());
+
+        let far_field = self.far_field && self.supports_far_field();
+        let mut visible: Vec<IVec3> = self
+            .chunks
+            .keys()
+            .copied()
+            .filter(|&pos| {
+                draws_mesh(
+                    pos,
+                    camera_chunk,
+                    self.render_distance,
+                    far_field.then_some(&self.far_regions),
+                )
+            })
+            .collect();
+
+        // Occlusion is only traced within the render distance, the chunks past it that are drawn
+        // wait for their region's octree.
+        self.occluded = 0;
+        if self.occlusion_culling {
+            let reachable = visible_chunks(eye, self.render_distance, |pos| {
+                self.chunks
+                    .get(&pos)
+                    .map_or(ChunkVisibility::ALL, |chunk| chunk.visibility)
+            });
+            let before = visible.len();
+            visible.retain(|&pos| {
+                !in_render_distance(pos, camera_chunk, self.render_distance)
+                    || reachable.contains(&pos)
+            });
+            self.occluded = (before - visible.len()) as u32;
+        }
+
+        self.render_opaque(renderer, &visible);
+        for volume in self.far_regions.values_mut() {
+            volume.render(renderer);
+        }
+
+        self.render_entities(renderer, world, &visible, alpha);
+
+        // Transparent surfaces blend correctly only when drawn back to front.
+        let mut fluids: Vec<(f32, &RenderObject)> = visible
+            .iter()
+            .filter_map(|pos| {
+                let center = chunk_origin(*pos).as_vec3() + Vec3::splat(CHUNK_SIZE as f32 * 0.5);
+                self.chunks[pos]
+                    .fluid
+                    .as_ref()
+                    .map(|fluid| (center.distance_squared(eye), fluid))
+            })
+            .collect();
+        fluids.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        for (_, fluid) in fluids {
+            renderer.push_object(fluid);
+        }
+    }
+
+    // Pooled chunks go into one indirect batch, culled against the frustum here or on the GPU.
+    fn render_opaque(&mut self, renderer: &mut Renderer, visible: &[IVec3]) {
+        let frustum = renderer.camera.frustum();
+        let culler = self.culler.as_mut().filter(|_| self.gpu_culling);
+
+        let mut draws = vec![];
+        let mut candidates = vec![];
+        let mut culled = 0;
+        for pos in visible {
+            let chunk = &self.chunks[pos];
+            if let Some(opaque) = &chunk.opaque {
+                renderer.push_object(opaque);
+            }
+            let Some(pooled) = &chunk.pooled else {
+                continue;
+            };
+
+            let draw = pooled.allocation.draw_args();
+            if culler.is_some() {
+                candidates.push(CullCandidate::new(&draw, &pooled.bounds));
+            } else if frustum.intersects_aabb(&pooled.bounds) {
+                draws.push(draw);
+            } else {
+                culled += 1;
+            }
+        }
+        renderer.count_culled(culled);
+
+        let (draws, draw_count) = match culler {
+            Some(culler) => {
+                let dispatch = culler.prepare(renderer.context(), &frustum, &candidates);
+                if !candidates.is_empty() {
+                    renderer.push_compute(&dispatch, ComputeStage::BeforeGraphics);
+                }
+                (culler.draws().clone(), candidates.len() as u32)
+            }
+            None => {
+                self.draws.upload(renderer.context(), &draws);
+                (self.draws.buffer().clone(), self.draws.len())
+            }
+        };
+        if draw_count == 0 {
+            return;
+        }
+
+        renderer.push_batch(&IndirectBatch {
+            material: self.opaque_material.clone(),
+            vertices: self.pool.vertex_buffer().clone(),
+            indices: self.pool.index_buffer().clone(),
+            instances: self.identity.buffer().clone(),
+            draws,
+            draw_count,
+        });
+    }
+
+    // Only entities in `visible` chunks are drawn, batched by mesh and material.
+    fn render_entities(
+        &mut self,
+        renderer: &mut Renderer,
+        world: &World,
+        visible: &[IVec3],
+        alpha: f32,
+    ) {
+        self.entity_batches.clear();
+
+        let entities = world.entities();
+        for &chunk in visible {
+            for &id in entities.in_chunk(chunk) {
+                let Some(entity) = entities.get(id) else {
+                    continue;
+                };
+                let Some(render) = entity.render else {
+                    continue;
+                };
+
+                // Meshes span 0..1 on each axis, entity positions are bottom centers.
+                let model = Mat4::from_scale_rotation_translation(
+                    render.scale,
+                    entity.transform.rotation,
+                    entity.transform.interpolated_position(alpha),
+                ) * Mat4::from_translation(Vec3::new(-0.5, 0.0, -0.5));
+
+                if let Some(block) = render.mesh.as_block() {
+                    self.meshes.entry(render.mesh).or_insert_with(|| {
+                        let data = block_mesh(block, &self.uvs);
+                        Mesh::new(renderer.context(), &data.vertices, &data.indices)
+                    });
+                }
+                self.entity_batches.push(
+                    renderer,
+                    (render.mesh, render.material),
+                    InstanceData { model },
+                );
+            }
+        }
+
+        let meshes = &self.meshes;
+        let materials = &self.materials;
+        self.entity_batches
+            .submit(renderer, PassType::Opaque, |(mesh, material)| {
+                Some((
+                    meshes.get(&mesh)?.clone(),
+                    materials.get(&material)?.clone(),
+                ))
+            });
+    }
+
+    // Moves the mesh to world space and puts it in the pool, None if it's empty or doesn't fit.
+    fn pool_mesh(
+        &mut self,
+        renderer: &Renderer,
+        data: &MeshData,
+        origin: Vec3,
+    ) -> Option<PooledMesh> {
+        if data.is_empty() {
+            return None;
+        }
+
+        let vertices: Vec<Vertex> = data
+            .vertices
+            .iter()
+            .map(|vertex| Vertex {
+                position: (Vec3::from(vertex.position) + origin).into(),
+                ..*vertex
+            })
+            .collect();
+        let allocation = self
+            .pool
+            .insert(renderer.context(), &vertices, &data.indices)?;
+
+        Some(PooledMesh {
+            allocation,
+            bounds: Aabb::from_points(vertices.iter().map(|vertex| vertex.position.into())),
+        })
+    }
+
+    fn create_object(
+        renderer: &Renderer,
+        data: &MeshData,
+        material: &Material,
+        pass: PassType,
+        instance: &[InstanceData],
+        buffer: &Buffer<InstanceData>,
+    ) -> Option<RenderObject> {
+        if data.is_empty() {
+            return None;
+        }
+
+        let mesh = Mesh::new(renderer.context(), &data.vertices, &data.indices);
+        Some(RenderObject {
+            bounds: mesh.instance_bounds(instance),
+            mesh,
+            material: material.clone(),
+            pass,
+            instances: buffer.buffer().clone(),
+            instances_len: buffer.len(),
+        })
+    }
+}
+
+// The far field region containing the chunk at `pos`.
+fn region(pos: IVec3) -> IVec3 {
+    pos.div_euclid(IVec3::splat(REGION_CHUNKS))
+}
+
+fn in_render_distance(pos: IVec3, camera_chunk: IVec3, render_distance: i32) -> bool {
+    (pos - camera_chunk).abs().max_element() <= render_distance
+}
+
+// Whether every chunk of `region` is beyond the render distance.
+fn is_far_region(region: IVec3, camera_chunk: IVec3, render_distance: i32) -> bool {
+    let min = region * REGION_CHUNKS;
+    let nearest = camera_chunk.clamp(min, min + IVec3::splat(REGION_CHUNKS - 1));
+    !in_render_distance(nearest, camera_chunk, render_distance)
+}
+
+// Whether the chunk at `pos` is drawn from its mesh. With the far field, that's every chunk whose
+// region isn't raymarched yet, so nothing past the render distance goes missing in between.
+fn draws_mesh<V>(
+    pos: IVec3,
+    camera_chunk: IVec3,
+    render_distance: i32,
+    far_regions: Option<&HashMap<IVec3, V>>,
+) -> bool {
+    match far_regions {
+        Some(far_regions) => !far_regions.contains_key(&region(pos)),
+        None => in_render_distance(pos, camera_chunk, render_distance),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loaded_chunks_are_drawn_as_meshes_or_far_regions() {
+        let camera_chunk = IVec3::new(1, 0, -3);
+        let render_distance = 8;
+        let loaded: Vec<IVec3> = (-12..12)
+            .flat_map(|x| (-12..12).flat_map(move |y| (-12..12).map(move |z| IVec3::new(x, y, z))))
+            .collect();
+        let far: HashMap<IVec3, ()> = loaded
+            .iter()
+            .map(|&pos| region(pos))
+            .filter(|&region| is_far_region(region, camera_chunk, render_distance))
+            .map(|region| (region, ()))
+            .collect();
+        assert!(!far.is_empty());
+
+        // Before any octree is built, and once they all are.
+        for built in [HashMap::new(), far] {
+            for &pos in &loaded {
+                let meshed = draws_mesh(pos, camera_chunk, render_distance, Some(&built));
+                assert!(meshed != built.contains_key(&region(pos)), "{pos}");
+                if in_render_distance(pos, camera_chunk, render_distance) {
+                    assert!(meshed, "{pos}");
+                }
+            }
+        }
+    }
+}